@@ -0,0 +1,168 @@
+//!
+//! Benchmarks of painting, compositing and transfers on a headless canvas.
+//!
+//! ```text
+//! cargo bench --bench canvas
+//! cargo bench --bench canvas -- composite/1024x1024
+//! ```
+//!
+//! Every iteration waits for the GPU, so the times include the work on the device and not only
+//! recording it. Benchmarks are skipped if there is no adapter.
+//!
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use wgpu01::{brush, color, rect, texture, Error, HeadlessCanvas, Transfers};
+
+fn headless(size: [u32; 2]) -> Option<HeadlessCanvas>{
+    match HeadlessCanvas::new(size){
+        Ok(canvas) => Some(canvas),
+        Err(Error::NoAdapter) => {
+            eprintln!("skipped, no adapter");
+            None
+        },
+        Err(err) => panic!("{}", err),
+    }
+}
+
+fn wait(canvas: &HeadlessCanvas){
+    canvas.device.poll(wgpu::Maintain::Wait);
+}
+
+///
+/// An opaque image with some structure, so layers are not uniform.
+///
+fn pattern(size: [u32; 2]) -> image::DynamicImage{
+    image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(size[0], size[1], |x, y| {
+        image::Rgba([(x % 256) as u8, (y % 256) as u8, ((x ^ y) % 256) as u8, 255])
+    }))
+}
+
+///
+/// Short segments spread over the canvas in a fixed pattern.
+///
+fn segment(i: u64, count: u64) -> brush::StrokeData{
+    let t = i as f32 / count as f32;
+    let angle = t * std::f32::consts::TAU * 7.0;
+    let radius = 0.1 + 0.3 * t;
+    let pos0 = [0.5 + radius * angle.cos(), 0.5 + radius * angle.sin()];
+    let pos1 = [pos0[0] + 0.01, pos0[1] + 0.005];
+    brush::StrokeData{pos0, pos1, p0: 1.0, p1: 0.5}
+}
+
+///
+/// Layer::queue_stroke and apply_strokes for batches of strokes.
+///
+fn strokes(c: &mut Criterion){
+    let size = [1024, 1024];
+    let mut canvas = match headless(size){
+        Some(canvas) => canvas,
+        None => return,
+    };
+    let index = canvas.push_empty("Add").unwrap();
+    let brushop = canvas.brushops.arc_to("default").unwrap();
+    let background = texture::Texture::new(&canvas.device, size, Some("Background"), HeadlessCanvas::FORMAT, color::ColorSpace::Linear, false, texture::Texture::DEFAULT_USAGE);
+
+    let mut group = c.benchmark_group("strokes");
+    for count in [1u64, 16, 256, 4096]{
+        group.throughput(Throughput::Elements(count));
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, &count| {
+            b.iter(|| {
+                let mut layer = canvas.canvas.layers[index].borrow_mut();
+                for i in 0..count{
+                    layer.queue_stroke(brush::Stroke::new(brushop.clone(), segment(i, count)));
+                }
+
+                let mut encoder = canvas.device.create_command_encoder(&wgpu::CommandEncoderDescriptor{
+                    label: Some("Strokes Encoder"),
+                });
+                layer.apply_strokes(&canvas.device, &canvas.queue, &mut encoder, &background.bind_group, size).unwrap();
+                canvas.queue.submit(std::iter::once(encoder.finish()));
+                wait(&canvas);
+            });
+        });
+    }
+    group.finish();
+}
+
+///
+/// Canvas::draw of the whole canvas by layer count and canvas size.
+///
+fn composite(c: &mut Criterion){
+    let mut group = c.benchmark_group("composite");
+    group.sample_size(20);
+
+    for side in [512u32, 1024, 2048]{
+        let size = [side, side];
+        let img = pattern(size);
+        group.throughput(Throughput::Elements((side * side) as u64));
+
+        for layers in [1usize, 4, 16]{
+            let mut canvas = match headless(size){
+                Some(canvas) => canvas,
+                None => return,
+            };
+            for _ in 0..layers{
+                canvas.push_image(&img, "Add").unwrap();
+            }
+
+            let id = BenchmarkId::new(format!("{}x{}", side, side), layers);
+            group.bench_with_input(id, &layers, |b, _| {
+                b.iter(|| {
+                    // Without changes the canvas would only redraw dirty regions.
+                    canvas.canvas.invalidate();
+                    canvas.render().unwrap();
+                    wait(&canvas);
+                });
+            });
+        }
+    }
+    group.finish();
+}
+
+///
+/// Texture uploads and readbacks through Transfers.
+///
+fn transfer(c: &mut Criterion){
+    let canvas = match headless([1, 1]){
+        Some(canvas) => canvas,
+        None => return,
+    };
+    let format = HeadlessCanvas::FORMAT;
+    let bytes_per_pixel = texture::bytes_per_pixel(format).unwrap();
+    let mut transfers = Transfers::new();
+
+    let mut group = c.benchmark_group("transfer");
+    for side in [256u32, 1024, 2048]{
+        let size = [side, side];
+        let tex = texture::Texture::new(&canvas.device, size, Some("Transfer Texture"), format, color::ColorSpace::Linear, false, texture::Texture::DEFAULT_USAGE);
+        let data = vec![0x3cu8; (side * side * bytes_per_pixel) as usize];
+        group.throughput(Throughput::Bytes(data.len() as u64));
+
+        let id = format!("{}x{}", side, side);
+        group.bench_with_input(BenchmarkId::new("upload", &id), &size, |b, size| {
+            b.iter(|| {
+                let mut encoder = canvas.device.create_command_encoder(&wgpu::CommandEncoderDescriptor{
+                    label: Some("Upload Encoder"),
+                });
+                let upload = transfers.upload_region(&canvas.device, &mut encoder, &tex, &rect::Rect::full(*size), &data).unwrap();
+                transfers.submit(&canvas.queue, encoder);
+                wait(&canvas);
+                pollster::block_on(upload).unwrap();
+            });
+        });
+        group.bench_with_input(BenchmarkId::new("readback", &id), &size, |b, _| {
+            b.iter(|| {
+                let mut encoder = canvas.device.create_command_encoder(&wgpu::CommandEncoderDescriptor{
+                    label: Some("Readback Encoder"),
+                });
+                let download = transfers.download(&canvas.device, &mut encoder, &tex).unwrap();
+                transfers.submit(&canvas.queue, encoder);
+                wait(&canvas);
+                pollster::block_on(download).unwrap()
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, strokes, composite, transfer);
+criterion_main!(benches);
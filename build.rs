@@ -0,0 +1,14 @@
+fn main(){
+    // The header is checked in, so cbindgen is only needed when the C API changed.
+    // It is generated into OUT_DIR where tests/ffi.rs compares it with the checked in one.
+    #[cfg(feature = "header")]
+    {
+        let dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        let out_dir = std::env::var("OUT_DIR").unwrap();
+        println!("cargo:rerun-if-changed=src");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        cbindgen::generate(&dir)
+            .expect("failed to generate the C header")
+            .write_to_file(std::path::Path::new(&out_dir).join("wgpu01.h"));
+    }
+}
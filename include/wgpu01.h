@@ -0,0 +1,119 @@
+#ifndef WGPU01_H
+#define WGPU01_H
+
+/* Generated by cbindgen from src/ffi.rs, regenerate with `WGPU01_BLESS=1 cargo test --features header --test ffi`. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum Wgpu01Result {
+  WGPU01_RESULT_OK = 0,
+  WGPU01_RESULT_NULL_POINTER = 1,
+  WGPU01_RESULT_INVALID_ARGUMENT = 2,
+  WGPU01_RESULT_NO_ADAPTER = 3,
+  WGPU01_RESULT_DEVICE_LOST = 4,
+  WGPU01_RESULT_SHADER_COMPILATION = 5,
+  WGPU01_RESULT_UNSUPPORTED_FORMAT = 6,
+  WGPU01_RESULT_MISSING_OP = 7,
+  WGPU01_RESULT_MISSING_LAYER = 8,
+  WGPU01_RESULT_IO = 9,
+  WGPU01_RESULT_DECODE = 10,
+  WGPU01_RESULT_PANIC = 11,
+  WGPU01_RESULT_OTHER = 12,
+} Wgpu01Result;
+
+/**
+ *
+ * Opaque handle to a headless canvas.
+ *
+ */
+typedef struct Wgpu01Canvas Wgpu01Canvas;
+
+/**
+ *
+ * A point of a stroke in canvas uv, (0, 0) is the bottom left corner.
+ *
+ */
+typedef struct Wgpu01StrokePoint {
+  float x;
+  float y;
+  float pressure;
+} Wgpu01StrokePoint;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ *
+ * Message of the last failed call on this thread or null.
+ * Valid until the next failing call on this thread.
+ *
+ */
+const char *wgpu01_last_error_message(void);
+
+/**
+ *
+ * Creates a canvas of width x height pixels with its own device.
+ * out is set to the canvas, it has to be freed with wgpu01_canvas_destroy.
+ *
+ */
+enum Wgpu01Result wgpu01_canvas_create(uint32_t width, uint32_t height, struct Wgpu01Canvas **out);
+
+/**
+ *
+ * Frees a canvas, null is ignored.
+ *
+ */
+void wgpu01_canvas_destroy(struct Wgpu01Canvas *canvas);
+
+/**
+ *
+ * Adds a layer from encoded image bytes (PNG, JPEG, ...) on top of the others.
+ * It is placed at its pixel size in the center of the canvas and blended with Add.
+ * out_index is set to the index of the layer if it is not null.
+ *
+ */
+enum Wgpu01Result wgpu01_canvas_add_layer(struct Wgpu01Canvas *canvas,
+                                          const uint8_t *data,
+                                          size_t len,
+                                          uint32_t *out_index);
+
+/**
+ *
+ * Queues a stroke segment of the default brush from p0 to p1 on a layer.
+ * It is painted by the next wgpu01_canvas_composite.
+ *
+ */
+enum Wgpu01Result wgpu01_canvas_queue_stroke(struct Wgpu01Canvas *canvas,
+                                             uint32_t layer,
+                                             struct Wgpu01StrokePoint p0,
+                                             struct Wgpu01StrokePoint p1);
+
+/**
+ *
+ * Applies queued strokes and composites the layers.
+ *
+ */
+enum Wgpu01Result wgpu01_canvas_composite(struct Wgpu01Canvas *canvas);
+
+enum Wgpu01Result wgpu01_canvas_size(struct Wgpu01Canvas *canvas,
+                                     uint32_t *width,
+                                     uint32_t *height);
+
+/**
+ *
+ * Reads back the last composite as sRGB RGBA8, rows from top to bottom without padding.
+ * len has to be width * height * 4.
+ *
+ */
+enum Wgpu01Result wgpu01_canvas_read_rgba(struct Wgpu01Canvas *canvas, uint8_t *out, size_t len);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* WGPU01_H */
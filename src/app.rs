@@ -0,0 +1,385 @@
+//!
+//! The window, painting with the mouse on a canvas.
+//!
+//! Options are parsed by cli::WindowArgs. --script runs a Rhai script on startup and again on F5,
+//! --record writes the strokes painted to a stroke log when the window closes.
+//! Images saved by scripts are read back without stalling the window and written a few frames
+//! later, errors writing them are printed.
+//!
+//! A stroke lasts while the left mouse button is held or a finger touches. Touches take their
+//! pressure from the force if the platform reports it, the mouse paints with full pressure.
+//! winit reports no tilt, it is recorded as 0.
+//!
+use crate::cli::WindowArgs;
+use crate::framework::*;
+use wgpu01::blendop;
+use wgpu01::brush;
+use wgpu01::cache;
+use wgpu01::canvas;
+use wgpu01::color;
+use wgpu01::error;
+use wgpu01::layer;
+use wgpu01::script;
+use wgpu01::stroke_log;
+use wgpu01::texture;
+use wgpu01::{Download, Transfers};
+use image::GenericImageView;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use winit::event::*;
+use winit::event_loop::ControlFlow;
+
+// the BrushOp strokes are painted with.
+const BRUSH: &str = "default";
+
+struct WinState{
+    cache: Arc<cache::PipelineCache>,
+
+    format: wgpu::TextureFormat,
+
+    blendops: Arc<blendop::BlendOpManager>,
+
+    brushops: Arc<brush::BrushOpManager>,
+
+    canvas: canvas::Canvas,
+
+    transfers: Transfers,
+
+    // images saved by scripts, written when their readback finished.
+    exports: Vec<(PathBuf, Download)>,
+
+    recorder: stroke_log::StrokeRecorder,
+
+    // the log is written there when the window closes.
+    record: Option<PathBuf>,
+
+    // last position of the cursor, in canvas uv.
+    cursor: [f32; 2],
+
+    // run on startup and again on F5.
+    script: Option<PathBuf>,
+
+    script_pending: bool,
+}
+
+///
+/// Lets scripts change the canvas of the window.
+///
+struct AppHost<'a>{
+    fstate: &'a FrameworkState,
+    state: &'a mut WinState,
+}
+
+impl<'a> script::ScriptHost for AppHost<'a>{
+    fn canvas(&mut self) -> &mut canvas::Canvas{
+        &mut self.state.canvas
+    }
+
+    fn blendops(&self) -> &blendop::BlendOpManager{
+        &self.state.blendops
+    }
+
+    fn brushops(&self) -> &brush::BrushOpManager{
+        &self.state.brushops
+    }
+
+    fn push_image(&mut self, img: &image::DynamicImage, blendop: &str) -> error::Result<usize>{
+        let state = &mut *self.state;
+        let mut layer = layer::Layer::from_image(&self.fstate.device, &self.fstate.queue, &state.cache, &state.format, state.canvas.color_space(), state.blendops.arc_to(blendop)?, img)?;
+        layer.fit_to_pixels([img.width(), img.height()]);
+        state.canvas.push_layer(&self.fstate.device, &state.cache, layer)?;
+        Ok(state.canvas.layers.len() - 1)
+    }
+
+    fn push_empty(&mut self, blendop: &str) -> error::Result<usize>{
+        let state = &mut *self.state;
+        let size = state.canvas.size();
+        let mut layer = layer::Layer::new(&self.fstate.device, &self.fstate.queue, &state.cache, &state.format, state.canvas.color_space(), size, state.blendops.arc_to(blendop)?)?;
+        layer.fit_to_pixels(size);
+        state.canvas.push_layer(&self.fstate.device, &state.cache, layer)?;
+        Ok(state.canvas.layers.len() - 1)
+    }
+
+    fn render(&mut self) -> error::Result<()>{
+        // The window renders every frame anyway.
+        Ok(())
+    }
+
+    ///
+    /// Reads the composite back without waiting, the render loop writes the file once it arrived.
+    ///
+    fn save(&mut self, path: &Path) -> error::Result<()>{
+        let size = self.state.canvas.size();
+        let target = texture::Texture::new(&self.fstate.device, size, Some("Script Target"), self.fstate.config.format, color::ColorSpace::Srgb, false, texture::Texture::DEFAULT_USAGE);
+
+        let mut encoder = self.fstate.device.create_command_encoder(&wgpu::CommandEncoderDescriptor{
+            label: Some("Script Encoder"),
+        });
+        self.state.canvas.draw(&self.fstate.device, &mut encoder, &self.fstate.queue, &target.view, size)?;
+        let download = self.state.transfers.download(&self.fstate.device, &mut encoder, &target)?;
+        self.state.transfers.submit(&self.fstate.queue, encoder);
+
+        self.state.exports.push((path.to_path_buf(), download));
+        Ok(())
+    }
+}
+
+impl Drop for WinState{
+    fn drop(&mut self){
+        for (path, _) in &self.exports{
+            eprintln!("{} was not written, the window closed before the canvas was read back", path.display());
+        }
+        if let Some(path) = &self.record{
+            let log = std::mem::take(&mut self.recorder).finish();
+            if let Err(err) = log.save(path){
+                eprintln!("Failed to write the stroke log: {}", err);
+            }
+        }
+    }
+}
+
+impl WinState{
+    fn new(fstate: &mut FrameworkState, args: WindowArgs) -> error::Result<Self> {
+
+        let cache = Arc::new(cache::PipelineCache::with_dir(fstate.device.clone(), cache::PipelineCache::default_dir()));
+
+        // Layers are painted and composited in half float so blending can exceed 1.0.
+        let format = wgpu::TextureFormat::Rgba16Float;
+
+        let blendops = Arc::new(blendop::BlendOpManager::new(&fstate.device, &fstate.queue, &cache, &format)?);
+        let brushops = Arc::new(brush::BrushOpManager::new(&fstate.device, &fstate.queue, &cache, format, args.samples)?);
+
+        let mut canvas = canvas::Canvas::new(&fstate.device, &fstate.queue, &cache, format, fstate.config.format, color::ColorSpace::Linear, blendops.clone(), [1000, 1000])?;
+        canvas.set_sample_count(&fstate.device, &cache, args.samples)?;
+
+        let layer = layer::Layer::load(
+                &fstate.device,
+                &fstate.queue,
+                &cache,
+                &format,
+                canvas.color_space(),
+                blendops.arc_to("Add")?,
+                "assets/test1.jpg"
+        )?;
+        canvas.push_layer(&fstate.device, &cache, layer)?;
+
+        canvas.layers[0].borrow_mut().scale = glm::vec3(300.0, 200.0, 1.0);
+
+        Ok(Self{
+            cache,
+            format,
+            blendops,
+            brushops,
+            canvas,
+            transfers: Transfers::new(),
+            exports: Vec::new(),
+            recorder: stroke_log::StrokeRecorder::new(),
+            record: args.record,
+            cursor: [0.0; 2],
+            script: args.script,
+            script_pending: true,
+        })
+    }
+
+    ///
+    /// Canvas uv of a position in the window, y has to be inverted.
+    ///
+    fn to_uv(fstate: &FrameworkState, position: &winit::dpi::PhysicalPosition<f64>) -> [f32; 2]{
+        [position.x as f32 / fstate.size.width as f32, 1.0 - position.y as f32 / fstate.size.height as f32]
+    }
+
+    fn begin_stroke(&mut self){
+        begin_stroke(&mut self.recorder, &self.canvas);
+    }
+
+    fn paint(&mut self, pos: [f32; 2], pressure: f32){
+        if let Err(err) = paint(&mut self.recorder, &self.canvas, &self.brushops, pos, pressure){
+            eprintln!("{}", err);
+            self.recorder.end();
+        }
+    }
+
+    ///
+    /// Writes the exports whose readback finished.
+    ///
+    fn write_exports(&mut self){
+        self.exports.retain_mut(|(path, download)| {
+            let result = match download.try_take(){
+                Ok(Some(data)) => data.to_image().and_then(|img| Ok(img.save(&*path)?)),
+                Ok(None) => return true,
+                Err(err) => Err(err),
+            };
+            if let Err(err) = result{
+                eprintln!("Failed to write {}: {}", path.display(), err);
+            }
+            false
+        });
+    }
+
+    fn run_script(&mut self, fstate: &FrameworkState){
+        if let Some(path) = self.script.clone(){
+            if let Err(err) = script::run_file(&mut AppHost{fstate, state: self}, &path){
+                eprintln!("{}", err);
+            }
+        }
+    }
+}
+
+///
+/// Begins a stroke on the active layer, nothing is recorded if the canvas has no layers.
+///
+fn begin_stroke(recorder: &mut stroke_log::StrokeRecorder, canvas: &canvas::Canvas){
+    if let Some(layer) = canvas.active_layer(){
+        recorder.begin(layer, BRUSH, BTreeMap::new());
+    }
+}
+
+///
+/// Records a sample of the current stroke and paints the segment to its layer, queued the same
+/// way replaying the log does.
+///
+fn paint(recorder: &mut stroke_log::StrokeRecorder, canvas: &canvas::Canvas, brushops: &brush::BrushOpManager, pos: [f32; 2], pressure: f32) -> error::Result<()>{
+    let layer = match recorder.layer(){
+        Some(layer) => layer,
+        None => return Ok(()),
+    };
+    let segment = recorder.sample(stroke_log::Sample{
+        time: recorder.time(),
+        pos,
+        pressure,
+        tilt: [0.0, 0.0],
+    });
+
+    if let Some(data) = segment{
+        // a script can remove the layer while the stroke lasts.
+        let layer = canvas.layers.get(layer).ok_or(error::Error::MissingLayer(layer))?;
+        layer.borrow_mut().queue_stroke(brush::Stroke::new(brushops.arc_to(BRUSH)?, data));
+    }
+    Ok(())
+}
+
+impl State for WinState{
+    fn render(&mut self, fstate: &mut FrameworkState, _control_flow: &mut ControlFlow) -> error::Result<()> {
+        if self.script_pending{
+            self.script_pending = false;
+            self.run_script(fstate);
+        }
+
+        let output = fstate.surface.get_current_texture()?;
+        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut encoder = fstate.device.create_command_encoder(&wgpu::CommandEncoderDescriptor{
+            label: Some("Render Encoder"),
+        });
+
+        self.canvas.draw(&fstate.device, &mut encoder, &fstate.queue, &view, [fstate.size.width, fstate.size.height])?;
+
+        self.transfers.submit(&fstate.queue, encoder);
+        output.present();
+        // drives pending readbacks without blocking.
+        fstate.device.poll(wgpu::Maintain::Poll);
+        self.write_exports();
+
+        Ok(())
+    }
+
+    fn input(&mut self, event: &WindowEvent) -> bool{
+        if let WindowEvent::KeyboardInput{input: KeyboardInput{state: ElementState::Pressed, virtual_keycode: Some(VirtualKeyCode::F5), ..}, ..} = event{
+            // run by the next render, which has the device.
+            self.script_pending = self.script.is_some();
+            return true;
+        }
+        false
+    }
+
+    fn cursor_moved(&mut self, fstate: &mut FrameworkState, _device_id: &winit::event::DeviceId, position: &winit::dpi::PhysicalPosition<f64>) {
+        self.cursor = Self::to_uv(fstate, position);
+        if self.recorder.is_recording(){
+            self.paint(self.cursor, 1.0);
+        }
+    }
+
+    fn mouse_input(&mut self, _fstate: &mut FrameworkState, _device_id: &winit::event::DeviceId, state: &ElementState, button: &MouseButton){
+        match (state, button){
+            (ElementState::Pressed, MouseButton::Left) => {
+                self.begin_stroke();
+                self.paint(self.cursor, 1.0);
+            },
+            (ElementState::Released, MouseButton::Left) => self.recorder.end(),
+            _ => {},
+        }
+    }
+
+    fn touch(&mut self, fstate: &mut FrameworkState, touch: &Touch){
+        let pos = Self::to_uv(fstate, &touch.location);
+        let pressure = touch.force.map_or(1.0, |force| force.normalized() as f32);
+        match touch.phase{
+            TouchPhase::Started => {
+                self.begin_stroke();
+                self.paint(pos, pressure);
+            },
+            TouchPhase::Moved => self.paint(pos, pressure),
+            TouchPhase::Ended | TouchPhase::Cancelled => self.recorder.end(),
+        }
+    }
+
+    fn resize(&mut self, fstate: &mut FrameworkState, new_size: winit::dpi::PhysicalSize<u32>){
+        if let Err(err) = self.canvas.resize(&fstate.device, &fstate.queue, [new_size.width, new_size.height]){
+            eprintln!("{}", err);
+        }
+    }
+}
+
+///
+/// Opens the window and runs until it is closed.
+///
+pub fn run(args: WindowArgs) -> crate::Result<()>{
+    Framework::new(|fstate| WinState::new(fstate, args))?.run();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use wgpu01::HeadlessCanvas;
+
+    #[test]
+    fn paints_on_the_active_layer_after_scripts_remove_layers(){
+        let (device, queue) = match pollster::block_on(HeadlessCanvas::request_device(true)){
+            Ok(device) => device,
+            Err(error::Error::NoAdapter) => {
+                println!("skipped, no adapter");
+                return;
+            },
+            Err(err) => panic!("{}", err),
+        };
+        let mut canvas = HeadlessCanvas::with_device(device, queue, [64, 48]).unwrap();
+        script::run(&mut canvas, r#"
+            canvas.add_layer("Add");
+            canvas.add_layer("Add");
+            canvas.add_layer("Add");
+            canvas.remove_layer(0);
+            canvas.set_active(0);
+        "#).unwrap();
+
+        let mut recorder = stroke_log::StrokeRecorder::new();
+        begin_stroke(&mut recorder, &canvas.canvas);
+        paint(&mut recorder, &canvas.canvas, &canvas.brushops, [0.2, 0.5], 1.0).unwrap();
+        paint(&mut recorder, &canvas.canvas, &canvas.brushops, [0.8, 0.5], 1.0).unwrap();
+        recorder.end();
+        assert_eq!(recorder.log().strokes.len(), 1);
+        assert_eq!(recorder.log().strokes[0].layer, 0);
+        canvas.render().unwrap();
+        let img = canvas.read_rgba8().unwrap();
+        assert!(img.get_pixel(32, 24).0[0] > 200, "{:?}", img.get_pixel(32, 24));
+
+        // without layers nothing is painted or recorded.
+        script::run(&mut canvas, "canvas.remove_layer(0); canvas.remove_layer(0);").unwrap();
+        begin_stroke(&mut recorder, &canvas.canvas);
+        paint(&mut recorder, &canvas.canvas, &canvas.brushops, [0.2, 0.5], 1.0).unwrap();
+        paint(&mut recorder, &canvas.canvas, &canvas.brushops, [0.8, 0.5], 1.0).unwrap();
+        recorder.end();
+        assert_eq!(recorder.log().strokes.len(), 1);
+    }
+}
@@ -1,5 +1,3 @@
-#[allow(unused)]
-use anyhow::*;
 use std::marker::PhantomData;
 use std::sync::Arc;
 use std::any::{Any, TypeId};
@@ -33,6 +31,7 @@ pub struct BindGroupLayoutWithDesc{
     pub entries: Vec<wgpu::BindGroupLayoutEntry>,
 }
 
+#[derive(Clone)]
 pub struct BindGroupLayoutBuilder{
     index: u32,
     entries: Vec<wgpu::BindGroupLayoutEntry>,
@@ -78,6 +77,10 @@ impl BindGroupLayoutBuilder{
         self.push_entry(wgpu::ShaderStages::all(), ty)
     }
 
+    pub fn entries(&self) -> &[wgpu::BindGroupLayoutEntry]{
+        &self.entries
+    }
+
     pub fn create(self, device: &wgpu::Device, label: Option<&str>) -> BindGroupLayoutWithDesc{
         BindGroupLayoutWithDesc{
             layout: device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor{
@@ -130,7 +133,7 @@ impl<'l> BindGroupBuilder<'l>{
 }
 
 
-mod glsl{
+pub mod glsl{
     pub fn buffer(read_only: bool) -> wgpu::BindingType {
         wgpu::BindingType::Buffer {
             ty: wgpu::BufferBindingType::Storage { read_only },
@@ -1,4 +1,4 @@
-use anyhow::*;
+use crate::error::{Error, Result};
 use crate::pipeline;
 use crate::render_target::ColorAttachment;
 use crate::texture;
@@ -8,6 +8,8 @@ use crate::vert;
 use crate::program;
 use crate::render_target::RenderTarget;
 use crate::binding::ToBindGroupLayout;
+use crate::cache;
+use crate::rect;
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::borrow::Cow;
@@ -19,46 +21,69 @@ use std::borrow::Cow;
 ///
 pub struct BlendOp{
     drawable: Box<dyn mesh::Drawable>,
-    render_pipeline: pipeline::RenderPipeline,
+    render_pipeline: Arc<pipeline::RenderPipeline>,
+    associative: bool,
 }
 
 impl BlendOp{
-    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, format: &wgpu::TextureFormat, src: &str) -> Result<Self>{
+    ///
+    /// associative has to be true only if blending with the op is associative and black is its
+    /// identity, like for Add. See BlendOp::associative.
+    ///
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, cache: &cache::PipelineCache, format: &wgpu::TextureFormat, src: &str, associative: bool) -> Result<Self>{
+        texture::check_filterable(device, *format)?;
         let drawable = Box::new(mesh::Mesh::<vert::Vert2>::new(device, &vert::Vert2::QUAD_VERTS, &vert::Vert2::QUAD_IDXS)?);
 
-        let bind_group_layout = texture::Texture::create_bind_group_layout(device, None);
+        let render_pipeline = cache.render_pipeline(&format!("BlendOp {:016x} {:?}", cache::PipelineCache::source_hash(src), format), |device|{
+            let bind_group_layouts = pipeline::create_named_bind_group_layouts(device, Self::bind_group_layouts());
 
-        let render_pipeline_layout = pipeline::PipelineLayoutBuilder::new()
-            .push_named("src", &bind_group_layout)
-            .push_named("dst", &bind_group_layout)
-            .create(device, None);
+            let render_pipeline_layout = pipeline::PipelineLayoutBuilder::new()
+                .push_all_named(&bind_group_layouts)
+                .create(device, None);
 
-        let vert_shader = pipeline::shader_with_shaderc(device, include_str!("shaders/vert_screen.glsl"), shaderc::ShaderKind::Vertex, "main", Some("VertexShader"))?;
-        let frag_shader = pipeline::shader_with_shaderc(device, include_str!("shaders/frag_add.glsl"), shaderc::ShaderKind::Fragment, "main", Some("FragmentShader"))?;
+            let vert_shader = cache.shader_with_shaderc(include_str!("shaders/vert_screen.glsl"), shaderc::ShaderKind::Vertex, "main", &[], Some("VertexShader"))?;
+            let frag_shader = cache.shader_with_shaderc(include_str!("shaders/frag_add.glsl"), shaderc::ShaderKind::Fragment, "main", &[], Some("FragmentShader"))?;
 
-        let vertex_state_layout = pipeline::VertexStateBuilder::new(&vert_shader)
-            .push_named("model", drawable.vert_buffer_layout())
-            .set_entry_point("main")
-            .build();
+            let vertex_state_layout = pipeline::VertexStateBuilder::new(&vert_shader)
+                .push_named("model", drawable.vert_buffer_layout())
+                .set_entry_point("main")
+                .build();
 
-        let fragment_state = pipeline::FragmentStateBuilder::new(&frag_shader)
-            .set_entry_point("main")
-            .build();
+            let fragment_state = pipeline::FragmentStateBuilder::new(&frag_shader)
+                .set_entry_point("main")
+                .build();
 
-        let render_pipeline = program::new(
-            &device,
-            *format,
-            &render_pipeline_layout,
-            &vertex_state_layout,
-            &fragment_state,
-        )?;
+            program::new(
+                &device,
+                *format,
+                &render_pipeline_layout,
+                &vertex_state_layout,
+                &fragment_state,
+            )
+        })?;
 
         Ok(Self{
             drawable,
             render_pipeline,
+            associative,
         })
     }
 
+    ///
+    /// Whether layers blended with this op can be flattened onto black first and blended as one
+    /// image, which lets Canvas cache the layers above the active one.
+    ///
+    pub fn associative(&self) -> bool{
+        self.associative
+    }
+
+    pub fn bind_group_layouts() -> pipeline::NamedBindGroupLayouts{
+        vec![
+            ("src", texture::Texture::bind_group_layout_builder()),
+            ("dst", texture::Texture::bind_group_layout_builder()),
+        ]
+    }
+
     pub fn draw(&self, encoder: &mut wgpu::CommandEncoder, queue: &wgpu::Queue, dst: &wgpu::TextureView, src0: &wgpu::BindGroup, src1: &wgpu::BindGroup) -> Result<()>{
         {
             let mut render_pass = pipeline::RenderPassBuilder::new()
@@ -66,14 +91,32 @@ impl BlendOp{
                 .begin(encoder, None);
             let mut render_pass_pipeline = render_pass.set_pipeline(&self.render_pipeline);
 
-            render_pass_pipeline.set_bind_group("src", src0, &[]);
-            render_pass_pipeline.set_bind_group("dst", src1, &[]);
+            render_pass_pipeline.try_set_bind_group("src", src0, &[])?;
+            render_pass_pipeline.try_set_bind_group("dst", src1, &[])?;
 
             self.drawable.draw(&mut render_pass_pipeline);
         }
 
         Ok(())
     }
+
+    ///
+    /// Like draw but only blends region and keeps the rest of dst.
+    ///
+    pub fn draw_region(&self, encoder: &mut wgpu::CommandEncoder, queue: &wgpu::Queue, dst: &wgpu::TextureView, src0: &wgpu::BindGroup, src1: &wgpu::BindGroup, region: &rect::Rect) -> Result<()>{
+        let mut render_pass = pipeline::RenderPassBuilder::new()
+            .push_color_attachment(dst.color_attachment_load())
+            .begin(encoder, None);
+        render_pass.set_scissor_rect(region);
+        let mut render_pass_pipeline = render_pass.set_pipeline(&self.render_pipeline);
+
+        render_pass_pipeline.try_set_bind_group("src", src0, &[])?;
+        render_pass_pipeline.try_set_bind_group("dst", src1, &[])?;
+
+        self.drawable.draw(&mut render_pass_pipeline);
+
+        Ok(())
+    }
 }
 
 impl<'pd> mesh::DataDrawable<'pd, (&'pd wgpu::BindGroup, &'pd wgpu::BindGroup)> for BlendOp{
@@ -92,10 +135,10 @@ pub struct BlendOpManager{
 }
 
 impl BlendOpManager{
-    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, format: &wgpu::TextureFormat) -> Result<Self>{
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, cache: &cache::PipelineCache, format: &wgpu::TextureFormat) -> Result<Self>{
         let mut ops: HashMap<String, Arc<BlendOp>> = HashMap::new();
 
-        let blendop_add = BlendOp::new(device, queue, format, include_str!("shaders/add.wgsl"))?;
+        let blendop_add = BlendOp::new(device, queue, cache, format, include_str!("shaders/add.wgsl"), true)?;
         ops.insert("Add".to_string(), Arc::new(blendop_add));
 
         Ok(Self{
@@ -104,7 +147,22 @@ impl BlendOpManager{
     }
 
     pub fn arc_to(&self, key: &str) -> Result<Arc<BlendOp>>{
-        Ok(self.ops.get(key).ok_or(anyhow!("No BlendOp found for this name"))?.clone())
+        Ok(self.ops.get(key).ok_or_else(|| Error::MissingBlendOp(key.to_string()))?.clone())
+    }
+
+    pub fn names(&self) -> Vec<String>{
+        let mut names: Vec<String> = self.ops.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    ///
+    /// The name op is registered under.
+    ///
+    pub fn name_of(&self, op: &Arc<BlendOp>) -> Option<&str>{
+        self.ops.iter()
+            .find(|(_, o)| Arc::ptr_eq(o, op))
+            .map(|(name, _)| name.as_str())
     }
 }
 
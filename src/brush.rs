@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use std::sync::Arc;
-use anyhow::*;
+use std::ops::Range;
+use crate::error::{Error, Result};
 use crate::buffer;
 use crate::mesh;
 use crate::mesh::Drawable;
@@ -12,147 +13,247 @@ use crate::layer;
 use crate::binding::GetBindGroup;
 use crate::render_target::RenderTarget;
 use crate::binding::ToBindGroupLayout;
+use crate::cache;
 
 
 pub struct BrushOp{
-    render_pipeline: pipeline::RenderPipeline,
-    drawable: Arc<dyn mesh::Drawable>,
-}
-
-pub struct BrushBindGroups<'bg>{
-    stroke_data: StrokeBindGroups<'bg>,
-    stroke: &'bg buffer::UniformBindGroup<StrokeDataUniform>,
-    transforms: &'bg buffer::UniformBindGroup<mesh::ModelTransforms>,
-}
-
-/// Add an iter for layouts and bindgroups
-impl<'bg> BrushBindGroups<'bg>{
-    pub fn set_bind_groups(&self, render_pass_pipeline: &mut pipeline::RenderPassPipeline<'bg, '_>){
-        render_pass_pipeline.set_bind_group("transforms", self.transforms.get_bind_group(), &[]);
-        render_pass_pipeline.set_bind_group("background", self.stroke_data.background,      &[]);
-        render_pass_pipeline.set_bind_group("self",       self.stroke_data.tex_self,        &[]);
-        render_pass_pipeline.set_bind_group("stroke",     self.stroke.get_bind_group(),     &[]);
-    }
+    render_pipeline: Arc<pipeline::RenderPipeline>,
+    drawable: Arc<mesh::Mesh<vert::Vert2>>,
+    sample_count: u32,
 }
 
 impl BrushOp{
-    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, src: &str) -> Result<Self>{
+    ///
+    /// sample_count has to match the one of the layers the BrushOp paints on.
+    ///
+    pub fn new(device: &wgpu::Device, cache: &cache::PipelineCache, format: wgpu::TextureFormat, sample_count: u32, src: &str) -> Result<Self>{
+        // Strokes are blended onto the layer.
+        texture::check_filterable(device, format)?;
+
         // TODO: Should use a global mesh.
         let drawable = Arc::new(mesh::Mesh::<vert::Vert2>::new(
                 device, &vert::Vert2::QUAD_VERTS, 
                 &vert::Vert2::QUAD_IDXS
         )?);
 
-        let texture_bgl = texture::Texture::create_bind_group_layout(device, None);
-        let stroke_uniform_bgl = buffer::UniformBindGroup::<StrokeDataUniform>::create_bind_group_layout(device, None);
-        let transforms_uniform_bgl = buffer::UniformBindGroup::<mesh::ModelTransforms>::create_bind_group_layout(device, None);
-
-        let render_pipeline_layout = pipeline::PipelineLayoutBuilder::new()
-            .push_named("transforms", &transforms_uniform_bgl)
-            .push_named("self", &texture_bgl)
-            .push_named("stroke", &stroke_uniform_bgl)
-            .push_named("background", &texture_bgl)
-            .create(device, None);
-
-        let vert_shader = pipeline::shader_with_shaderc(device, include_str!("shaders/vert_brush.glsl"), shaderc::ShaderKind::Vertex, "main", Some("VertexShader"))?;
-        let frag_shader = pipeline::shader_with_shaderc(device, include_str!("shaders/frag_brush01.glsl"), shaderc::ShaderKind::Fragment, "main", Some("FragmentShader"))?;
-
-        let vertex_state = pipeline::VertexStateBuilder::new(&vert_shader)
-            .push_named("model", drawable.vert_buffer_layout())
-            .set_entry_point("main")
-            .build();
-
-        let fragment_state = pipeline::FragmentStateBuilder::new(&frag_shader)
-            .set_entry_point("main")
-            .build();
-
-        let render_pipeline = program::new(
-            &device,
-            format,
-            &render_pipeline_layout,
-            &vertex_state,
-            &fragment_state,
-        )?;
+        let render_pipeline = cache.render_pipeline(&format!("BrushOp {:016x} {:?} x{}", cache::PipelineCache::source_hash(src), format, sample_count), |device|{
+            let bind_group_layouts = pipeline::create_named_bind_group_layouts(device, Self::bind_group_layouts());
+
+            let render_pipeline_layout = pipeline::PipelineLayoutBuilder::new()
+                .push_all_named(&bind_group_layouts)
+                .create(device, None);
+
+            let vert_shader = cache.shader_with_shaderc(include_str!("shaders/vert_brush.glsl"), shaderc::ShaderKind::Vertex, "main", &[], Some("VertexShader"))?;
+            let frag_shader = cache.shader_with_shaderc(include_str!("shaders/frag_brush01.glsl"), shaderc::ShaderKind::Fragment, "main", &[], Some("FragmentShader"))?;
+
+            let vertex_state = pipeline::VertexStateBuilder::new(&vert_shader)
+                .push_named("model", drawable.vert_buffer_layout())
+                .push_named("strokes", StrokeData::instance_buffer_layout())
+                .set_entry_point("main")
+                .build();
+
+            // Strokes are blended onto the layer by the hardware so the layer does not have to be
+            // read in the shader and all strokes can be drawn in one instanced draw call.
+            let fragment_state = pipeline::FragmentStateBuilder::new(&frag_shader)
+                .set_entry_point("main")
+                .push_target(wgpu::ColorTargetState{
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })
+                .build();
+
+            Ok(pipeline::RenderPipelineBuilder::new(vertex_state, fragment_state)
+                .set_layout(&render_pipeline_layout)
+                .set_schema(Self::SCHEMA)
+                .set_sample_count(sample_count)
+                .try_build(device)?)
+        })?;
 
         Ok(Self{
             render_pipeline,
             drawable,
+            sample_count,
         })
     }
 
-    // TODO: change bind_groups to render_pass.
-    pub fn draw<'rp>(&'rp self, render_pass: &'_ mut pipeline::RenderPassPipeline<'rp, '_>) -> Result<()>{
-        self.drawable.draw(render_pass);
-
-        Ok(())
+    pub fn sample_count(&self) -> u32{
+        self.sample_count
     }
 
-    pub fn get_pipeline(&self) -> &pipeline::RenderPipeline{
-        &self.render_pipeline
+    ///
+    /// What draw_instances sets.
+    ///
+    pub const SCHEMA: pipeline::PipelineSchema<'static> = pipeline::PipelineSchema{
+        bind_groups: &["transforms", "background"],
+        vertex_buffers: &["model", "strokes"],
+    };
+
+    pub fn bind_group_layouts() -> pipeline::NamedBindGroupLayouts{
+        vec![
+            ("transforms", buffer::UniformBindGroup::<mesh::ModelTransforms>::bind_group_layout_builder()),
+            ("background", texture::Texture::bind_group_layout_builder()),
+        ]
     }
-}
 
-impl<'pd> mesh::DataDrawable<'pd, BrushBindGroups<'pd>> for BrushOp{
-    fn draw_data(&'pd self, render_pass: &'_ mut pipeline::RenderPass<'pd>, data: BrushBindGroups<'pd>){
+    ///
+    /// Draws the strokes in instances of the batch on top of the render target.
+    ///
+    pub fn draw_instances<'rp>(&'rp self, render_pass: &'_ mut pipeline::RenderPass<'rp>, data: StrokeBindGroups<'rp>, batch: &'rp StrokeBatch, instances: Range<u32>) -> Result<(), pipeline::BindingError>{
         let mut render_pass_pipeline = render_pass.set_pipeline(&self.render_pipeline);
 
-        data.set_bind_groups(&mut render_pass_pipeline);
-        /*
-        render_pass_pipeline.set_bind_group("transforms", data.transforms.get_bind_group(), &[]);
-        render_pass_pipeline.set_bind_group("background", data.stroke_data.background, &[]);
-        render_pass_pipeline.set_bind_group("self", data.stroke_data.tex_self, &[]);
-        render_pass_pipeline.set_bind_group("stroke", data.stroke.get_bind_group(), &[]);
-        */
-        
-        self.drawable.draw(&mut render_pass_pipeline);
+        render_pass_pipeline.try_set_bind_group("transforms", data.transforms.get_bind_group(), &[])?;
+        render_pass_pipeline.try_set_bind_group("background", data.background, &[])?;
+        render_pass_pipeline.try_set_vertex_buffer("strokes", batch.instances.slice())?;
+
+        self.drawable.draw_instanced(&mut render_pass_pipeline, instances);
+        Ok(())
+    }
+
+    pub fn get_pipeline(&self) -> &pipeline::RenderPipeline{
+        &self.render_pipeline
     }
 }
 
+///
+/// A stroke segment, stored per instance in a StrokeBatch.
+///
 #[repr(C)]
 #[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct StrokeDataUniform{
+pub struct StrokeData{
     pub pos0: [f32; 2],
     pub pos1: [f32; 2],
     pub p0: f32,
     pub p1: f32,
 }
 
+impl StrokeData{
+    /// Distance from the stroke line at which fallofn(d * 50.0) in frag_brush01.glsl drops below 1e-4.
+    pub const RADIUS: f32 = 0.061;
+    /// Distance from the middle of the stroke, in stroke lengths, at which falloft in frag_brush01.glsl
+    /// drops below 1e-4, that is 3 * sqrt(ln(1e4)) ≈ 9.105 rounded up.
+    pub const EXTENT: f32 = 9.11;
+
+    pub fn instance_buffer_layout() -> wgpu::VertexBufferLayout<'static>{
+        const ATTRIBS: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
+            2 => Float32x2,
+            3 => Float32x2,
+            4 => Float32,
+            5 => Float32
+        ];
+        wgpu::VertexBufferLayout{
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &ATTRIBS,
+        }
+    }
+
+    ///
+    /// Min and max corner of the area the stroke paints to in background uv.
+    ///
+    pub fn bounds(&self) -> [[f32; 2]; 2]{
+        let mut bounds = [[0.0; 2]; 2];
+        for i in 0..2{
+            let center = (self.pos0[i] + self.pos1[i]) / 2.0;
+            let extent = (self.pos1[i] - self.pos0[i]).abs() * Self::EXTENT + Self::RADIUS;
+            bounds[0][i] = center - extent;
+            bounds[1][i] = center + extent;
+        }
+        bounds
+    }
+}
+
+#[derive(Clone, Copy)]
 pub struct StrokeBindGroups<'bg>{
     pub background: &'bg wgpu::BindGroup,
-    pub tex_self: &'bg wgpu::BindGroup,
+    pub transforms: &'bg buffer::UniformBindGroup<mesh::ModelTransforms>,
 }
 
 pub struct Stroke{
     brushop: Arc<BrushOp>,
-    pub data_uniform: buffer::UniformBindGroup<StrokeDataUniform>,
-    pub transforms_uniform: buffer::UniformBindGroup<mesh::ModelTransforms>,
+    pub data: StrokeData,
 }
 
 impl Stroke{
-    pub fn new(device: &wgpu::Device, brushop: Arc<BrushOp>, suniform: StrokeDataUniform) -> Self{
-        let data_uniform = buffer::UniformBindGroup::new_with_data(device, &suniform);
-        let transforms_uniform = buffer::UniformBindGroup::new(device);
-
+    pub fn new(brushop: Arc<BrushOp>, data: StrokeData) -> Self{
         Self{
             brushop,
-            data_uniform,
-            transforms_uniform,
+            data,
         }
     }
 
-    pub fn update_transforms(&mut self, queue: &wgpu::Queue, transforms_uniform: &mesh::ModelTransforms){
-        self.transforms_uniform.update(queue, transforms_uniform);
+    pub fn brushop(&self) -> &Arc<BrushOp>{
+        &self.brushop
     }
 
+    pub fn bounds(&self) -> [[f32; 2]; 2]{
+        self.data.bounds()
+    }
+}
+
+///
+/// Instance data of all strokes drawn in a frame.
+///
+/// Strokes are pushed in groups, one per render target. Consecutive strokes of a group that use
+/// the same BrushOp are drawn with a single instanced draw call.
+///
+pub struct StrokeBatch{
+    instances: buffer::InstanceBuffer<StrokeData>,
+    data: Vec<StrokeData>,
+    draws: Vec<(Arc<BrushOp>, Range<u32>)>,
 }
 
-impl<'pd> mesh::DataDrawable<'pd, StrokeBindGroups<'pd>> for Stroke{
-    fn draw_data(&'pd self, render_pass: &'_ mut pipeline::RenderPass<'pd>, data: StrokeBindGroups<'pd>) {
-        self.brushop.draw_data(render_pass, BrushBindGroups{
-            stroke_data: data,
-            stroke: &self.data_uniform,
-            transforms: &self.transforms_uniform,
-        });
+impl StrokeBatch{
+    pub fn new(device: &wgpu::Device) -> Self{
+        Self{
+            instances: buffer::InstanceBuffer::new(device, 64),
+            data: Vec::new(),
+            draws: Vec::new(),
+        }
+    }
+
+    pub fn clear(&mut self){
+        self.data.clear();
+        self.draws.clear();
+    }
+
+    ///
+    /// Pushes strokes as a group and returns the range of draws they need.
+    ///
+    pub fn push_group<'s>(&mut self, strokes: impl IntoIterator<Item = &'s Stroke>) -> Range<usize>{
+        let start = self.draws.len();
+        for stroke in strokes{
+            let instance = self.data.len() as u32;
+            self.data.push(stroke.data);
+
+            let in_group = self.draws.len() > start;
+            match self.draws.last_mut(){
+                Some((brushop, instances)) if in_group && Arc::ptr_eq(brushop, &stroke.brushop) => {
+                    instances.end = instance + 1;
+                },
+                _ => {
+                    self.draws.push((stroke.brushop.clone(), instance..instance + 1));
+                },
+            }
+        }
+        start..self.draws.len()
+    }
+
+    ///
+    /// Uploads the instances, has to be called before drawing.
+    ///
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue){
+        self.instances.write(device, queue, &self.data);
+    }
+
+    ///
+    /// Draws a group returned by push_group.
+    ///
+    pub fn draw_group<'rp>(&'rp self, render_pass: &'_ mut pipeline::RenderPass<'rp>, data: StrokeBindGroups<'rp>, group: Range<usize>) -> Result<(), pipeline::BindingError>{
+        for (brushop, instances) in &self.draws[group]{
+            brushop.draw_instances(render_pass, data, self, instances.clone())?;
+        }
+        Ok(())
     }
 }
 
@@ -161,10 +262,13 @@ pub struct BrushOpManager{
 }
 
 impl BrushOpManager{
-    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, format: wgpu::TextureFormat) -> Result<Self>{
+    ///
+    /// Creates the BrushOps for layers of format drawn with sample_count.
+    ///
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, cache: &cache::PipelineCache, format: wgpu::TextureFormat, sample_count: u32) -> Result<Self>{
         let mut ops: HashMap<String, Arc<BrushOp>> = HashMap::new();
 
-        let brushop_default = BrushOp::new(device, format, include_str!("shaders/brush01.wgsl"))?;
+        let brushop_default = BrushOp::new(device, cache, format, sample_count, include_str!("shaders/brush01.wgsl"))?;
 
         ops.insert("default".to_string(), Arc::new(brushop_default));
 
@@ -174,7 +278,13 @@ impl BrushOpManager{
     }
 
     pub fn arc_to(&self, key: &str) -> Result<Arc<BrushOp>>{
-        Ok(self.ops.get(key).ok_or(anyhow!("No BrushOp found for this name"))?.clone())
+        Ok(self.ops.get(key).ok_or_else(|| Error::MissingBrushOp(key.to_string()))?.clone())
+    }
+
+    pub fn names(&self) -> Vec<String>{
+        let mut names: Vec<String> = self.ops.keys().cloned().collect();
+        names.sort();
+        names
     }
 }
 
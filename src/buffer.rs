@@ -1,4 +1,4 @@
-use anyhow::*;
+use crate::error::Result;
 use wgpu::util::DeviceExt;
 use std::marker::PhantomData;
 
@@ -200,6 +200,11 @@ impl<C: bytemuck::Pod> UniformBindGroup<C>{
     pub fn update(&mut self, queue: &wgpu::Queue, src: &C){
         self.uniform_buffer.update(queue, src)
     }
+
+    pub fn bind_group_layout_builder() -> binding::BindGroupLayoutBuilder{
+        binding::BindGroupLayoutBuilder::new()
+            .push_entry_all(binding::wgsl::uniform())
+    }
 }
 
 impl<C: bytemuck::Pod> binding::GetBindGroupLayout for UniformBindGroup<C>{
@@ -216,8 +221,72 @@ impl<C: bytemuck::Pod> binding::GetBindGroup for UniformBindGroup<C>{
 
 impl<C: bytemuck::Pod> binding::ToBindGroupLayout for UniformBindGroup<C>{
     fn create_bind_group_layout(device: &wgpu::Device, label: Option<&str>) -> binding::BindGroupLayoutWithDesc {
-        binding::BindGroupLayoutBuilder::new()
-            .push_entry_all(binding::wgsl::uniform())
+        Self::bind_group_layout_builder()
             .create(device, None)
     }
 }
+
+///
+/// Vertex buffer for per instance data that is rewritten every frame.
+/// It only grows, so after a few frames no more buffers are created.
+///
+pub struct InstanceBuffer<C>{
+    buffer: wgpu::Buffer,
+    capacity: usize,
+    len: usize,
+    content_type: PhantomData<C>,
+}
+
+impl<C: bytemuck::Pod> InstanceBuffer<C>{
+    fn name() -> &'static str{
+        let type_name = std::any::type_name::<C>();
+        let pos = type_name.rfind(':').unwrap();
+        &type_name[(pos + 1)..]
+    }
+
+    fn create_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer{
+        device.create_buffer(&wgpu::BufferDescriptor{
+            label: Some(&format!("InstanceBuffer: {}", Self::name())),
+            size: (capacity * std::mem::size_of::<C>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    pub fn new(device: &wgpu::Device, capacity: usize) -> Self{
+        let capacity = capacity.max(1);
+        Self{
+            buffer: Self::create_buffer(device, capacity),
+            capacity,
+            len: 0,
+            content_type: PhantomData,
+        }
+    }
+
+    ///
+    /// Replaces the content with src, growing the buffer if it is too small.
+    ///
+    pub fn write(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, src: &[C]){
+        if src.len() > self.capacity{
+            self.capacity = src.len().next_power_of_two();
+            self.buffer = Self::create_buffer(device, self.capacity);
+        }
+
+        if !src.is_empty(){
+            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(src));
+        }
+        self.len = src.len();
+    }
+
+    pub fn len(&self) -> usize{
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.len == 0
+    }
+
+    pub fn slice(&self) -> wgpu::BufferSlice{
+        self.buffer.slice(..(self.len * std::mem::size_of::<C>()) as u64)
+    }
+}
@@ -0,0 +1,264 @@
+use crate::pipeline;
+use crate::error::Result;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::Hasher;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+///
+/// FNV-1a hash used to key the disk cache.
+/// std's DefaultHasher is not guaranteed to be stable between Rust releases.
+///
+struct Fnv64(u64);
+
+impl Fnv64{
+    fn new() -> Self{
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for Fnv64{
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes{
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+/// First word of every SPIR-V module.
+const SPIRV_MAGIC: u32 = 0x07230203;
+/// Words in the header of a SPIR-V module, magic, version, generator, bound and schema.
+const SPIRV_HEADER_WORDS: usize = 5;
+
+///
+/// Words of a little endian SPIR-V module, None if bytes can't be one.
+///
+fn parse_spirv(bytes: &[u8]) -> Option<Vec<u32>>{
+    if !bytes.len().is_multiple_of(4) || bytes.len() < SPIRV_HEADER_WORDS * 4{
+        return None;
+    }
+    let words: Vec<u32> = bytes.chunks_exact(4).map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect();
+    if words[0] != SPIRV_MAGIC{
+        return None;
+    }
+    Some(words)
+}
+
+///
+/// Caches compiled shader modules and render pipelines so they can be shared between all
+/// BlendOps, BrushOps and Layers.
+///
+/// Compiled SPIR-V is additionally written to a directory keyed by a hash of the source, shader
+/// kind, entry point, defines and compile options, so shaderc only runs when one of them changed.
+///
+/// Shader modules and pipelines only work on the device they were created with, so the cache
+/// holds its device and creates everything with it. Every device needs its own cache, the
+/// directory can be shared.
+///
+pub struct PipelineCache{
+    device: Arc<wgpu::Device>,
+    dir: Option<PathBuf>,
+    shaders: Mutex<HashMap<u64, Arc<wgpu::ShaderModule>>>,
+    pipelines: Mutex<HashMap<String, Arc<pipeline::RenderPipeline>>>,
+}
+
+impl PipelineCache{
+    ///
+    /// Creates a cache that only lives in memory.
+    ///
+    pub fn new(device: Arc<wgpu::Device>) -> Self{
+        Self{
+            device,
+            dir: None,
+            shaders: Mutex::new(HashMap::new()),
+            pipelines: Mutex::new(HashMap::new()),
+        }
+    }
+
+    ///
+    /// Creates a cache backed by SPIR-V files in dir.
+    ///
+    pub fn with_dir(device: Arc<wgpu::Device>, dir: impl Into<PathBuf>) -> Self{
+        Self{
+            dir: Some(dir.into()),
+            ..Self::new(device)
+        }
+    }
+
+    ///
+    /// The device all shader modules and pipelines of the cache are created with.
+    ///
+    pub fn device(&self) -> &Arc<wgpu::Device>{
+        &self.device
+    }
+
+    ///
+    /// The directory used by the application.
+    /// Can be overridden with the PBRUSH_SHADER_CACHE environment variable.
+    ///
+    pub fn default_dir() -> PathBuf{
+        match std::env::var_os("PBRUSH_SHADER_CACHE"){
+            Some(dir) => PathBuf::from(dir),
+            None => std::env::temp_dir().join("pbrush_shader_cache"),
+        }
+    }
+
+    ///
+    /// Hash of a shader source, for pipeline keys of ops that are defined by their source.
+    ///
+    pub fn source_hash(src: &str) -> u64{
+        let mut hasher = Fnv64::new();
+        hasher.write(src.as_bytes());
+        hasher.finish()
+    }
+
+    fn shader_hash(src: &str, kind: shaderc::ShaderKind, entry_point: &str, defines: &[(&str, Option<&str>)]) -> u64{
+        // Every field is prefixed with its length, so ("AB", None) and ("A", Some("B")) differ.
+        fn write_field(hasher: &mut Fnv64, bytes: &[u8]){
+            hasher.write_u64(bytes.len() as u64);
+            hasher.write(bytes);
+        }
+
+        let mut hasher = Fnv64::new();
+        write_field(&mut hasher, src.as_bytes());
+        write_field(&mut hasher, format!("{:?}", kind).as_bytes());
+        write_field(&mut hasher, entry_point.as_bytes());
+        hasher.write_u64(defines.len() as u64);
+        for (name, value) in defines{
+            write_field(&mut hasher, name.as_bytes());
+            match value{
+                Some(value) => {
+                    hasher.write_u8(1);
+                    write_field(&mut hasher, value.as_bytes());
+                },
+                None => hasher.write_u8(0),
+            }
+        }
+        write_field(&mut hasher, pipeline::SHADERC_OPTIONS.as_bytes());
+        hasher.finish()
+    }
+
+    ///
+    /// Reads cached SPIR-V, files that are not a SPIR-V module are ignored and compiled again.
+    ///
+    fn load_spirv(&self, hash: u64) -> Option<Vec<u32>>{
+        let path = self.dir.as_ref()?.join(format!("{:016x}.spv", hash));
+        parse_spirv(&fs::read(path).ok()?)
+    }
+
+    fn store_spirv(&self, hash: u64, spirv: &[u32]){
+        let dir = match &self.dir{
+            Some(dir) => dir,
+            None => return,
+        };
+        let bytes: Vec<u8> = spirv.iter().flat_map(|w| w.to_le_bytes()).collect();
+        let result = fs::create_dir_all(dir)
+            .and_then(|_| fs::write(dir.join(format!("{:016x}.spv", hash)), bytes));
+        if let Err(e) = result{
+            log::warn!("could not write shader cache: {}", e);
+        }
+    }
+
+    ///
+    /// Same as pipeline::shader_with_shaderc but only compiles on a cache miss.
+    ///
+    pub fn shader_with_shaderc(&self, src: &str, kind: shaderc::ShaderKind, entry_point: &str, defines: &[(&str, Option<&str>)], label: Option<&str>) -> Result<Arc<wgpu::ShaderModule>>{
+        let hash = Self::shader_hash(src, kind, entry_point, defines);
+
+        if let Some(module) = self.shaders.lock().unwrap().get(&hash){
+            return Ok(module.clone());
+        }
+
+        let spirv = match self.load_spirv(hash){
+            Some(spirv) => spirv,
+            None => {
+                let spirv = pipeline::compile_with_shaderc(src, kind, entry_point, defines, label)?;
+                self.store_spirv(hash, &spirv);
+                spirv
+            }
+        };
+
+        let module = Arc::new(pipeline::shader_from_spirv(&self.device, &spirv, label));
+        self.shaders.lock().unwrap().insert(hash, module.clone());
+
+        Ok(module)
+    }
+
+    ///
+    /// Returns the pipeline stored under key or builds and stores it.
+    /// The key has to describe everything that influences the pipeline, like the target format.
+    /// build gets the device of the cache to create the pipeline with.
+    ///
+    pub fn render_pipeline<F>(&self, key: &str, build: F) -> Result<Arc<pipeline::RenderPipeline>>
+        where F: FnOnce(&wgpu::Device) -> Result<pipeline::RenderPipeline>
+    {
+        if let Some(render_pipeline) = self.pipelines.lock().unwrap().get(key){
+            return Ok(render_pipeline.clone());
+        }
+
+        // build is allowed to use the shader cache so the lock can't be held here.
+        let mut render_pipeline = build(&self.device)?;
+        if render_pipeline.label.is_none(){
+            render_pipeline.label = Some(key.to_string());
+        }
+        let render_pipeline = Arc::new(render_pipeline);
+        self.pipelines.lock().unwrap().insert(key.to_string(), render_pipeline.clone());
+
+        Ok(render_pipeline)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::error::Error;
+
+    #[test]
+    fn shader_hash_separates_fields(){
+        let hash = |src, defines: &[(&str, Option<&str>)]| PipelineCache::shader_hash(src, shaderc::ShaderKind::Vertex, "main", defines);
+
+        assert_ne!(hash("", &[("AB", None)]), hash("", &[("A", Some("B"))]));
+        assert_ne!(hash("", &[("A", None)]), hash("", &[("A", Some(""))]));
+        assert_ne!(hash("AB", &[]), hash("A", &[("B", None)]));
+        assert_eq!(hash("A", &[("B", Some("C"))]), hash("A", &[("B", Some("C"))]));
+    }
+
+    #[test]
+    fn parse_spirv_checks_header(){
+        let header: Vec<u8> = [SPIRV_MAGIC, 0x00010000, 0, 1, 0].iter().flat_map(|w| w.to_le_bytes()).collect();
+        assert_eq!(parse_spirv(&header), Some(vec![SPIRV_MAGIC, 0x00010000, 0, 1, 0]));
+
+        // too short, not a multiple of 4 and the wrong magic.
+        assert_eq!(parse_spirv(&header[..16]), None);
+        assert_eq!(parse_spirv(&header[..19]), None);
+        assert_eq!(parse_spirv(&[0u8; 20]), None);
+        assert_eq!(parse_spirv(&[]), None);
+    }
+
+    #[test]
+    fn builds_on_the_device_of_the_cache(){
+        let device = match pollster::block_on(crate::HeadlessCanvas::request_device(true)){
+            Ok((device, _queue)) => Arc::new(device),
+            Err(Error::NoAdapter) => {
+                println!("skipped, no adapter");
+                return;
+            },
+            Err(err) => panic!("{}", err),
+        };
+        let cache = PipelineCache::new(device.clone());
+        let shader = || cache.shader_with_shaderc(include_str!("shaders/vert_screen.glsl"), shaderc::ShaderKind::Vertex, "main", &[], None).unwrap();
+        assert!(Arc::ptr_eq(&shader(), &shader()));
+
+        let result = cache.render_pipeline("Test", |build_device| {
+            assert!(std::ptr::eq(build_device, &*device));
+            Err(Error::InvalidArgument("not built".to_string()))
+        });
+        assert!(matches!(result, Err(Error::InvalidArgument(_))));
+    }
+}
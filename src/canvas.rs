@@ -1,7 +1,14 @@
 use crate::blendop;
+use crate::cache;
+use crate::color;
 use crate::layer;
+use crate::rect;
+use crate::render_graph;
+use crate::render_target::ColorAttachment;
+use crate::texture_pool;
 use crate::texture;
-use anyhow::*;
+use crate::tonemap;
+use crate::error::{Error, Result};
 use std::cell::RefCell;
 use std::sync::Arc;
 
@@ -9,105 +16,361 @@ pub struct Canvas {
     pub layers: Vec<RefCell<layer::Layer>>,
     blendops: Arc<blendop::BlendOpManager>,
     size: [u32; 2],
-    tex_tmp0: texture::Texture,
-    tex_tmp1: texture::Texture,
-    tex_tmp2: texture::Texture,
+    format: wgpu::TextureFormat,
+    // backs the intermediate textures of the render graph.
+    transients: texture_pool::TexturePool,
+    // stays black, it is what the first layer is blended onto.
+    tex_empty: texture::Texture,
+    // flattened layers below and above the active layer and the final composite.
+    tex_below: texture::Texture,
+    tex_above: texture::Texture,
+    tex_composite: texture::Texture,
+    tex_msaa: Option<texture::MultisampledTexture>,
+    sample_count: u32,
+    tonemap: tonemap::Tonemap,
+    color_space: color::ColorSpace,
+    active: Option<usize>,
+    // regions that have to be recomposited independent of the layers.
+    dirty: Option<rect::Rect>,
+    dirty_below: Option<rect::Rect>,
+    dirty_above: Option<rect::Rect>,
 }
 
 impl Canvas {
+    ///
+    /// format is the working format layers are composited in, e.g. Rgba16Float.
+    /// surface_format is the format of the views passed to draw.
+    /// color_space is the space layers are blended in, layers should be created with it.
+    /// Fails with UnsupportedFormat if format can't be filtered, see texture::check_filterable.
+    ///
     pub fn new(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
+        cache: &cache::PipelineCache,
         format: wgpu::TextureFormat,
+        surface_format: wgpu::TextureFormat,
+        color_space: color::ColorSpace,
         blendops: Arc<blendop::BlendOpManager>,
         size: [u32; 2],
     ) -> Result<Self> {
+        texture::check_filterable(device, format)?;
+
         let layers: Vec<RefCell<layer::Layer>> = Vec::new();
 
         let blendops = blendops;
 
-        let tex_tmp0 = texture::Texture::new_black(size, device, queue, None, format)?;
-        let tex_tmp1 = texture::Texture::new_black(size, device, queue, None, format)?;
-        let tex_tmp2 = texture::Texture::new_black(size, device, queue, None, format)?;
+        let [tex_empty, tex_below, tex_above, tex_composite] = Self::create_targets(device, queue, size, format, color_space);
+
+        let tonemap = tonemap::Tonemap::new(device, cache, surface_format, color_space)?;
 
         Ok(Self {
             layers,
             blendops,
             size,
-            tex_tmp0,
-            tex_tmp1,
-            tex_tmp2,
+            format,
+            transients: texture_pool::TexturePool::new(),
+            tex_empty,
+            tex_below,
+            tex_above,
+            tex_composite,
+            tex_msaa: None,
+            sample_count: 1,
+            tonemap,
+            color_space,
+            active: None,
+            dirty: Some(rect::Rect::full(size)),
+            dirty_below: Some(rect::Rect::full(size)),
+            dirty_above: Some(rect::Rect::full(size)),
         })
     }
 
-    pub fn push_layer(&mut self, layer: layer::Layer) {
+    pub fn color_space(&self) -> color::ColorSpace{
+        self.color_space
+    }
+
+    pub fn size(&self) -> [u32; 2]{
+        self.size
+    }
+
+    ///
+    /// Draws the layers multisampled if sample_count is larger than 1.
+    /// Strokes are painted with the sample count too, so BrushOps have to be created with it.
+    ///
+    pub fn set_sample_count(&mut self, device: &wgpu::Device, cache: &cache::PipelineCache, sample_count: u32) -> Result<()>{
+        for layer in &self.layers{
+            layer.borrow_mut().set_sample_count(device, cache, sample_count)?;
+        }
+
+        self.sample_count = sample_count;
+        self.tex_msaa = Self::create_msaa(device, self.size, self.format, sample_count);
+        Ok(())
+    }
+
+    ///
+    /// The textures kept between frames, cleared to black in one submission.
+    ///
+    fn create_targets(device: &wgpu::Device, queue: &wgpu::Queue, size: [u32; 2], format: wgpu::TextureFormat, color_space: color::ColorSpace) -> [texture::Texture; 4]{
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor{
+            label: Some("Canvas Clear Encoder"),
+        });
+
+        let targets = ["Canvas Empty", "Canvas Below", "Canvas Above", "Canvas Composite"].map(|label|{
+            let texture = texture::Texture::new(device, size, Some(label), format, color_space, false, texture::Texture::DEFAULT_USAGE);
+            texture.clear(&mut encoder);
+            texture
+        });
+
+        queue.submit(std::iter::once(encoder.finish()));
+        targets
+    }
+
+    fn create_msaa(device: &wgpu::Device, size: [u32; 2], format: wgpu::TextureFormat, sample_count: u32) -> Option<texture::MultisampledTexture>{
+        if sample_count > 1{
+            Some(texture::MultisampledTexture::new(device, size, Some("Canvas MSAA"), format, sample_count))
+        }
+        else{
+            None
+        }
+    }
+
+    pub fn sample_count(&self) -> u32{
+        self.sample_count
+    }
+
+    ///
+    /// Adds a layer on top, it is set to the sample count of the canvas.
+    ///
+    pub fn push_layer(&mut self, device: &wgpu::Device, cache: &cache::PipelineCache, mut layer: layer::Layer) -> Result<()>{
+        if layer.sample_count() != self.sample_count{
+            layer.set_sample_count(device, cache, self.sample_count)?;
+        }
         self.layers.push(RefCell::new(layer));
+        self.invalidate();
+        Ok(())
     }
 
     pub fn remove_layer(&mut self, index: usize) {
         self.layers.remove(index);
+        if let Some(active) = self.active{
+            if index < active{
+                self.active = Some(active - 1);
+            }
+        }
+        self.invalidate();
+    }
+
+    ///
+    /// Recomposites everything on the next draw.
+    /// Has to be called when layers are changed in ways the canvas can not see, like their
+    /// BlendOp.
+    ///
+    pub fn invalidate(&mut self){
+        let full = Some(rect::Rect::full(self.size));
+        self.dirty = full;
+        self.dirty_below = full;
+        self.dirty_above = full;
+    }
+
+    ///
+    /// The layer that is painted on, the last one if none was set.
+    ///
+    pub fn active_layer(&self) -> Option<usize>{
+        match self.active{
+            Some(active) if active < self.layers.len() => Some(active),
+            _ => self.layers.len().checked_sub(1),
+        }
+    }
+
+    ///
+    /// Sets the layer that is painted on.
+    /// The layers below and above it are cached so painting on it only costs a constant number of
+    /// blends.
+    ///
+    pub fn set_active_layer(&mut self, index: usize) -> Result<()>{
+        if index >= self.layers.len(){
+            return Err(Error::MissingLayer(index));
+        }
+        if self.active_layer() != Some(index){
+            self.active = Some(index);
+            self.invalidate();
+        }
+        Ok(())
     }
 
-    pub fn draw(&mut self, encoder: &mut wgpu::CommandEncoder, queue: &wgpu::Queue, dst: &wgpu::TextureView, dst_size: [u32; 2]) -> Result<()> {
-        for (i, layer) in self.layers.iter().enumerate() {
+    ///
+    /// Collects the regions of the caches and the composite that change when drawing.
+    ///
+    fn take_dirty_rects(&mut self, active: usize, dst_size: [u32; 2]) -> (Option<rect::Rect>, Option<rect::Rect>, Option<rect::Rect>){
+        let mut below = self.dirty_below.take();
+        let mut above = self.dirty_above.take();
+        let mut composite = self.dirty.take();
 
-            if i % 2 == 0{
-                layer.borrow_mut().apply_strokes(queue, encoder, &self.tex_tmp2.bind_group, dst_size)?;
+        for (i, layer) in self.layers.iter().enumerate(){
+            let layer_dirty = layer.borrow().dirty_rect(dst_size);
+            if i < active{
+                below = rect::union(below, layer_dirty);
             }
-            else{
-                layer.borrow_mut().apply_strokes(queue, encoder, &self.tex_tmp1.bind_group, dst_size)?;
+            else if i > active{
+                above = rect::union(above, layer_dirty);
             }
+            composite = rect::union(composite, layer_dirty);
+        }
+        composite = rect::union(composite, rect::union(below, above));
+
+        let clip = |dirty: Option<rect::Rect>| dirty
+            .map(|dirty| dirty.intersect(&rect::Rect::full(self.size)))
+            .filter(|dirty| !dirty.is_empty());
+
+        (clip(below), clip(above), clip(composite))
+    }
+
+    ///
+    /// Composites the layers into dst.
+    ///
+    /// The layers below and above the active layer are kept flattened between frames and only the
+    /// regions changed by strokes or moved layers are recomposited.
+    /// The layers above are only cached if they all share one associative BlendOp, otherwise they
+    /// are blended one by one on every draw.
+    ///
+    pub fn draw(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, queue: &wgpu::Queue, dst: &wgpu::TextureView, dst_size: [u32; 2]) -> Result<()> {
+        let active = match self.active_layer(){
+            Some(active) => active,
+            None => {
+                // nothing to composite, dst must not keep what was drawn before.
+                encoder.begin_render_pass(&wgpu::RenderPassDescriptor{
+                    label: Some("Clear"),
+                    color_attachments: &[dst.color_attachment_clear()],
+                    depth_stencil_attachment: None,
+                });
+                return Ok(());
+            },
+        };
+
+        let (below, above, composite) = self.take_dirty_rects(active, dst_size);
+        let cache_above = self.can_cache_above(active);
+        if !cache_above{
+            // rebuilt completely once it can be used again.
+            self.dirty_above = Some(rect::Rect::full(self.size));
+        }
+
+        let desc = render_graph::TextureDesc{
+            size: self.size,
+            format: self.format,
+            color_space: self.color_space,
+        };
+        let ctx = LayerPassContext{
+            device,
+            queue,
+            msaa: self.tex_msaa.as_ref(),
+            desc,
+            dst_size,
+        };
+
+        let mut graph = render_graph::RenderGraph::new();
+        let empty = graph.import_texture(&self.tex_empty);
+        let tex_below = graph.import_texture(&self.tex_below);
+        let tex_above = graph.import_texture(&self.tex_above);
+        let tex_composite = graph.import_texture(&self.tex_composite);
+        let surface = graph.import_view(dst);
+
+        if let Some(region) = below{
+            add_layer_passes(&mut graph, &ctx, &self.layers[..active], empty, tex_below, region);
+        }
+        if let (Some(region), true) = (above, cache_above){
+            add_layer_passes(&mut graph, &ctx, &self.layers[active + 1..], empty, tex_above, region);
+        }
 
+        if let Some(region) = composite{
+            let background = if active == 0 {empty} else {tex_below};
+            let layers = &self.layers[active..active + 1];
 
-            layer.borrow_mut().draw(encoder, queue, &self.tex_tmp0.view, dst_size)?;
-            let blendop = layer.borrow().blendop();
-
-            if i == self.layers.len() - 1 {
-                if i % 2 == 0 {
-                    blendop.draw(
-                        encoder,
-                        queue,
-                        dst,
-                        &self.tex_tmp0.bind_group,
-                        &self.tex_tmp2.bind_group,
-                    )?;
-                } else {
-                    blendop.draw(
-                        encoder,
-                        queue,
-                        dst,
-                        &self.tex_tmp0.bind_group,
-                        &self.tex_tmp1.bind_group,
-                    )?;
-                }
-            } else {
-                if i % 2 == 0 {
-                    blendop.draw(
-                        encoder,
-                        queue,
-                        &self.tex_tmp1.view,
-                        &self.tex_tmp0.bind_group,
-                        &self.tex_tmp2.bind_group,
-                    )?;
-                } else {
-                    blendop.draw(
-                        encoder,
-                        queue,
-                        &self.tex_tmp2.view,
-                        &self.tex_tmp0.bind_group,
-                        &self.tex_tmp1.bind_group,
-                    )?;
-                }
+            match self.layers.get(active + 1){
+                Some(lowest_above) if cache_above => {
+                    let painted = graph.create_texture(desc);
+                    add_layer_passes(&mut graph, &ctx, layers, background, painted, region);
+
+                    let blendop = lowest_above.borrow().blendop();
+                    graph.add_pass("Blend above", &[tex_above, painted], &[tex_composite], move |encoder, res|{
+                        blendop.draw_region(encoder, queue, res.view(tex_composite)?, &res.texture(tex_above)?.bind_group, &res.texture(painted)?.bind_group, &region)
+                    });
+                },
+                _ => {
+                    add_layer_passes(&mut graph, &ctx, &self.layers[active..], background, tex_composite, region);
+                },
             }
         }
 
-        Ok(())
+        let tonemap = &self.tonemap;
+        graph.add_pass("Tonemap", &[tex_composite], &[surface], move |encoder, res|{
+            tonemap.draw(encoder, res.view(surface)?, &res.texture(tex_composite)?.bind_group)
+        });
+
+        graph.execute(device, encoder, &mut self.transients)
+    }
+
+    ///
+    /// Whether the layers above active can be flattened into tex_above and blended onto the
+    /// active layer in one go. That is exact if they all use the same associative BlendOp.
+    ///
+    fn can_cache_above(&self, active: usize) -> bool{
+        let above = &self.layers[active + 1..];
+        let first = match above.first(){
+            Some(layer) => layer.borrow().blendop(),
+            None => return true,
+        };
+        first.associative() && above.iter().all(|layer| Arc::ptr_eq(&layer.borrow().blendop(), &first))
     }
 
     pub fn resize(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, size: [u32; 2]) -> Result<()>{
-        self.tex_tmp0 = texture::Texture::new_black(size, device, queue, None, self.tex_tmp0.format)?;
-        self.tex_tmp1 = texture::Texture::new_black(size, device, queue, None, self.tex_tmp1.format)?;
-        self.tex_tmp2 = texture::Texture::new_black(size, device, queue, None, self.tex_tmp2.format)?;
+        let [tex_empty, tex_below, tex_above, tex_composite] = Self::create_targets(device, queue, size, self.format, self.color_space);
+        self.tex_empty = tex_empty;
+        self.tex_below = tex_below;
+        self.tex_above = tex_above;
+        self.tex_composite = tex_composite;
+        // Pooled textures of the old size would never be handed out again.
+        self.transients.clear();
+        self.tex_msaa = Self::create_msaa(device, size, self.format, self.sample_count);
+        self.size = size;
+        self.invalidate();
         Ok(())
     }
 }
+
+///
+/// What the passes of a layer need besides the layer.
+///
+struct LayerPassContext<'a>{
+    device: &'a wgpu::Device,
+    queue: &'a wgpu::Queue,
+    msaa: Option<&'a texture::MultisampledTexture>,
+    desc: render_graph::TextureDesc,
+    dst_size: [u32; 2],
+}
+
+///
+/// Adds the passes compositing layers onto background into region of dst.
+/// Every layer gets a pass drawing it, with its strokes applied, and a pass blending it.
+///
+fn add_layer_passes<'a>(graph: &mut render_graph::RenderGraph<'a>, ctx: &LayerPassContext<'a>, layers: &'a [RefCell<layer::Layer>], background: render_graph::ResourceId, dst: render_graph::ResourceId, region: rect::Rect){
+    let mut prev = background;
+
+    for (i, layer) in layers.iter().enumerate(){
+        let drawn = graph.create_texture(ctx.desc);
+        let composite = if i + 1 == layers.len() {dst} else {graph.create_texture(ctx.desc)};
+
+        let LayerPassContext{device, queue, msaa, dst_size, ..} = *ctx;
+
+        graph.add_pass("Layer", &[prev], &[drawn], move |encoder, res|{
+            let mut layer = layer.borrow_mut();
+            layer.apply_strokes(device, queue, encoder, &res.texture(prev)?.bind_group, dst_size)?;
+            layer.draw(encoder, queue, res.view(drawn)?, msaa, dst_size, &region)
+        });
+
+        let blendop = layer.borrow().blendop();
+        graph.add_pass("Blend", &[drawn, prev], &[composite], move |encoder, res|{
+            blendop.draw_region(encoder, queue, res.view(composite)?, &res.texture(drawn)?.bind_group, &res.texture(prev)?.bind_group, &region)
+        });
+
+        prev = composite;
+    }
+}
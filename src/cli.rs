@@ -0,0 +1,516 @@
+//!
+//! Command line batch mode, runs without a window on a HeadlessCanvas.
+//!
+//! ```text
+//! wgpu01 render <input>... -o <output> [--size WxH] [--blendop NAME] [--cpu]
+//! wgpu01 convert <input> <output>
+//! wgpu01 apply-strokes <image> <strokes> -o <output> [--brush NAME] [--cpu]
+//! wgpu01 info <input>... [--size WxH] [--blendop NAME] [--cpu]
+//! wgpu01 script <file> [--size WxH]
+//! wgpu01 [--script <file>] [--record <file>] [--samples N]
+//! ```
+//!
+//! Without a command the window is opened, see app.
+//!
+//! Inputs are images, stacked as layers from the bottom up, or Rhai scripts (.rhai) that build
+//! the document, see script. The canvas has the size of the first image unless --size is given.
+//! The format of written images follows their extension. convert only changes the format, the
+//! pixels and their bit depth are kept where the format allows it.
+//!
+//! render, apply-strokes and info use the CPU reference renderer with --cpu or if there is no
+//! adapter, it can not run scripts.
+//!
+//! Stroke files are stroke logs, see stroke_log, or hold one point per line as `x y [pressure]`
+//! in canvas uv, (0, 0) is the bottom left. An empty line ends a stroke, lines starting with # are
+//! ignored. Points are painted on the image with --brush, logs are replayed as recorded.
+//!
+use crate::app;
+use crate::{Error, Result};
+use wgpu01::color;
+use wgpu01::script;
+use wgpu01::stroke_log::{self, StrokeLog};
+use wgpu01::{HeadlessCanvas, ReferenceCanvas};
+use image::GenericImageView;
+use std::path::{Path, PathBuf};
+
+pub const USAGE: &str = "\
+usage:
+    wgpu01 render <input>... -o <output> [--size WxH] [--blendop NAME] [--cpu]
+    wgpu01 convert <input> <output>
+    wgpu01 apply-strokes <image> <strokes> -o <output> [--brush NAME] [--cpu]
+    wgpu01 info <input>... [--size WxH] [--blendop NAME] [--cpu]
+    wgpu01 script <file> [--size WxH]
+    wgpu01 [--script <file>] [--record <file>] [--samples N]    opens the window";
+
+const COMMANDS: &[&str] = &["render", "convert", "apply-strokes", "info", "script"];
+
+/// size of canvases without an image to take it from.
+const DEFAULT_SIZE: [u32; 2] = [1000, 1000];
+
+///
+/// True if name is a subcommand, otherwise the window is opened.
+///
+fn is_command(name: &str) -> bool{
+    COMMANDS.contains(&name)
+}
+
+struct Args{
+    positional: Vec<String>,
+    output: Option<PathBuf>,
+    size: Option<[u32; 2]>,
+    blendop: String,
+    brush: String,
+    cpu: bool,
+}
+
+fn usage(message: &str) -> Error{
+    Error::Usage(format!("{}\n{}", message, USAGE))
+}
+
+fn parse_size(size: &str) -> Option<[u32; 2]>{
+    let (width, height) = size.split_once('x')?;
+    let size = [width.parse().ok()?, height.parse().ok()?];
+    if size[0] == 0 || size[1] == 0{
+        return None;
+    }
+    Some(size)
+}
+
+impl Args{
+    fn parse(args: &[String]) -> Result<Self>{
+        let mut parsed = Self{
+            positional: Vec::new(),
+            output: None,
+            size: None,
+            blendop: "Add".to_string(),
+            brush: "default".to_string(),
+            cpu: false,
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next(){
+            let mut value = || args.next().ok_or_else(|| usage(&format!("{} needs a value", arg)));
+            match arg.as_str(){
+                "-o" | "--output" => parsed.output = Some(PathBuf::from(value()?)),
+                "--size" => {
+                    let size = value()?;
+                    parsed.size = Some(parse_size(size).ok_or_else(|| usage(&format!("Invalid size {:?}, expected WxH", size)))?);
+                },
+                "--blendop" => parsed.blendop = value()?.clone(),
+                "--brush" => parsed.brush = value()?.clone(),
+                "--cpu" => parsed.cpu = true,
+                _ if arg.starts_with('-') => return Err(usage(&format!("Unknown option {}", arg))),
+                _ => parsed.positional.push(arg.clone()),
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn output(&self) -> Result<&Path>{
+        self.output.as_deref().ok_or_else(|| usage("Missing -o <output>"))
+    }
+
+    fn inputs(&self) -> Result<&[String]>{
+        if self.positional.is_empty(){
+            return Err(usage("Missing input"));
+        }
+        Ok(&self.positional)
+    }
+}
+
+///
+/// Options of the window.
+///
+pub struct WindowArgs{
+    pub script: Option<PathBuf>,
+    pub record: Option<PathBuf>,
+    /// layers and strokes are drawn with this many samples, 1 or 4.
+    pub samples: u32,
+}
+
+impl WindowArgs{
+    fn parse(args: &[String]) -> Result<Self>{
+        let mut parsed = Self{
+            script: None,
+            record: None,
+            samples: 1,
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next(){
+            let mut value = || args.next().ok_or_else(|| usage(&format!("{} needs a value", arg)));
+            match arg.as_str(){
+                "--script" => parsed.script = Some(PathBuf::from(value()?)),
+                "--record" => parsed.record = Some(PathBuf::from(value()?)),
+                "--samples" => {
+                    let samples = value()?;
+                    parsed.samples = match samples.as_str(){
+                        "1" => 1,
+                        "4" => 4,
+                        _ => return Err(usage(&format!("Invalid sample count {:?}, expected 1 or 4", samples))),
+                    };
+                },
+                _ if arg.starts_with('-') => return Err(usage(&format!("Unknown option {}", arg))),
+                _ => return Err(usage(&format!("Unknown command {}", arg))),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+fn is_script(path: &str) -> bool{
+    Path::new(path).extension().map_or(false, |ext| ext == "rhai")
+}
+
+///
+/// --size, or the size of the first image.
+///
+fn canvas_size(args: &Args, inputs: &[String]) -> Result<[u32; 2]>{
+    if let Some(size) = args.size{
+        return Ok(size);
+    }
+    match inputs.iter().find(|input| !is_script(input)){
+        Some(image) => {
+            let (width, height) = image::image_dimensions(image)?;
+            Ok([width, height])
+        },
+        None => Ok(DEFAULT_SIZE),
+    }
+}
+
+///
+/// A layer as printed by info.
+///
+struct LayerInfo{
+    size: [u32; 2],
+    blendop: String,
+    translation: [f32; 3],
+    scale: [f32; 3],
+}
+
+///
+/// What render, apply-strokes and info need of a canvas, so they can run on the CPU.
+///
+trait Backend{
+    fn push_image(&mut self, img: &image::DynamicImage, blendop: &str) -> wgpu01::Result<usize>;
+    fn run_script(&mut self, path: &Path) -> wgpu01::Result<()>;
+    fn replay(&mut self, log: &StrokeLog) -> wgpu01::Result<()>;
+    fn render_rgba8(&mut self) -> wgpu01::Result<image::RgbaImage>;
+    fn size(&self) -> [u32; 2];
+    fn color_space(&self) -> color::ColorSpace;
+    fn layers(&self) -> Vec<LayerInfo>;
+}
+
+impl Backend for HeadlessCanvas{
+    fn push_image(&mut self, img: &image::DynamicImage, blendop: &str) -> wgpu01::Result<usize>{
+        HeadlessCanvas::push_image(self, img, blendop)
+    }
+
+    fn run_script(&mut self, path: &Path) -> wgpu01::Result<()>{
+        script::run_file(self, path)
+    }
+
+    fn replay(&mut self, log: &StrokeLog) -> wgpu01::Result<()>{
+        log.replay(&self.canvas, &self.brushops)
+    }
+
+    fn render_rgba8(&mut self) -> wgpu01::Result<image::RgbaImage>{
+        self.render()?;
+        self.read_rgba8()
+    }
+
+    fn size(&self) -> [u32; 2]{
+        HeadlessCanvas::size(self)
+    }
+
+    fn color_space(&self) -> color::ColorSpace{
+        self.canvas.color_space()
+    }
+
+    fn layers(&self) -> Vec<LayerInfo>{
+        self.canvas.layers.iter()
+            .map(|layer| {
+                let layer = layer.borrow();
+                LayerInfo{
+                    size: layer.size(),
+                    blendop: self.blendops.name_of(&layer.blendop()).unwrap_or("?").to_string(),
+                    translation: layer.translation.into(),
+                    scale: layer.scale.into(),
+                }
+            })
+            .collect()
+    }
+}
+
+impl Backend for ReferenceCanvas{
+    fn push_image(&mut self, img: &image::DynamicImage, blendop: &str) -> wgpu01::Result<usize>{
+        ReferenceCanvas::push_image(self, &img.to_rgba8(), blendop)
+    }
+
+    fn run_script(&mut self, path: &Path) -> wgpu01::Result<()>{
+        Err(wgpu01::Error::Script(format!("{}: scripts can not run on the CPU renderer", path.display())))
+    }
+
+    fn replay(&mut self, log: &StrokeLog) -> wgpu01::Result<()>{
+        ReferenceCanvas::replay(self, log)
+    }
+
+    fn render_rgba8(&mut self) -> wgpu01::Result<image::RgbaImage>{
+        self.render()
+    }
+
+    fn size(&self) -> [u32; 2]{
+        ReferenceCanvas::size(self)
+    }
+
+    fn color_space(&self) -> color::ColorSpace{
+        ReferenceCanvas::color_space(self)
+    }
+
+    fn layers(&self) -> Vec<LayerInfo>{
+        self.layers.iter()
+            .map(|layer| LayerInfo{
+                size: layer.size(),
+                blendop: layer.blendop.clone(),
+                translation: layer.translation.into(),
+                scale: layer.scale.into(),
+            })
+            .collect()
+    }
+}
+
+///
+/// A HeadlessCanvas, or the CPU renderer with --cpu or without an adapter.
+///
+fn backend(args: &Args, size: [u32; 2]) -> Result<Box<dyn Backend>>{
+    if !args.cpu{
+        match HeadlessCanvas::new(size){
+            Ok(canvas) => return Ok(Box::new(canvas)),
+            Err(wgpu01::Error::NoAdapter) => eprintln!("No adapter found, rendering on the CPU"),
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(Box::new(ReferenceCanvas::new(size, color::ColorSpace::Linear)))
+}
+
+///
+/// Adds images as layers and runs scripts in the order given.
+///
+fn load(canvas: &mut dyn Backend, inputs: &[String], blendop: &str) -> Result<()>{
+    for input in inputs{
+        if is_script(input){
+            canvas.run_script(Path::new(input))?;
+        }
+        else{
+            canvas.push_image(&image::open(input)?, blendop)?;
+        }
+    }
+    Ok(())
+}
+
+///
+/// Saves in the format of the extension. Jpeg gets alpha dropped and 8 bits per channel, other
+/// formats keep the color type of img.
+///
+fn save_image(img: &image::DynamicImage, path: &Path) -> Result<()>{
+    let ext = path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+    match (ext.as_deref(), img.color()){
+        (Some("jpg") | Some("jpeg"), image::ColorType::L8 | image::ColorType::Rgb8) => img.save(path)?,
+        (Some("jpg") | Some("jpeg"), _) => img.to_rgb8().save(path)?,
+        _ => img.save(path)?,
+    }
+    Ok(())
+}
+
+fn render_and_save(canvas: &mut dyn Backend, path: &Path) -> Result<()>{
+    save_image(&image::DynamicImage::ImageRgba8(canvas.render_rgba8()?), path)
+}
+
+///
+/// Reads a stroke file, returns the points of every stroke.
+///
+fn read_strokes(path: &Path) -> Result<Vec<Vec<[f32; 3]>>>{
+    let src = std::fs::read_to_string(path)?;
+    let invalid = |line: usize, message: String| Error::from(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("{}:{}: {}", path.display(), line + 1, message),
+    ));
+
+    let mut strokes = Vec::new();
+    let mut stroke = Vec::new();
+    for (i, line) in src.lines().enumerate(){
+        let line = line.trim();
+        if line.starts_with('#'){
+            continue;
+        }
+        if line.is_empty(){
+            if !stroke.is_empty(){
+                strokes.push(std::mem::take(&mut stroke));
+            }
+            continue;
+        }
+
+        let values = line.split_whitespace()
+            .map(|v| v.parse::<f32>().map_err(|e| invalid(i, format!("{:?}: {}", v, e))))
+            .collect::<Result<Vec<f32>>>()?;
+        match values[..]{
+            [x, y] => stroke.push([x, y, 1.0]),
+            [x, y, pressure] => stroke.push([x, y, pressure]),
+            _ => return Err(invalid(i, format!("Expected x y [pressure], got {} numbers", values.len()))),
+        }
+    }
+    if !stroke.is_empty(){
+        strokes.push(stroke);
+    }
+    Ok(strokes)
+}
+
+fn render(args: &Args) -> Result<()>{
+    let inputs = args.inputs()?;
+    let output = args.output()?;
+
+    let mut canvas = backend(args, canvas_size(args, inputs)?)?;
+    load(canvas.as_mut(), inputs, &args.blendop)?;
+    render_and_save(canvas.as_mut(), output)
+}
+
+fn convert(args: &Args) -> Result<()>{
+    let (input, output) = match &args.positional[..]{
+        [input, output] => (input, output),
+        _ => return Err(usage("convert takes an input and an output")),
+    };
+    if is_script(input){
+        return Err(usage("convert takes an image, use render for scripts"));
+    }
+
+    save_image(&image::open(input)?, Path::new(output))
+}
+
+fn apply_strokes(args: &Args) -> Result<()>{
+    let (input, strokes) = match &args.positional[..]{
+        [input, strokes] => (input, strokes),
+        _ => return Err(usage("apply-strokes takes an image and a stroke file")),
+    };
+    let output = args.output()?;
+    let strokes = Path::new(strokes);
+
+    let img = image::open(input)?;
+    let mut canvas = backend(args, [img.width(), img.height()])?;
+    let layer = canvas.push_image(&img, &args.blendop)?;
+
+    // Stroke logs name their layers and brushes, plain point lists paint on the image.
+    let log = if StrokeLog::is_log(strokes){
+        StrokeLog::load(strokes)?
+    }
+    else{
+        let strokes = read_strokes(strokes)?.into_iter()
+            .map(|points| stroke_log::RecordedStroke{
+                layer,
+                brush: args.brush.clone(),
+                params: Default::default(),
+                samples: points.iter()
+                    .map(|p| stroke_log::Sample{time: 0.0, pos: [p[0], p[1]], pressure: p[2], tilt: [0.0, 0.0]})
+                    .collect(),
+            })
+            .collect();
+        StrokeLog{strokes}
+    };
+    canvas.replay(&log)?;
+
+    render_and_save(canvas.as_mut(), output)
+}
+
+fn info(args: &Args) -> Result<()>{
+    let inputs = args.inputs()?;
+
+    let mut canvas = backend(args, canvas_size(args, inputs)?)?;
+    load(canvas.as_mut(), inputs, &args.blendop)?;
+
+    let size = canvas.size();
+    println!("canvas: {}x{} {:?}", size[0], size[1], canvas.color_space());
+    for (i, layer) in canvas.layers().iter().enumerate(){
+        println!(
+            "layer {}: {}x{} blendop {} translation {:?} scale {:?}",
+            i,
+            layer.size[0],
+            layer.size[1],
+            layer.blendop,
+            layer.translation,
+            layer.scale,
+        );
+    }
+    Ok(())
+}
+
+fn script(args: &Args) -> Result<()>{
+    let path = match &args.positional[..]{
+        [path] => path,
+        _ => return Err(usage("script takes one file")),
+    };
+
+    let mut canvas = HeadlessCanvas::new(args.size.unwrap_or(DEFAULT_SIZE))?;
+    Ok(script::run_file(&mut canvas, Path::new(path))?)
+}
+
+///
+/// Runs the subcommand args starts with, or opens the window if args starts with no command.
+///
+pub fn run(args: &[String]) -> Result<()>{
+    let (command, rest) = match args.split_first(){
+        Some((command, rest)) if is_command(command) => (command, rest),
+        _ => return app::run(WindowArgs::parse(args)?),
+    };
+    let args = Args::parse(rest)?;
+    match command.as_str(){
+        "render" => render(&args),
+        "convert" => convert(&args),
+        "apply-strokes" => apply_strokes(&args),
+        "info" => info(&args),
+        "script" => script(&args),
+        _ => Err(usage(&format!("Unknown command {}", command))),
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String>{
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn reads_stroke_files(){
+        let dir = std::env::temp_dir().join("wgpu01_cli_unit");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("strokes.txt");
+        std::fs::write(&path, "# two strokes\n0.1 0.1\n0.2 0.2 0.5\n\n\n0.5 0.5 1\n0.6 0.5\n").unwrap();
+
+        let strokes = read_strokes(&path).unwrap();
+        assert_eq!(strokes, vec![
+            vec![[0.1, 0.1, 1.0], [0.2, 0.2, 0.5]],
+            vec![[0.5, 0.5, 1.0], [0.6, 0.5, 1.0]],
+        ]);
+
+        std::fs::write(&path, "0.1 0.1\n0.2\n").unwrap();
+        assert!(read_strokes(&path).is_err());
+    }
+
+    #[test]
+    fn parses_window_options(){
+        let parsed = WindowArgs::parse(&args(&["--samples", "4", "--script", "a.rhai", "--record", "log.txt"])).unwrap();
+        assert_eq!(parsed.samples, 4);
+        assert_eq!(parsed.script, Some(PathBuf::from("a.rhai")));
+        assert_eq!(parsed.record, Some(PathBuf::from("log.txt")));
+
+        let defaults = WindowArgs::parse(&[]).unwrap();
+        assert_eq!(defaults.samples, 1);
+        assert!(defaults.script.is_none() && defaults.record.is_none());
+
+        for case in [&["--record"][..], &["--samples", "x"], &["--samples", "2"], &["--samples", "0"], &["--size", "10x10"], &["render.png"]]{
+            assert!(matches!(WindowArgs::parse(&args(case)), Err(Error::Usage(_))), "{:?} was accepted", case);
+        }
+    }
+}
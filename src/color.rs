@@ -0,0 +1,130 @@
+///
+/// The space color values are stored and blended in.
+///
+/// Canvases use Linear by default so blending and brushing happens in linear light.
+/// Srgb keeps values gamma encoded for documents that rely on the legacy look of gamma space
+/// blending.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorSpace{
+    Linear,
+    Srgb,
+}
+
+impl Default for ColorSpace{
+    fn default() -> Self {
+        ColorSpace::Linear
+    }
+}
+
+pub fn srgb_to_linear(v: f32) -> f32{
+    if v <= 0.04045{
+        v / 12.92
+    }
+    else{
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(v: f32) -> f32{
+    if v <= 0.0031308{
+        v * 12.92
+    }
+    else{
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+///
+/// Converts a color from src to dst space, alpha is always linear.
+///
+pub fn convert(rgba: [f32; 4], src: ColorSpace, dst: ColorSpace) -> [f32; 4]{
+    let f = match (src, dst){
+        (ColorSpace::Srgb, ColorSpace::Linear) => srgb_to_linear,
+        (ColorSpace::Linear, ColorSpace::Srgb) => linear_to_srgb,
+        _ => return rgba,
+    };
+    [f(rgba[0]), f(rgba[1]), f(rgba[2]), rgba[3]]
+}
+
+///
+/// Whether the GPU converts between sRGB and linear when sampling and writing the format.
+///
+pub fn is_srgb_format(format: wgpu::TextureFormat) -> bool{
+    format.describe().srgb
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn endpoints_are_fixed(){
+        for v in [0.0, 1.0]{
+            assert_eq!(srgb_to_linear(v), v);
+            assert!((linear_to_srgb(v) - v).abs() < 1e-6, "{}", linear_to_srgb(v));
+        }
+    }
+
+    #[test]
+    fn known_values(){
+        // sRGB mid grey is about 21.4% linear light.
+        assert!((srgb_to_linear(0.5) - 0.21404).abs() < 1e-5);
+        assert!((linear_to_srgb(0.21404) - 0.5).abs() < 1e-5);
+        // the linear segment below the thresholds.
+        assert_eq!(srgb_to_linear(0.04045), 0.04045 / 12.92);
+        assert_eq!(linear_to_srgb(0.0031308), 0.0031308 * 12.92);
+    }
+
+    #[test]
+    fn round_trips(){
+        for i in 0..=1000{
+            let v = i as f32 / 1000.0;
+            assert!((linear_to_srgb(srgb_to_linear(v)) - v).abs() < 1e-5, "{}", v);
+            assert!((srgb_to_linear(linear_to_srgb(v)) - v).abs() < 1e-5, "{}", v);
+        }
+        // every 8 bit value survives the trip.
+        for v in 0..=255u8{
+            let back = (linear_to_srgb(srgb_to_linear(v as f32 / 255.0)) * 255.0).round() as u8;
+            assert_eq!(back, v);
+        }
+    }
+
+    #[test]
+    fn curves_are_continuous_and_monotonic(){
+        // both pieces meet at the thresholds.
+        assert!((((0.04045f32 + 0.055) / 1.055).powf(2.4) - 0.04045 / 12.92).abs() < 1e-6);
+        assert!(((1.055 * 0.0031308f32.powf(1.0 / 2.4) - 0.055) - 0.0031308 * 12.92).abs() < 1e-6);
+
+        let mut prev = [srgb_to_linear(0.0), linear_to_srgb(0.0)];
+        for i in 1..=1000{
+            let v = i as f32 / 1000.0;
+            let next = [srgb_to_linear(v), linear_to_srgb(v)];
+            assert!(next[0] > prev[0] && next[1] > prev[1], "{}", v);
+            prev = next;
+        }
+    }
+
+    #[test]
+    fn convert_keeps_alpha(){
+        let rgba = [0.5, 0.25, 1.0, 0.5];
+        let linear = convert(rgba, ColorSpace::Srgb, ColorSpace::Linear);
+        assert_eq!(linear[3], 0.5);
+        assert_eq!(linear[0], srgb_to_linear(0.5));
+        assert_eq!(convert(rgba, ColorSpace::Srgb, ColorSpace::Srgb), rgba);
+        assert_eq!(convert(rgba, ColorSpace::Linear, ColorSpace::Linear), rgba);
+
+        let back = convert(linear, ColorSpace::Linear, ColorSpace::Srgb);
+        for (a, b) in back.iter().zip(rgba.iter()){
+            assert!((a - b).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn srgb_formats(){
+        assert!(is_srgb_format(wgpu::TextureFormat::Rgba8UnormSrgb));
+        assert!(is_srgb_format(wgpu::TextureFormat::Bgra8UnormSrgb));
+        assert!(!is_srgb_format(wgpu::TextureFormat::Rgba8Unorm));
+        assert!(!is_srgb_format(wgpu::TextureFormat::Rgba16Float));
+    }
+}
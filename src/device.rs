@@ -1,7 +0,0 @@
-
-#[derive(Default)]
-pub struct Device{
-    pub pos: [f32; 2],
-    pub p: f32,
-    pub tilt: [f32; 2],
-}
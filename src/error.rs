@@ -0,0 +1,61 @@
+use crate::pipeline;
+use crate::rect;
+use crate::render_graph;
+
+///
+/// Errors of the rendering library.
+///
+#[derive(Debug, thiserror::Error)]
+pub enum Error{
+    #[error("Failed to compile shader {label:?}: {message}")]
+    ShaderCompilation{label: String, message: String},
+    #[error("Texture format {0:?} is not supported")]
+    UnsupportedFormat(wgpu::TextureFormat),
+    #[error("No BlendOp named {0:?}")]
+    MissingBlendOp(String),
+    #[error("No BrushOp named {0:?}")]
+    MissingBrushOp(String),
+    #[error("Layer {0} does not exist")]
+    MissingLayer(usize),
+    #[error("Tile {0:?} is not allocated")]
+    MissingTile([u32; 2]),
+    #[error("Sample count {layer} of the layer does not match sample count {target} of the target")]
+    SampleCountMismatch{layer: u32, target: u32},
+    #[error("Expected {expected} bytes but got {actual}")]
+    DataSize{expected: usize, actual: usize},
+    #[error("Region {0:?} is outside of the texture")]
+    OutOfBounds(rect::Rect),
+    /// A value passed to the library that it can't work with.
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+    /// Depth or stencil state was set on a RenderPipelineBuilder without a depth stencil format.
+    #[error("Depth or stencil state needs a depth stencil format")]
+    NoDepthStencilFormat,
+    #[error(transparent)]
+    Binding(#[from] pipeline::BindingError),
+    #[error(transparent)]
+    RenderGraph(#[from] render_graph::GraphError),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to decode image: {0}")]
+    Image(#[from] image::ImageError),
+    /// Mapping a buffer only fails if the device was lost or the buffer destroyed.
+    #[error("The device was lost")]
+    DeviceLost,
+    #[error("Surface error: {0}")]
+    Surface(#[from] wgpu::SurfaceError),
+    #[error("No adapter compatible with the surface was found")]
+    NoAdapter,
+    #[error("Failed to request a device: {0}")]
+    RequestDevice(#[from] wgpu::RequestDeviceError),
+    #[error("Script error: {0}")]
+    Script(String),
+}
+
+impl From<wgpu::BufferAsyncError> for Error{
+    fn from(_: wgpu::BufferAsyncError) -> Self{
+        Self::DeviceLost
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
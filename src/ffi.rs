@@ -0,0 +1,234 @@
+//!
+//! C ABI over HeadlessCanvas, see include/wgpu01.h.
+//!
+//! Canvases are handed out as opaque pointers. Every function returns a Wgpu01Result, the
+//! message of the last error of the calling thread is available through
+//! wgpu01_last_error_message. Panics are caught and reported as WGPU01_RESULT_PANIC.
+//!
+use crate::brush;
+use crate::error::Error;
+use crate::headless::HeadlessCanvas;
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Wgpu01Result{
+    Ok = 0,
+    NullPointer = 1,
+    InvalidArgument = 2,
+    NoAdapter = 3,
+    DeviceLost = 4,
+    ShaderCompilation = 5,
+    UnsupportedFormat = 6,
+    MissingOp = 7,
+    MissingLayer = 8,
+    Io = 9,
+    Decode = 10,
+    Panic = 11,
+    Other = 12,
+}
+
+impl From<&Error> for Wgpu01Result{
+    fn from(err: &Error) -> Self{
+        match err{
+            Error::ShaderCompilation{..} => Self::ShaderCompilation,
+            Error::UnsupportedFormat(_) => Self::UnsupportedFormat,
+            Error::MissingBlendOp(_) | Error::MissingBrushOp(_) | Error::Binding(_) => Self::MissingOp,
+            Error::MissingLayer(_) => Self::MissingLayer,
+            Error::Io(_) => Self::Io,
+            Error::Image(_) => Self::Decode,
+            Error::DeviceLost => Self::DeviceLost,
+            Error::NoAdapter | Error::RequestDevice(_) => Self::NoAdapter,
+            Error::InvalidArgument(_) | Error::DataSize{..} | Error::OutOfBounds(_) => Self::InvalidArgument,
+            Error::Script(_) | Error::Surface(_)
+                | Error::MissingTile(_) | Error::SampleCountMismatch{..} | Error::RenderGraph(_)
+                | Error::NoDepthStencilFormat => Self::Other,
+        }
+    }
+}
+
+///
+/// Opaque handle to a headless canvas.
+///
+pub struct Wgpu01Canvas{
+    canvas: HeadlessCanvas,
+}
+
+///
+/// A point of a stroke in canvas uv, (0, 0) is the bottom left corner.
+///
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Wgpu01StrokePoint{
+    pub x: f32,
+    pub y: f32,
+    pub pressure: f32,
+}
+
+thread_local!{
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String){
+    // Interior nul bytes would cut the message short anyway.
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+///
+/// Runs f, recording the error message if it fails or panics.
+///
+fn guard<F>(f: F) -> Wgpu01Result
+    where F: FnOnce() -> Result<(), (Wgpu01Result, String)>
+{
+    match panic::catch_unwind(AssertUnwindSafe(f)){
+        Ok(Ok(())) => Wgpu01Result::Ok,
+        Ok(Err((result, message))) => {
+            set_last_error(message);
+            result
+        },
+        Err(payload) => {
+            let message = payload.downcast_ref::<&str>().map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "panic".to_string());
+            set_last_error(message);
+            Wgpu01Result::Panic
+        },
+    }
+}
+
+fn fail(err: Error) -> (Wgpu01Result, String){
+    ((&err).into(), err.to_string())
+}
+
+unsafe fn canvas_mut<'a>(canvas: *mut Wgpu01Canvas) -> Result<&'a mut HeadlessCanvas, (Wgpu01Result, String)>{
+    canvas.as_mut()
+        .map(|canvas| &mut canvas.canvas)
+        .ok_or((Wgpu01Result::NullPointer, "canvas is null".to_string()))
+}
+
+///
+/// Message of the last failed call on this thread or null.
+/// Valid until the next failing call on this thread.
+///
+#[no_mangle]
+pub extern "C" fn wgpu01_last_error_message() -> *const c_char{
+    LAST_ERROR.with(|last| last.borrow().as_ref().map(|m| m.as_ptr()).unwrap_or(std::ptr::null()))
+}
+
+///
+/// Creates a canvas of width x height pixels with its own device.
+/// out is set to the canvas, it has to be freed with wgpu01_canvas_destroy.
+///
+#[no_mangle]
+pub unsafe extern "C" fn wgpu01_canvas_create(width: u32, height: u32, out: *mut *mut Wgpu01Canvas) -> Wgpu01Result{
+    guard(||{
+        if out.is_null(){
+            return Err((Wgpu01Result::NullPointer, "out is null".to_string()));
+        }
+        if width == 0 || height == 0{
+            return Err((Wgpu01Result::InvalidArgument, format!("Canvas size {}x{} is empty", width, height)));
+        }
+        let canvas = HeadlessCanvas::new([width, height]).map_err(fail)?;
+        *out = Box::into_raw(Box::new(Wgpu01Canvas{canvas}));
+        Ok(())
+    })
+}
+
+///
+/// Frees a canvas, null is ignored.
+///
+#[no_mangle]
+pub unsafe extern "C" fn wgpu01_canvas_destroy(canvas: *mut Wgpu01Canvas){
+    if !canvas.is_null(){
+        drop(Box::from_raw(canvas));
+    }
+}
+
+///
+/// Adds a layer from encoded image bytes (PNG, JPEG, ...) on top of the others.
+/// It is placed at its pixel size in the center of the canvas and blended with Add.
+/// out_index is set to the index of the layer if it is not null.
+///
+#[no_mangle]
+pub unsafe extern "C" fn wgpu01_canvas_add_layer(canvas: *mut Wgpu01Canvas, data: *const u8, len: usize, out_index: *mut u32) -> Wgpu01Result{
+    guard(||{
+        let canvas = canvas_mut(canvas)?;
+        if data.is_null(){
+            return Err((Wgpu01Result::NullPointer, "data is null".to_string()));
+        }
+        let bytes = std::slice::from_raw_parts(data, len);
+        let img = image::load_from_memory(bytes).map_err(|err| fail(err.into()))?;
+        let index = canvas.push_image(&img, "Add").map_err(fail)?;
+        if let Some(out_index) = out_index.as_mut(){
+            *out_index = index as u32;
+        }
+        Ok(())
+    })
+}
+
+///
+/// Queues a stroke segment of the default brush from p0 to p1 on a layer.
+/// It is painted by the next wgpu01_canvas_composite.
+///
+#[no_mangle]
+pub unsafe extern "C" fn wgpu01_canvas_queue_stroke(canvas: *mut Wgpu01Canvas, layer: u32, p0: Wgpu01StrokePoint, p1: Wgpu01StrokePoint) -> Wgpu01Result{
+    guard(||{
+        let canvas = canvas_mut(canvas)?;
+        canvas.queue_stroke(layer as usize, "default", brush::StrokeData{
+            pos0: [p0.x, p0.y],
+            pos1: [p1.x, p1.y],
+            p0: p0.pressure,
+            p1: p1.pressure,
+        }).map_err(fail)
+    })
+}
+
+///
+/// Applies queued strokes and composites the layers.
+///
+#[no_mangle]
+pub unsafe extern "C" fn wgpu01_canvas_composite(canvas: *mut Wgpu01Canvas) -> Wgpu01Result{
+    guard(||{
+        canvas_mut(canvas)?.render().map_err(fail)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wgpu01_canvas_size(canvas: *mut Wgpu01Canvas, width: *mut u32, height: *mut u32) -> Wgpu01Result{
+    guard(||{
+        let size = canvas_mut(canvas)?.size();
+        if let Some(width) = width.as_mut(){
+            *width = size[0];
+        }
+        if let Some(height) = height.as_mut(){
+            *height = size[1];
+        }
+        Ok(())
+    })
+}
+
+///
+/// Reads back the last composite as sRGB RGBA8, rows from top to bottom without padding.
+/// len has to be width * height * 4.
+///
+#[no_mangle]
+pub unsafe extern "C" fn wgpu01_canvas_read_rgba(canvas: *mut Wgpu01Canvas, out: *mut u8, len: usize) -> Wgpu01Result{
+    guard(||{
+        let canvas = canvas_mut(canvas)?;
+        if out.is_null(){
+            return Err((Wgpu01Result::NullPointer, "out is null".to_string()));
+        }
+        let size = canvas.size();
+        let expected = size[0] as usize * size[1] as usize * 4;
+        if len != expected{
+            return Err((Wgpu01Result::InvalidArgument, format!("Buffer of {} bytes does not fit {} bytes", len, expected)));
+        }
+        let img = canvas.read_rgba8().map_err(fail)?;
+        std::slice::from_raw_parts_mut(out, len).copy_from_slice(img.as_raw());
+        Ok(())
+    })
+}
@@ -1,3 +1,6 @@
+use wgpu01::error::{Error, Result};
+use wgpu01::texture;
+use std::sync::Arc;
 #[allow(unused)]
 use winit::{
     event::*,
@@ -12,17 +15,18 @@ use winit::{
 };
 
 pub trait State{
-    fn new(fstate: &mut FrameworkState) -> Self;
-    fn render(&mut self, fstate: &mut FrameworkState, control_flow: &mut ControlFlow) -> Result<(), wgpu::SurfaceError>{Ok(())}
+    fn render(&mut self, fstate: &mut FrameworkState, control_flow: &mut ControlFlow) -> Result<()>{Ok(())}
     fn input(&mut self, event: &WindowEvent) -> bool{false}
     fn cursor_moved(&mut self, fstate: &mut FrameworkState, device_id: &winit::event::DeviceId, position: &winit::dpi::PhysicalPosition<f64>){}
+    fn mouse_input(&mut self, _fstate: &mut FrameworkState, _device_id: &winit::event::DeviceId, _state: &ElementState, _button: &MouseButton){}
+    fn touch(&mut self, _fstate: &mut FrameworkState, _touch: &Touch){}
     fn device_event(&mut self, fstate: &mut FrameworkState, device_id: &winit::event::DeviceId, device_event: &DeviceEvent){}
     fn resize(&mut self, fstate: &mut FrameworkState, new_size: winit::dpi::PhysicalSize<u32>){}
 }
 
 pub struct FrameworkState{
     pub surface: wgpu::Surface,
-    pub device: wgpu::Device,
+    pub device: Arc<wgpu::Device>,
     pub queue: wgpu::Queue,
     pub config: wgpu::SurfaceConfiguration,
     pub size: winit::dpi::PhysicalSize<u32>,
@@ -30,7 +34,7 @@ pub struct FrameworkState{
 }
 
 impl FrameworkState{
-    pub async fn new(window: Window) -> Self{
+    pub async fn new(window: Window) -> Result<Self>{
         let size = window.inner_size();
 
         let instance = wgpu::Instance::new(wgpu::Backends::all());
@@ -41,32 +45,33 @@ impl FrameworkState{
                 compatible_surface: Some(&surface),
                 force_fallback_adapter: false,
             },
-        ).await.unwrap();
+        ).await.ok_or(Error::NoAdapter)?;
         let (device, queue) = adapter.request_device(
             &wgpu::DeviceDescriptor{
-                features: wgpu::Features::empty(),
+                // Rgba32Float layers are only filterable with adapter specific format features.
+                features: texture::device_features(&adapter),
                 limits: wgpu::Limits::default(),
                 label: None,
             },
             None,
-        ).await.unwrap();
+        ).await?;
         let config = wgpu::SurfaceConfiguration{
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface.get_preferred_format(&adapter).unwrap(),
+            format: surface.get_preferred_format(&adapter).ok_or(Error::NoAdapter)?,
             width: size.width,
             height: size.height,
             present_mode: wgpu::PresentMode::Fifo,
         };
         surface.configure(&device, &config);
 
-        Self{
+        Ok(Self{
             surface,
-            device,
+            device: Arc::new(device),
             queue,
             config,
             size,
             window,
-        }
+        })
     }
 
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>){
@@ -87,23 +92,26 @@ pub struct Framework<S: State>{
 
 impl<S: 'static +  State> Framework<S>{
 
-    pub fn new() -> Self{
+    ///
+    /// Opens the window, the state is created by init once the device exists.
+    ///
+    pub fn new(init: impl FnOnce(&mut FrameworkState) -> Result<S>) -> crate::Result<Self>{
         env_logger::init();
 
         let event_loop = EventLoop::new();
         let window = WindowBuilder::new()
             .with_inner_size(winit::dpi::LogicalSize::new(1000, 600))
-            .build(&event_loop).unwrap();
+            .build(&event_loop)?;
 
-        let mut fstate = pollster::block_on(FrameworkState::new(window));
+        let mut fstate = pollster::block_on(FrameworkState::new(window))?;
 
-        let state = S::new(&mut fstate);
+        let state = init(&mut fstate)?;
 
-        Self{
+        Ok(Self{
             fstate,
             state,
             event_loop,
-        }
+        })
     }
 
     pub fn run(mut self){
@@ -127,6 +135,12 @@ impl<S: 'static +  State> Framework<S>{
                         WindowEvent::CursorMoved{device_id, position, ..} => {
                             self.state.cursor_moved(&mut self.fstate, device_id, position);
                         }
+                        WindowEvent::MouseInput{device_id, state, button, ..} => {
+                            self.state.mouse_input(&mut self.fstate, device_id, state, button);
+                        }
+                        WindowEvent::Touch(touch) => {
+                            self.state.touch(&mut self.fstate, touch);
+                        }
                         _ => {},
                     }
                 },
@@ -135,10 +149,13 @@ impl<S: 'static +  State> Framework<S>{
                     match self.state.render(&mut self.fstate, control_flow){
                         Ok(_) => {}
 
-                        Err(wgpu::SurfaceError::Lost) => self.fstate.resize(self.fstate.size),
-                        Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
+                        Err(Error::Surface(wgpu::SurfaceError::Lost)) => self.fstate.resize(self.fstate.size),
+                        Err(Error::Surface(wgpu::SurfaceError::OutOfMemory)) | Err(Error::DeviceLost) => {
+                            eprintln!("Exiting, the device is unusable");
+                            *control_flow = ControlFlow::Exit;
+                        },
 
-                        Err(e) => eprintln!("{:?}", e),
+                        Err(e) => eprintln!("{}", e),
                     }
                 },
                 Event::DeviceEvent{device_id, event} => {
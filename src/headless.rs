@@ -0,0 +1,165 @@
+use crate::blendop;
+use crate::brush;
+use crate::cache;
+use crate::canvas;
+use crate::color;
+use crate::error::{Error, Result};
+use crate::layer;
+use crate::texture;
+use image::GenericImageView;
+use std::sync::Arc;
+
+///
+/// A Canvas that is rendered into a texture instead of a window, so it can be read back.
+///
+/// Owns its device and everything needed to add layers and paint on them. The composite is
+/// tonemapped into an sRGB 8 bit target like the one of the window.
+///
+pub struct HeadlessCanvas{
+    pub device: Arc<wgpu::Device>,
+    pub queue: wgpu::Queue,
+    pub cache: Arc<cache::PipelineCache>,
+    pub blendops: Arc<blendop::BlendOpManager>,
+    pub brushops: Arc<brush::BrushOpManager>,
+    pub canvas: canvas::Canvas,
+    target: texture::Texture,
+    size: [u32; 2],
+}
+
+impl HeadlessCanvas{
+    /// format layers are painted and composited in.
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+    /// format the composite is read back in.
+    pub const TARGET_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    ///
+    /// Requests a device without a surface.
+    /// force_fallback_adapter selects a software adapter, if the platform has one.
+    ///
+    pub async fn request_device(force_fallback_adapter: bool) -> Result<(wgpu::Device, wgpu::Queue)>{
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let adapter = instance.request_adapter(
+            &wgpu::RequestAdapterOptions{
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: None,
+                force_fallback_adapter,
+            },
+        ).await.ok_or(Error::NoAdapter)?;
+        let (device, queue) = adapter.request_device(
+            &wgpu::DeviceDescriptor{
+                // Rgba32Float layers are only filterable with adapter specific format features.
+                features: texture::device_features(&adapter),
+                limits: wgpu::Limits::default(),
+                label: Some("Headless Device"),
+            },
+            None,
+        ).await?;
+        Ok((device, queue))
+    }
+
+    pub fn new(size: [u32; 2]) -> Result<Self>{
+        let (device, queue) = pollster::block_on(Self::request_device(false))?;
+        Self::with_device(device, queue, size)
+    }
+
+    ///
+    /// A canvas on an existing device, with a PipelineCache that only lives in memory.
+    ///
+    pub fn with_device(device: wgpu::Device, queue: wgpu::Queue, size: [u32; 2]) -> Result<Self>{
+        let device = Arc::new(device);
+        let cache = Arc::new(cache::PipelineCache::new(device.clone()));
+        let blendops = Arc::new(blendop::BlendOpManager::new(&device, &queue, &cache, &Self::FORMAT)?);
+        let brushops = Arc::new(brush::BrushOpManager::new(&device, &queue, &cache, Self::FORMAT, 1)?);
+
+        let canvas = canvas::Canvas::new(&device, &queue, &cache, Self::FORMAT, Self::TARGET_FORMAT, color::ColorSpace::Linear, blendops.clone(), size)?;
+
+        let target = texture::Texture::new(&device, size, Some("Headless Target"), Self::TARGET_FORMAT, color::ColorSpace::Srgb, false, texture::Texture::DEFAULT_USAGE);
+
+        Ok(Self{
+            device,
+            queue,
+            cache,
+            blendops,
+            brushops,
+            canvas,
+            target,
+            size,
+        })
+    }
+
+    pub fn size(&self) -> [u32; 2]{
+        self.size
+    }
+
+    ///
+    /// Draws layers and strokes with sample_count samples, 1 or 4.
+    /// Replaces the BrushOps, strokes queued before are drawn with the old sample count and fail.
+    ///
+    pub fn set_sample_count(&mut self, sample_count: u32) -> Result<()>{
+        self.brushops = Arc::new(brush::BrushOpManager::new(&self.device, &self.queue, &self.cache, Self::FORMAT, sample_count)?);
+        self.canvas.set_sample_count(&self.device, &self.cache, sample_count)
+    }
+
+    pub fn sample_count(&self) -> u32{
+        self.canvas.sample_count()
+    }
+
+    ///
+    /// Adds a layer showing img at its pixel size in the center of the canvas.
+    /// Returns the index of the layer.
+    ///
+    pub fn push_image(&mut self, img: &image::DynamicImage, blendop: &str) -> Result<usize>{
+        let mut layer = layer::Layer::from_image(&self.device, &self.queue, &self.cache, &Self::FORMAT, self.canvas.color_space(), self.blendops.arc_to(blendop)?, img)?;
+        layer.fit_to_pixels([img.width(), img.height()]);
+
+        self.canvas.push_layer(&self.device, &self.cache, layer)?;
+        Ok(self.canvas.layers.len() - 1)
+    }
+
+    ///
+    /// Adds an empty layer covering the canvas.
+    ///
+    pub fn push_empty(&mut self, blendop: &str) -> Result<usize>{
+        let mut layer = layer::Layer::new(&self.device, &self.queue, &self.cache, &Self::FORMAT, self.canvas.color_space(), self.size, self.blendops.arc_to(blendop)?)?;
+        layer.fit_to_pixels(self.size);
+
+        self.canvas.push_layer(&self.device, &self.cache, layer)?;
+        Ok(self.canvas.layers.len() - 1)
+    }
+
+    ///
+    /// Queues a stroke of brushop on a layer, it is applied by the next render.
+    ///
+    pub fn queue_stroke(&mut self, layer: usize, brushop: &str, data: brush::StrokeData) -> Result<()>{
+        let brushop = self.brushops.arc_to(brushop)?;
+        let layer = self.canvas.layers.get(layer)
+            .ok_or(Error::MissingLayer(layer))?;
+        layer.borrow_mut().queue_stroke(brush::Stroke::new(brushop, data));
+        Ok(())
+    }
+
+    ///
+    /// Applies the queued strokes and composites the canvas into the target.
+    ///
+    pub fn render(&mut self) -> Result<()>{
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor{
+            label: Some("Headless Encoder"),
+        });
+
+        self.canvas.draw(&self.device, &mut encoder, &self.queue, &self.target.view, self.size)?;
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+        Ok(())
+    }
+
+    ///
+    /// The last render, blocks until it finished.
+    ///
+    pub fn read_rgba8(&self) -> Result<image::RgbaImage>{
+        self.target.read_to_image(&self.device, &self.queue)
+    }
+
+    pub fn target(&self) -> &texture::Texture{
+        &self.target
+    }
+}
@@ -12,8 +12,14 @@ use crate::vert::Vert2;
 use crate::pipeline;
 use crate::binding::GetBindGroupLayout;
 use crate::brush;
-use anyhow::*;
-use std::collections::VecDeque;
+use crate::cache;
+use crate::color;
+use crate::mipmap;
+use crate::tile;
+use crate::rect;
+use crate::binding::GetBindGroup;
+use crate::error::{Error, Result};
+use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
 use crate::binding;
 use std::borrow::Cow;
@@ -26,76 +32,79 @@ pub struct TransformsUniform{
     pub proj: [[f32; 4]; 4],
 }
 
+///
+/// A layer stores its content in a tile::TiledTexture.
+/// Tiles are only allocated where the layer has content, so large layers stay cheap.
+///
 pub struct Layer{
-    drawable: Box<dyn UpdatedDrawable<ModelTransforms>>,
-    tex_src: texture::Texture,
-    // a temporary texture for painting to and from the layer.
-    tex_target: texture::Texture,
-    render_pipeline: pipeline::RenderPipeline,
+    drawable: mesh::Mesh<Vert2>,
+    tiles: tile::TiledTexture,
+    render_pipeline: Arc<pipeline::RenderPipeline>,
 
     pub translation: glm::Vec3,
     pub scale: glm::Vec3,
     pub rotation: glm::Vec4,
-    uniform_buffer: buffer::UniformBindGroup<ModelTransforms>,
 
     blendop: Arc<BlendOp>,
 
     strokes: VecDeque<brush::Stroke>,
+    stroke_batch: brush::StrokeBatch,
+
+    sample_count: u32,
+    // strokes are drawn into these and resolved into the tiles if sample_count is larger than 1.
+    stroke_loader: Option<mipmap::MultisampleLoader>,
+    stroke_targets: Vec<texture::MultisampledTexture>,
+
+    // model and proj of the last draw, anything else means the whole layer has to be redrawn.
+    drawn: Option<(glm::Mat4, glm::Mat4)>,
+
+    // tiles are mipmapped so zoomed out layers don't alias.
+    mipmap_generator: mipmap::MipmapGenerator,
 }
 
 impl Layer{
 
-    pub fn load(device: &wgpu::Device, queue: &wgpu::Queue, format: &wgpu::TextureFormat, blendop: Arc<BlendOp>, path: &str) -> Result<Self>{
-        let texture = texture::Texture::load_from_path(
-            device,
-            queue,
-            path,
-            None,
-            *format,
-        )?;
-
-        let tex_tmp = texture::Texture::new_black(
-            texture.size,
-            device,
-            queue,
-            None,
-            *format
-        )?;
-
-        let drawable = Box::new(Model::<Vert2>::new(device, &Vert2::QUAD_VERTS, &Vert2::QUAD_IDXS)?);
-
-        let model = glm::Mat4::identity();
-        let view = glm::Mat4::identity();
-        let proj = glm::Mat4::identity();
-        let model_transforms = ModelTransforms{
-            model: model.into(),
-            view: view.into(),
-            proj: proj.into()
-        };
-        let uniform_buffer = buffer::UniformBindGroup::new_with_data(device, &model_transforms);
+    pub fn load(device: &wgpu::Device, queue: &wgpu::Queue, cache: &cache::PipelineCache, format: &wgpu::TextureFormat, color_space: color::ColorSpace, blendop: Arc<BlendOp>, path: &str) -> Result<Self>{
+        let img = image::open(path)?;
+        Self::from_image(device, queue, cache, format, color_space, blendop, &img)
+    }
 
-        let render_pipeline_layout = pipeline::PipelineLayoutBuilder::new()
-            .push_named("transforms", &uniform_buffer.get_bind_group_layout())
-            .push_named("src", &texture.bind_group_layout)
-            .create(device, None);
+    pub fn from_image(device: &wgpu::Device, queue: &wgpu::Queue, cache: &cache::PipelineCache, format: &wgpu::TextureFormat, color_space: color::ColorSpace, blendop: Arc<BlendOp>, img: &image::DynamicImage) -> Result<Self>{
+        let tiles = tile::TiledTexture::from_image(device, queue, img, *format, color_space)?;
 
-        let vertex_shader = pipeline::shader_with_shaderc(device, include_str!("shaders/vert_model.glsl"), shaderc::ShaderKind::Vertex, "main", Some("VertexShader"))?;
+        let mut layer = Self::with_tiles(device, cache, tiles, blendop)?;
 
-        let vertex_state = pipeline::VertexStateBuilder::new(&vertex_shader)
-            .push_named("model", drawable.vert_buffer_layout())
-            .set_entry_point("main")
-            .build();
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor{
+            label: Some("Mipmap Encoder"),
+        });
+        for (_, tile) in layer.tiles.tiles(){
+            layer.mipmap_generator.generate(device, &mut encoder, &tile.texture, &tile.mip_chain);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+
+        Ok(layer)
+    }
 
-        let fragment_shader = pipeline::shader_with_shaderc(device, include_str!("shaders/frag_forward.glsl"), shaderc::ShaderKind::Fragment, "main", Some("FragmentShader"))?;
+    ///
+    /// Creates an empty layer, no tiles are allocated until it is painted on.
+    ///
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, cache: &cache::PipelineCache, format: &wgpu::TextureFormat, color_space: color::ColorSpace, size: [u32; 2], blendop: Arc<BlendOp>) -> Result<Self>{
+        let tiles = tile::TiledTexture::new(size, *format, color_space);
 
-        let fragment_state = pipeline::FragmentStateBuilder::new(&fragment_shader)
-            .set_entry_point("main")
-            .push_target_replace(*format)
-            .build();
+        let mut layer = Self::with_tiles(device, cache, tiles, blendop)?;
+        layer.scale = glm::vec3(1000.0, 1000.0, 1000.0);
+
+        Ok(layer)
+    }
 
-        let render_pipeline = pipeline::RenderPipelineBuilder::new(vertex_state, fragment_state)
-            .set_layout(&render_pipeline_layout)
-            .build(device);
+    fn with_tiles(device: &wgpu::Device, cache: &cache::PipelineCache, tiles: tile::TiledTexture, blendop: Arc<BlendOp>) -> Result<Self>{
+        texture::check_filterable(device, tiles.format)?;
+
+        let drawable = mesh::Mesh::<Vert2>::new(device, &Vert2::QUAD_VERTS, &Vert2::QUAD_IDXS)?;
+
+        let render_pipeline = Self::render_pipeline(cache, tiles.format, 1, drawable.vert_buffer_layout())?;
+
+        let mipmap_generator = mipmap::MipmapGenerator::new(device, cache, tiles.format)?;
 
         let translation = glm::vec3(0.0, 0.0, 0.0);
         let scale = glm::vec3(1.0, 1.0, 1.0);
@@ -104,127 +113,193 @@ impl Layer{
         let strokes: VecDeque<brush::Stroke> = VecDeque::new();
 
         Ok(Self{
-            tex_src: texture,
-            render_pipeline,
             drawable,
-            uniform_buffer,
+            tiles,
+            render_pipeline,
             blendop,
             translation,
             scale,
             rotation,
-            tex_target: tex_tmp,
             strokes,
+            stroke_batch: brush::StrokeBatch::new(device),
+            sample_count: 1,
+            stroke_loader: None,
+            stroke_targets: Vec::new(),
+            drawn: None,
+            mipmap_generator,
+        })
+    }
+
+    ///
+    /// The pipeline is the same for every layer of a format so it is shared through the cache.
+    ///
+    fn render_pipeline(cache: &cache::PipelineCache, format: wgpu::TextureFormat, sample_count: u32, vert_buffer_layout: wgpu::VertexBufferLayout<'static>) -> Result<Arc<pipeline::RenderPipeline>>{
+        cache.render_pipeline(&format!("Layer {:?} x{}", format, sample_count), |device|{
+            let bind_group_layouts = pipeline::create_named_bind_group_layouts(device, Self::bind_group_layouts());
+
+            let render_pipeline_layout = pipeline::PipelineLayoutBuilder::new()
+                .push_all_named(&bind_group_layouts)
+                .create(device, None);
+
+            let vertex_shader = cache.shader_with_shaderc(include_str!("shaders/vert_model.glsl"), shaderc::ShaderKind::Vertex, "main", &[], Some("VertexShader"))?;
+
+            let vertex_state = pipeline::VertexStateBuilder::new(&vertex_shader)
+                .push_named("model", vert_buffer_layout)
+                .set_entry_point("main")
+                .build();
+
+            let fragment_shader = cache.shader_with_shaderc(include_str!("shaders/frag_forward.glsl"), shaderc::ShaderKind::Fragment, "main", &[], Some("FragmentShader"))?;
+
+            let fragment_state = pipeline::FragmentStateBuilder::new(&fragment_shader)
+                .set_entry_point("main")
+                .push_target_replace(format)
+                .build();
+
+            pipeline::RenderPipelineBuilder::new(vertex_state, fragment_state)
+                .set_layout(&render_pipeline_layout)
+                .set_sample_count(sample_count)
+                .build(device)
         })
     }
 
-    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, format: &wgpu::TextureFormat, size: [u32; 2], blendop: Arc<BlendOp>) -> Result<Self>{
-        let texture = texture::Texture::new_black(
-            size,
-            &device,
-            &queue,
-            None,
-            *format,
-        )?;
-        let tex_tmp = texture::Texture::new_black(
-            texture.size,
-            device,
-            queue,
-            None,
-            *format
-        )?;
-
-        let drawable = Box::new(Model::<Vert2>::new(device, &Vert2::QUAD_VERTS, &Vert2::QUAD_IDXS)?);
-
-        let model = glm::Mat4::identity();
-        let view = glm::Mat4::identity();
-        let proj = glm::Mat4::identity();
-        let model_transforms = ModelTransforms{
-            model: model.into(),
-            view: view.into(),
-            proj: proj.into()
+    ///
+    /// Rebuilds the pipeline so the layer can be drawn into a MultisampledTexture with sample_count.
+    /// Strokes are painted with the same sample count, their BrushOps have to be created with it.
+    ///
+    pub fn set_sample_count(&mut self, device: &wgpu::Device, cache: &cache::PipelineCache, sample_count: u32) -> Result<()>{
+        self.render_pipeline = Self::render_pipeline(cache, self.tiles.format, sample_count, self.drawable.vert_buffer_layout())?;
+        self.stroke_loader = if sample_count > 1{
+            Some(mipmap::MultisampleLoader::new(device, cache, self.tiles.format, sample_count)?)
+        }
+        else{
+            None
         };
-        let uniform_buffer = buffer::UniformBindGroup::new_with_data(device, &model_transforms);
+        self.stroke_targets.clear();
+        self.sample_count = sample_count;
+        Ok(())
+    }
 
-        let render_pipeline_layout = pipeline::PipelineLayoutBuilder::new()
-            .push_named("transforms", &uniform_buffer.get_bind_group_layout())
-            .push_named("src", &texture.bind_group_layout)
-            .create(device, None);
+    ///
+    /// Index of the multisampled stroke target for tiles of size, created on first use.
+    ///
+    fn stroke_target(&mut self, device: &wgpu::Device, size: [u32; 2]) -> usize{
+        match self.stroke_targets.iter().position(|target| target.size == size){
+            Some(index) => index,
+            None => {
+                self.stroke_targets.push(texture::MultisampledTexture::new(device, size, Some("Stroke MSAA"), self.tiles.format, self.sample_count));
+                self.stroke_targets.len() - 1
+            },
+        }
+    }
 
-        let vertex_shader = pipeline::shader_with_shaderc(device, include_str!("shaders/vert_model.glsl"), shaderc::ShaderKind::Vertex, "main", Some("VertexShader"))?;
+    pub fn sample_count(&self) -> u32{
+        self.sample_count
+    }
 
-        let vertex_state = pipeline::VertexStateBuilder::new(&vertex_shader)
-            .push_named("model", drawable.vert_buffer_layout())
-            .set_entry_point("main")
-            .build();
+    pub fn bind_group_layouts() -> pipeline::NamedBindGroupLayouts{
+        vec![
+            ("transforms", buffer::UniformBindGroup::<ModelTransforms>::bind_group_layout_builder()),
+            ("src", texture::Texture::bind_group_layout_builder()),
+        ]
+    }
 
-        let fragment_shader = pipeline::shader_with_shaderc(device, include_str!("shaders/frag_forward.glsl"), shaderc::ShaderKind::Fragment, "main", Some("FragmentShader"))?;
+    pub fn size(&self) -> [u32; 2]{
+        self.tiles.size
+    }
 
-        let fragment_state = pipeline::FragmentStateBuilder::new(&fragment_shader)
-            .set_entry_point("main")
-            .build();
+    pub fn tiles(&self) -> &tile::TiledTexture{
+        &self.tiles
+    }
 
-        let render_pipeline = program::new(
-            &device,
-            *format,
-            &render_pipeline_layout,
-            &vertex_state,
-            &fragment_state,
-        )?;
+    ///
+    /// Model and projection matrix of the layer for a view of view_size.
+    ///
+    fn transforms(&self, view_size: [u32; 2]) -> (glm::Mat4, glm::Mat4){
+        transforms(&self.translation, &self.scale, &self.rotation, view_size)
+    }
 
-        let translation = glm::vec3(0.0, 0.0, 0.0);
-        let scale = glm::vec3(1000.0, 1000.0, 1000.0);
-        let rotation = glm::vec4(0.0, 0.0, 1.0, 0.0);
+    ///
+    /// Transforms of a tile.
+    /// The shaders apply proj before model, so the tile is placed in the layer by proj.
+    ///
+    fn tile_transforms(&self, coord: [u32; 2], model: &glm::Mat4, proj: &glm::Mat4) -> ModelTransforms{
+        ModelTransforms{
+            model: (*model).into(),
+            view: glm::Mat4::identity().into(),
+            proj: (proj * self.tiles.tile_local(coord)).into(),
+        }
+    }
 
-        let strokes: VecDeque<brush::Stroke> = VecDeque::new();
+    ///
+    /// Min and max corner of a tile in background uv, see f_bguv in vert_brush.glsl.
+    ///
+    fn tile_bounds(&self, transforms: &ModelTransforms) -> [[f32; 2]; 2]{
+        let model = glm::Mat4::from(transforms.model);
+        let proj = glm::Mat4::from(transforms.proj);
+        transform_uv_bounds([[0.0, 0.0], [1.0, 1.0]], &(model * proj))
+    }
 
-        Ok(Self{
-            tex_src: texture,
-            render_pipeline,
-            drawable,
-            uniform_buffer,
-            blendop,
-            translation,
-            scale,
-            rotation,
-            tex_target: tex_tmp,
-            strokes,
-        })
+    ///
+    /// The region of a view of view_size that changes when the layer is drawn next.
+    /// That is the area of the queued strokes or everything if the layer moved.
+    ///
+    pub fn dirty_rect(&self, view_size: [u32; 2]) -> Option<rect::Rect>{
+        let (model, proj) = self.transforms(view_size);
+        if self.drawn != Some((model, proj)){
+            return Some(rect::Rect::full(view_size));
+        }
+
+        self.strokes.iter()
+            .map(|stroke| rect::Rect::from_uv_bounds(stroke.bounds(), view_size))
+            .reduce(|a, b| a.union(&b))
     }
 
-    pub fn draw(&mut self, encoder: &mut wgpu::CommandEncoder, queue: &wgpu::Queue, dst: &wgpu::TextureView, dst_size: [u32; 2]) -> Result<()>{
-        //self.blendop.draw(encoder, dst, &self.texture.bind_group, &itex.bind_group)?;
+    ///
+    /// Draws the layer into dst.
+    /// If msaa is given the layer is rendered into it and resolved into dst.
+    /// Only region of dst is drawn to, tiles outside of it are skipped.
+    ///
+    pub fn draw(&mut self, encoder: &mut wgpu::CommandEncoder, queue: &wgpu::Queue, dst: &wgpu::TextureView, msaa: Option<&texture::MultisampledTexture>, dst_size: [u32; 2], region: &rect::Rect) -> Result<()>{
+        let sample_count = msaa.map(|msaa| msaa.sample_count).unwrap_or(1);
+        if sample_count != self.sample_count{
+            return Err(Error::SampleCountMismatch{layer: self.sample_count, target: sample_count});
+        }
 
+        let (model, proj) = self.transforms(dst_size);
 
-        let axisv = glm::vec3(self.rotation.x, self.rotation.y, self.rotation.z);
-        let axis: nalgebra::Unit<glm::Vec3> = nalgebra::Unit::new_normalize(axisv);
-        let rot = glm::Mat4::from_axis_angle(&axis, self.rotation[3]);
-        let scale = glm::Mat4::new_nonuniform_scaling(&self.scale);
-        let translation = glm::Mat4::new_translation(&self.translation);
+        self.drawn = Some((model, proj));
 
-        let size_vec = glm::vec2(dst_size[0] as f32, dst_size[1] as f32);
-        let size_vec_norm = size_vec;
-        let proj: [[f32; 4]; 4] = glm::ortho(-size_vec_norm[0]/2.0, size_vec_norm[0]/2.0, size_vec_norm[1]/2.0, -size_vec_norm[1]/2.0, -1.0, 1.0).into();
-        let view: [[f32; 4]; 4] = glm::Mat4::identity().into();
+        let tile_transforms: Vec<([u32; 2], ModelTransforms)> = self.tiles.tiles()
+            .map(|(coord, _)| (*coord, self.tile_transforms(*coord, &model, &proj)))
+            .filter(|(_, transforms)| !rect::Rect::from_uv_bounds(self.tile_bounds(transforms), dst_size).intersect(region).is_empty())
+            .collect();
+        for (coord, tile) in self.tiles.tiles_mut(){
+            if let Some((_, transforms)) = tile_transforms.iter().find(|(c, _)| c == coord){
+                tile.transforms.update(queue, transforms);
+            }
+        }
 
-        let model = ((translation * scale) * rot).into();
-        let model_transforms = ModelTransforms{
-            model,
-            view,
-            proj,
+        let color_attachment = match msaa{
+            Some(msaa) => msaa.view.color_attachment_clear_resolve(dst),
+            None => dst.color_attachment_clear(),
         };
 
-        self.uniform_buffer.update(queue, &model_transforms);
-
         let mut render_pass = pipeline::RenderPassBuilder::new()
-            .push_color_attachment(dst.color_attachment_clear())
+            .push_color_attachment(color_attachment)
             .begin(encoder, None);
+        render_pass.set_scissor_rect(region);
         let mut render_pass_pipeline = render_pass.set_pipeline(&self.render_pipeline);
 
-        render_pass_pipeline.set_bind_group("src", &self.tex_src.bind_group, &[]);
+        for (coord, tile) in self.tiles.tiles(){
+            if !tile_transforms.iter().any(|(c, _)| c == coord){
+                continue;
+            }
 
-        self.drawable.update(queue, &model_transforms);
+            render_pass_pipeline.try_set_bind_group("transforms", tile.transforms.get_bind_group(), &[])?;
+            render_pass_pipeline.try_set_bind_group("src", &tile.texture.bind_group, &[])?;
 
-        self.drawable.draw(&mut render_pass_pipeline);
+            self.drawable.draw(&mut render_pass_pipeline);
+        }
 
         Ok(())
     }
@@ -233,48 +308,107 @@ impl Layer{
         self.blendop.clone()
     }
 
+    ///
+    /// The Canvas can't see this change, it has to be invalidated afterwards.
+    ///
+    pub fn set_blendop(&mut self, blendop: Arc<BlendOp>){
+        self.blendop = blendop;
+    }
+
+    ///
+    /// Scales the layer so it covers size pixels of the view, the quad spans -1 to 1.
+    ///
+    pub fn fit_to_pixels(&mut self, size: [u32; 2]){
+        self.scale = glm::vec3(size[0] as f32 / 2.0, size[1] as f32 / 2.0, 1.0);
+    }
+
     pub fn queue_stroke(&mut self, stroke: brush::Stroke){
         self.strokes.push_back(stroke);
     }
 
-    pub fn apply_strokes(&mut self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, prev: &wgpu::BindGroup, view: [u32; 2]) -> Result<()>{
-
-        let axisv = glm::vec3(self.rotation.x, self.rotation.y, self.rotation.z);
-        let axis: nalgebra::Unit<glm::Vec3> = nalgebra::Unit::new_normalize(axisv);
-        let rot = glm::Mat4::from_axis_angle(&axis, self.rotation[3]);
-        let scale = glm::Mat4::new_nonuniform_scaling(&self.scale);
-        let translation = glm::Mat4::new_translation(&self.translation);
-
-        let size_vec = glm::vec2(view[0] as f32, view[1] as f32);
-        let size_vec_norm = size_vec;
-        let proj_mat4 = glm::ortho(-size_vec_norm[0]/2.0, size_vec_norm[0]/2.0, size_vec_norm[1]/2.0, -size_vec_norm[1]/2.0, -1.0, 1.0);
-        let proj: [[f32; 4]; 4] = proj_mat4.into();
-        let view: [[f32; 4]; 4] = glm::Mat4::identity().into();
-
-        let model_mat4 = (translation * scale) * rot;
-        let model = model_mat4.into();
-        let model_transforms = ModelTransforms{
-            model,
-            view,
-            proj,
-        };
+    ///
+    /// Draws the queued strokes into the tiles they overlap, allocating tiles as needed.
+    ///
+    pub fn apply_strokes(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, prev: &wgpu::BindGroup, view: [u32; 2]) -> Result<()>{
+        if self.strokes.is_empty(){
+            return Ok(());
+        }
+        if let Some(stroke) = self.strokes.iter().find(|stroke| stroke.brushop().sample_count() != self.sample_count){
+            return Err(Error::SampleCountMismatch{layer: self.sample_count, target: stroke.brushop().sample_count()});
+        }
 
+        let (model, proj) = self.transforms(view);
+        // Maps background uv to the uv of the layer texture.
+        let to_layer = glm::inverse(&(model * proj));
+
+        // The strokes painting to every touched tile and the texels of the tile they cover.
+        let mut touched: HashMap<[u32; 2], (Vec<usize>, rect::Rect)> = HashMap::new();
+        for (i, stroke) in self.strokes.iter().enumerate(){
+            let layer_bounds = transform_uv_bounds(stroke.bounds(), &to_layer);
+
+            for coord in self.tiles.coords_in(layer_bounds){
+                let (_, tile_size) = self.tiles.tile_rect(coord);
+                let region = rect::Rect::from_uv_bounds(self.tiles.tile_uv_bounds(coord, layer_bounds), tile_size);
+                if region.is_empty(){
+                    continue;
+                }
+
+                let (strokes, tile_region) = touched.entry(coord).or_insert_with(|| (Vec::new(), region));
+                strokes.push(i);
+                *tile_region = tile_region.union(&region);
+            }
+        }
 
-        for stroke in &mut self.strokes{
-            {
-                let mut render_pass = pipeline::RenderPassBuilder::new()
-                    .push_color_attachment(self.tex_target.view.color_attachment_clear())
-                    .begin(encoder, None);
+        // Every tile gets one group of instances, so it is drawn to in one render pass.
+        self.stroke_batch.clear();
+        let mut tile_groups = Vec::with_capacity(touched.len());
+        for (coord, (strokes, region)) in &touched{
+            self.tiles.alloc(device, encoder, *coord);
+            let tile_transforms = self.tile_transforms(*coord, &model, &proj);
+            if let Some(tile) = self.tiles.get_mut(*coord){
+                tile.transforms.update(queue, &tile_transforms);
+            }
 
-                stroke.update_transforms(queue, &model_transforms);
+            let group = self.stroke_batch.push_group(strokes.iter().map(|i| &self.strokes[*i]));
+            tile_groups.push((*coord, *region, group));
+        }
+        self.stroke_batch.upload(device, queue);
+
+        let targets: Vec<Option<usize>> = tile_groups.iter()
+            .map(|(coord, _, _)| match self.stroke_loader{
+                Some(_) => Some(self.stroke_target(device, self.tiles.tile_rect(*coord).1)),
+                None => None,
+            })
+            .collect();
+
+        for ((coord, region, group), target) in tile_groups.iter().zip(targets){
+            let tile = self.tiles.get(*coord).ok_or(Error::MissingTile(*coord))?;
+
+            // Multisampled strokes are drawn on a copy of the tile that is resolved back into it.
+            let color_attachment = match (&self.stroke_loader, target){
+                (Some(loader), Some(target)) => {
+                    let target = &self.stroke_targets[target];
+                    loader.load(encoder, &tile.mip_chain, target)?;
+                    target.view.color_attachment_load_resolve(tile.mip_chain.view(0))
+                },
+                _ => tile.mip_chain.view(0).color_attachment_load(),
+            };
+
+            let mut render_pass = pipeline::RenderPassBuilder::new()
+                .push_color_attachment(color_attachment)
+                .begin(encoder, Some("Strokes"));
+            render_pass.set_scissor_rect(region);
+
+            self.stroke_batch.draw_group(&mut render_pass, StrokeBindGroups{
+                background: prev,
+                transforms: &tile.transforms,
+            }, group.clone())?;
+        }
 
-                stroke.draw_data(&mut render_pass, StrokeBindGroups{
-                    background: prev,
-                    tex_self: &self.tex_src.bind_group,
-                });
+        for coord in touched.keys(){
+            if let Some(tile) = self.tiles.get(*coord){
+                self.mipmap_generator.generate(device, encoder, &tile.texture, &tile.mip_chain);
             }
-
-            self.tex_target.copy_all_to(&mut self.tex_src, encoder);
         }
 
         self.strokes.clear();
@@ -282,3 +416,42 @@ impl Layer{
         Ok(())
     }
 }
+
+///
+/// Model and projection matrix of a layer for a view of view_size.
+/// The shaders apply proj before model, so translation is in clip space.
+/// rotation is an axis and an angle in radians.
+///
+pub fn transforms(translation: &glm::Vec3, scale: &glm::Vec3, rotation: &glm::Vec4, view_size: [u32; 2]) -> (glm::Mat4, glm::Mat4){
+    let axisv = glm::vec3(rotation.x, rotation.y, rotation.z);
+    let axis: nalgebra::Unit<glm::Vec3> = nalgebra::Unit::new_normalize(axisv);
+    let rot = glm::Mat4::from_axis_angle(&axis, rotation[3]);
+    let scale = glm::Mat4::new_nonuniform_scaling(scale);
+    let translation = glm::Mat4::new_translation(translation);
+
+    let size_vec = glm::vec2(view_size[0] as f32, view_size[1] as f32);
+    let size_vec_norm = size_vec;
+    let proj = glm::ortho(-size_vec_norm[0]/2.0, size_vec_norm[0]/2.0, size_vec_norm[1]/2.0, -size_vec_norm[1]/2.0, -1.0, 1.0);
+
+    let model = (translation * scale) * rot;
+
+    (model, proj)
+}
+
+///
+/// Maps the min and max corner of uv bounds through m, which works on positions in [-1, 1].
+/// The result is the bounding box of the transformed corners.
+///
+fn transform_uv_bounds(bounds: [[f32; 2]; 2], m: &glm::Mat4) -> [[f32; 2]; 2]{
+    let mut result = [[f32::MAX; 2], [f32::MIN; 2]];
+    for corner in [[0, 0], [1, 0], [1, 1], [0, 1]]{
+        let pos = glm::vec4(bounds[corner[0]][0] * 2.0 - 1.0, bounds[corner[1]][1] * 2.0 - 1.0, 0.0, 1.0);
+        let p = m * pos;
+        for i in 0..2{
+            let uv = (p[i] + 1.0) / 2.0;
+            result[0][i] = result[0][i].min(uv);
+            result[1][i] = result[1][i].max(uv);
+        }
+    }
+    result
+}
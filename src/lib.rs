@@ -0,0 +1,66 @@
+//!
+//! Layered painting engine on top of wgpu.
+//!
+//! A Canvas holds Layers that are composited with BlendOps and painted on by queueing Strokes
+//! of a BrushOp. Pipelines and shaders are shared through a PipelineCache.
+//!
+//! The modules below the high level types are public as well so the engine can be extended
+//! with own pipelines, see pipeline and binding for the builders.
+//!
+//! ReferenceCanvas renders like Canvas on the CPU, it is the oracle of the rendering tests.
+//!
+
+#[macro_use]
+extern crate more_asserts;
+
+extern crate nalgebra_glm as glm;
+extern crate naga;
+
+pub mod binding;
+pub mod blendop;
+pub mod brush;
+pub mod buffer;
+pub mod cache;
+pub mod canvas;
+pub mod color;
+pub mod error;
+pub mod headless;
+pub mod layer;
+pub mod mesh;
+pub mod pipeline;
+pub mod rect;
+pub mod render_target;
+pub mod script;
+pub mod stroke_log;
+pub mod texture;
+pub mod vert;
+
+mod algebra;
+mod ffi;
+mod mipmap;
+mod program;
+mod reference;
+mod render_graph;
+mod surface;
+mod texture_pool;
+mod tile;
+mod tonemap;
+mod transfer;
+mod uniform;
+
+#[cfg(test)]
+mod shader_tests;
+
+pub use blendop::{BlendOp, BlendOpManager};
+pub use brush::{BrushOp, BrushOpManager, Stroke, StrokeData};
+pub use cache::PipelineCache;
+pub use canvas::Canvas;
+pub use color::ColorSpace;
+pub use error::{Error, Result};
+pub use headless::HeadlessCanvas;
+pub use layer::Layer;
+pub use rect::Rect;
+pub use reference::{ReferenceCanvas, ReferenceLayer};
+pub use render_graph::GraphError;
+pub use texture::Texture;
+pub use transfer::{Download, TextureData, Transfers, Upload};
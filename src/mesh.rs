@@ -7,7 +7,7 @@ use bytemuck;
 use cgmath::*;
 #[allow(unused)]
 use wgpu::util::DeviceExt;
-use anyhow::*;
+use crate::error::Result;
 use std::marker::PhantomData;
 
 ///
@@ -63,12 +63,20 @@ impl<V: Vert> Mesh<V>{
     }
 }
 
-impl<V: Vert> Drawable for Mesh<V>{
-    fn draw<'rp>(&'rp self, render_pass: &'_ mut pipeline::RenderPassPipeline<'rp, '_>) {
-
+impl<V: Vert> Mesh<V>{
+    ///
+    /// Draws the mesh once for every instance, instance buffers have to be set before.
+    ///
+    pub fn draw_instanced<'rp>(&'rp self, render_pass: &'_ mut pipeline::RenderPassPipeline<'rp, '_>, instances: std::ops::Range<u32>){
         render_pass.set_vertex_buffer("model", self.vertex_buffer.slice(..));
         render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-        render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+        render_pass.draw_indexed(0..self.num_indices, 0, instances);
+    }
+}
+
+impl<V: Vert> Drawable for Mesh<V>{
+    fn draw<'rp>(&'rp self, render_pass: &'_ mut pipeline::RenderPassPipeline<'rp, '_>) {
+        self.draw_instanced(render_pass, 0..1);
     }
     fn vert_buffer_layout(&self) -> wgpu::VertexBufferLayout<'static>{
         V::buffer_layout()
@@ -110,11 +118,23 @@ impl<V: Vert> Model<V>{
     }
 }
 
-impl<V: Vert> Drawable for Model<V>{
-    fn draw<'rp>(&'rp self, render_pass: &'_ mut pipeline::RenderPassPipeline<'rp, '_>) {
-        render_pass.set_bind_group("transforms", &self.uniform_buffer.get_bind_group(), &[]);
+impl<V: Vert> Model<V>{
+    ///
+    /// Fails instead of panicking if the pipeline has no "transforms" bind group.
+    ///
+    pub fn try_draw<'rp>(&'rp self, render_pass: &'_ mut pipeline::RenderPassPipeline<'rp, '_>) -> Result<(), pipeline::BindingError>{
+        render_pass.try_set_bind_group("transforms", &self.uniform_buffer.get_bind_group(), &[])?;
 
         self.mesh.draw(render_pass);
+        Ok(())
+    }
+}
+
+impl<V: Vert> Drawable for Model<V>{
+    fn draw<'rp>(&'rp self, render_pass: &'_ mut pipeline::RenderPassPipeline<'rp, '_>) {
+        if let Err(err) = self.try_draw(render_pass){
+            panic!("{}", err);
+        }
     }
 
     fn vert_buffer_layout(&self) -> wgpu::VertexBufferLayout<'static> {
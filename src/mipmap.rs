@@ -0,0 +1,245 @@
+use crate::binding;
+use crate::cache;
+use crate::mesh;
+use crate::mesh::Drawable;
+use crate::pipeline;
+use crate::program;
+use crate::render_target::ColorAttachment;
+use crate::texture;
+use crate::vert;
+use crate::error::Result;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+///
+/// Views of every mip level of a texture and a bind group sampling level 0.
+///
+/// Created once per texture so generating the mip chain does not need a device.
+///
+pub struct MipChain{
+    views: Vec<wgpu::TextureView>,
+    // The GL backend can only sample views starting at level 0, so level 0 is sampled through a
+    // view of the whole texture with the level of detail clamped to 0.
+    level0: wgpu::BindGroup,
+}
+
+impl MipChain{
+    pub fn new(device: &wgpu::Device, texture: &texture::Texture) -> Self{
+        let bind_group_layout = texture::Texture::bind_group_layout_builder()
+            .create(device, Some("MipChain BindGroupLayout"));
+
+        let views: Vec<wgpu::TextureView> = (0..texture.mip_level_count)
+            .map(|level| texture.texture.create_view(&wgpu::TextureViewDescriptor{
+                label: Some("MipChain View"),
+                format: Some(texture.format),
+                base_mip_level: level,
+                mip_level_count: std::num::NonZeroU32::new(1),
+                ..Default::default()
+            }))
+            .collect();
+
+        let level0 = binding::BindGroupBuilder::new(&bind_group_layout)
+            .texture(&texture.view)
+            .sampler(&level0_sampler(device))
+            .create(device, Some("MipChain BindGroup"));
+
+        Self{
+            views,
+            level0,
+        }
+    }
+
+    ///
+    /// View of a single level, level 0 can be rendered to directly.
+    ///
+    pub fn view(&self, level: usize) -> &wgpu::TextureView{
+        &self.views[level]
+    }
+}
+
+///
+/// Copies a texture into a render target of the same size with sample_count samples.
+///
+fn blit_pipeline(cache: &cache::PipelineCache, format: wgpu::TextureFormat, sample_count: u32, vert_buffer_layout: wgpu::VertexBufferLayout<'static>) -> Result<Arc<pipeline::RenderPipeline>>{
+    cache.render_pipeline(&format!("Blit {:?} x{}", format, sample_count), |device|{
+        let bind_group_layouts = pipeline::create_named_bind_group_layouts(device, MipmapGenerator::bind_group_layouts());
+
+        let render_pipeline_layout = pipeline::PipelineLayoutBuilder::new()
+            .push_all_named(&bind_group_layouts)
+            .create(device, None);
+
+        let vert_shader = cache.shader_with_shaderc(include_str!("shaders/vert_screen.glsl"), shaderc::ShaderKind::Vertex, "main", &[], Some("VertexShader"))?;
+        let frag_shader = cache.shader_with_shaderc(include_str!("shaders/frag_blit.glsl"), shaderc::ShaderKind::Fragment, "main", &[], Some("FragmentShader"))?;
+
+        let vertex_state = pipeline::VertexStateBuilder::new(&vert_shader)
+            .push_named("model", vert_buffer_layout)
+            .set_entry_point("main")
+            .build();
+
+        let fragment_state = pipeline::FragmentStateBuilder::new(&frag_shader)
+            .set_entry_point("main")
+            .build();
+
+        program::new_multisampled(
+            device,
+            format,
+            sample_count,
+            &render_pipeline_layout,
+            &vertex_state,
+            &fragment_state,
+        )
+    })
+}
+
+///
+/// A texture of the size of one mip level, sampled to render the next one.
+///
+struct ScratchLevel{
+    texture: texture::Texture,
+    // samples with a linear min filter, which the texture's own sampler doesn't have.
+    bind_group: wgpu::BindGroup,
+    size: wgpu::Extent3d,
+}
+
+///
+/// Samples level 0 bilinearly, also for textures with a mip chain.
+///
+fn level0_sampler(device: &wgpu::Device) -> wgpu::Sampler{
+    device.create_sampler(&wgpu::SamplerDescriptor{
+        label: Some("Mipmap Sampler"),
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        lod_max_clamp: 0.0,
+        ..Default::default()
+    })
+}
+
+///
+/// Generates mip chains on the GPU by rendering each level from the one above it.
+///
+/// Levels are rendered into scratch textures and copied into the chain, so no level is sampled
+/// through a view of a single level, which the GL backend can't do.
+///
+pub struct MipmapGenerator{
+    drawable: mesh::Mesh<vert::Vert2>,
+    render_pipeline: Arc<pipeline::RenderPipeline>,
+    // levels 1 and below, by the size of level 0.
+    scratch: HashMap<[u32; 2], Vec<ScratchLevel>>,
+}
+
+impl MipmapGenerator{
+    pub fn new(device: &wgpu::Device, cache: &cache::PipelineCache, format: wgpu::TextureFormat) -> Result<Self>{
+        let drawable = mesh::Mesh::<vert::Vert2>::new(device, &vert::Vert2::QUAD_VERTS, &vert::Vert2::QUAD_IDXS)?;
+
+        let render_pipeline = blit_pipeline(cache, format, 1, drawable.vert_buffer_layout())?;
+
+        Ok(Self{
+            drawable,
+            render_pipeline,
+            scratch: HashMap::new(),
+        })
+    }
+
+    fn scratch_levels(device: &wgpu::Device, texture: &texture::Texture) -> Vec<ScratchLevel>{
+        let bind_group_layout = texture::Texture::bind_group_layout_builder()
+            .create(device, Some("Mipmap Scratch BindGroupLayout"));
+        let sampler = level0_sampler(device);
+
+        (1..texture.mip_level_count)
+            .map(|level| {
+                let size = [(texture.size[0] >> level).max(1), (texture.size[1] >> level).max(1)];
+                let scratch = texture::Texture::new(device, size, Some("Mipmap Scratch"), texture.format, texture.color_space, false, texture::Texture::DEFAULT_USAGE);
+                let bind_group = binding::BindGroupBuilder::new(&bind_group_layout)
+                    .texture(&scratch.view)
+                    .sampler(&sampler)
+                    .create(device, Some("Mipmap Scratch BindGroup"));
+                ScratchLevel{
+                    texture: scratch,
+                    bind_group,
+                    size: wgpu::Extent3d{
+                        width: size[0],
+                        height: size[1],
+                        depth_or_array_layers: 1,
+                    },
+                }
+            })
+            .collect()
+    }
+
+    pub fn bind_group_layouts() -> pipeline::NamedBindGroupLayouts{
+        vec![
+            ("src", texture::Texture::bind_group_layout_builder()),
+        ]
+    }
+
+    ///
+    /// Regenerates all levels below level 0 of texture, mip_chain has to be the one of texture.
+    ///
+    pub fn generate(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, texture: &texture::Texture, mip_chain: &MipChain){
+        let levels = self.scratch.entry(texture.size)
+            .or_insert_with(|| Self::scratch_levels(device, texture));
+
+        let mut src = &mip_chain.level0;
+        for (i, level) in levels.iter().enumerate(){
+            {
+                let mut render_pass = pipeline::RenderPassBuilder::new()
+                    .push_color_attachment(level.texture.view.color_attachment_clear())
+                    .begin(encoder, Some("Mipmap"));
+                let mut render_pass_pipeline = render_pass.set_pipeline(&self.render_pipeline);
+
+                render_pass_pipeline.set_bind_group("src", src, &[]);
+
+                self.drawable.draw(&mut render_pass_pipeline);
+            }
+
+            encoder.copy_texture_to_texture(
+                level.texture.texture.as_image_copy(),
+                wgpu::ImageCopyTexture{
+                    texture: &texture.texture,
+                    mip_level: i as u32 + 1,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                level.size,
+            );
+            src = &level.bind_group;
+        }
+    }
+}
+
+///
+/// Copies level 0 of a mip chain into a MultisampledTexture, so it can be drawn on multisampled
+/// and resolved back into level 0.
+///
+pub struct MultisampleLoader{
+    drawable: mesh::Mesh<vert::Vert2>,
+    render_pipeline: Arc<pipeline::RenderPipeline>,
+}
+
+impl MultisampleLoader{
+    pub fn new(device: &wgpu::Device, cache: &cache::PipelineCache, format: wgpu::TextureFormat, sample_count: u32) -> Result<Self>{
+        let drawable = mesh::Mesh::<vert::Vert2>::new(device, &vert::Vert2::QUAD_VERTS, &vert::Vert2::QUAD_IDXS)?;
+
+        let render_pipeline = blit_pipeline(cache, format, sample_count, drawable.vert_buffer_layout())?;
+
+        Ok(Self{
+            drawable,
+            render_pipeline,
+        })
+    }
+
+    ///
+    /// Every sample of dst gets the texel of level 0 it lies in, dst has to be of the same size.
+    ///
+    pub fn load(&self, encoder: &mut wgpu::CommandEncoder, mip_chain: &MipChain, dst: &texture::MultisampledTexture) -> Result<()>{
+        let mut render_pass = pipeline::RenderPassBuilder::new()
+            .push_color_attachment(dst.view.color_attachment_clear())
+            .begin(encoder, Some("Multisample Load"));
+        let mut render_pass_pipeline = render_pass.set_pipeline(&self.render_pipeline);
+
+        render_pass_pipeline.try_set_bind_group("src", &mip_chain.level0, &[])?;
+
+        self.drawable.draw(&mut render_pass_pipeline);
+        Ok(())
+    }
+}
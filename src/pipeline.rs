@@ -7,8 +7,9 @@ use std::io::Read;
 use std::str;
 use std::sync::Arc;
 use crate::binding;
+use crate::rect;
 use std::borrow::Cow;
-use anyhow::*;
+use crate::error::{Error, Result};
 use core::ops::Range;
 use core::num::NonZeroU32;
 use naga;
@@ -143,10 +144,87 @@ impl <'vsb> VertexStateBuilder<'vsb>{
     }
 }
 
+///
+/// A name that was looked up on a pipeline which does not declare it.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BindingError{
+    BindGroup{pipeline: String, name: String},
+    VertexBuffer{pipeline: String, name: String},
+}
+
+impl std::fmt::Display for BindingError{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result{
+        match self{
+            Self::BindGroup{pipeline, name} => write!(f, "Pipeline {:?} has no bind group named {:?}", pipeline, name),
+            Self::VertexBuffer{pipeline, name} => write!(f, "Pipeline {:?} has no vertex buffer named {:?}", pipeline, name),
+        }
+    }
+}
+
+impl std::error::Error for BindingError{}
+
+///
+/// The names the code drawing with a pipeline is going to set.
+/// Declared on a RenderPipelineBuilder so missing names are reported when the pipeline is built
+/// instead of in the middle of a frame.
+///
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PipelineSchema<'s>{
+    pub bind_groups: &'s [&'s str],
+    pub vertex_buffers: &'s [&'s str],
+}
+
 pub struct RenderPipeline{
     pub pipeline: wgpu::RenderPipeline,
     pub bind_group_names: Arc<HashMap<String, usize>>,
     pub vertex_buffer_names: Arc<HashMap<String, usize>>,
+    /// used in errors, the PipelineCache sets it to the key if it is None.
+    pub label: Option<String>,
+}
+
+impl RenderPipeline{
+    pub fn name(&self) -> &str{
+        self.label.as_deref().unwrap_or("unlabeled")
+    }
+
+    pub fn bind_group_index(&self, name: &str) -> Result<u32, BindingError>{
+        self.bind_group_names.get(name)
+            .map(|index| *index as u32)
+            .ok_or_else(|| BindingError::BindGroup{pipeline: self.name().to_string(), name: name.to_string()})
+    }
+
+    pub fn vertex_buffer_index(&self, name: &str) -> Result<u32, BindingError>{
+        self.vertex_buffer_names.get(name)
+            .map(|index| *index as u32)
+            .ok_or_else(|| BindingError::VertexBuffer{pipeline: self.name().to_string(), name: name.to_string()})
+    }
+
+    ///
+    /// Checks that every name of the schema can be set on the pipeline.
+    ///
+    pub fn check_schema(&self, schema: &PipelineSchema) -> Result<(), BindingError>{
+        for name in schema.bind_groups{
+            self.bind_group_index(name)?;
+        }
+        for name in schema.vertex_buffers{
+            self.vertex_buffer_index(name)?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// Bind group layouts with the names they are pushed under, in the order of their sets.
+///
+/// Describes a pipeline layout without needing a device.
+///
+pub type NamedBindGroupLayouts = Vec<(&'static str, binding::BindGroupLayoutBuilder)>;
+
+pub fn create_named_bind_group_layouts(device: &wgpu::Device, layouts: NamedBindGroupLayouts) -> Vec<(&'static str, binding::BindGroupLayoutWithDesc)>{
+    layouts.into_iter()
+        .map(|(name, builder)| (name, builder.create(device, Some(name))))
+        .collect()
 }
 
 pub struct PipelineLayout{
@@ -185,6 +263,13 @@ impl<'l> PipelineLayoutBuilder<'l>{
         self
     }
 
+    pub fn push_all_named(mut self, bind_group_layouts: &'l [(&str, binding::BindGroupLayoutWithDesc)]) -> Self{
+        for (name, bind_group_layout) in bind_group_layouts{
+            self = self.push_named(name, bind_group_layout);
+        }
+        self
+    }
+
     pub fn push_push_constant_ranges(mut self, push_constant_ranges: wgpu::PushConstantRange) -> Self{
         self.push_constant_ranges.push(push_constant_ranges);
         self
@@ -214,11 +299,21 @@ pub struct RenderPassPipeline<'rp, 'rpr>{
 }
 
 impl<'rp, 'rpr> RenderPassPipeline<'rp, 'rpr>{
+    ///
+    /// Panics if the pipeline has no bind group called name, see try_set_bind_group.
+    ///
     pub fn set_bind_group(&mut self, name: &str, bind_group: &'rp wgpu::BindGroup, offsets: &'rp [wgpu::DynamicOffset]){
+        if let Err(err) = self.try_set_bind_group(name, bind_group, offsets){
+            panic!("{}", err);
+        }
+    }
+
+    pub fn try_set_bind_group(&mut self, name: &str, bind_group: &'rp wgpu::BindGroup, offsets: &'rp [wgpu::DynamicOffset]) -> Result<(), BindingError>{
         self.render_pass.render_pass.set_bind_group(
-            self.pipeline.bind_group_names[name] as u32, 
+            self.pipeline.bind_group_index(name)?,
             bind_group, offsets
         );
+        Ok(())
     }
 
     pub fn set_bind_groups(&mut self, bind_groups: &[&'rp wgpu::BindGroup]){
@@ -231,11 +326,21 @@ impl<'rp, 'rpr> RenderPassPipeline<'rp, 'rpr>{
         }
     }
 
+    ///
+    /// Panics if the pipeline has no vertex buffer called name, see try_set_vertex_buffer.
+    ///
     pub fn set_vertex_buffer(&mut self, name: &str, buffer_slice: wgpu::BufferSlice<'rp>){
+        if let Err(err) = self.try_set_vertex_buffer(name, buffer_slice){
+            panic!("{}", err);
+        }
+    }
+
+    pub fn try_set_vertex_buffer(&mut self, name: &str, buffer_slice: wgpu::BufferSlice<'rp>) -> Result<(), BindingError>{
         self.render_pass.render_pass.set_vertex_buffer(
-            self.pipeline.vertex_buffer_names[name] as u32, 
+            self.pipeline.vertex_buffer_index(name)?,
             buffer_slice
         );
+        Ok(())
     }
 
     pub fn set_index_buffer(&mut self, buffer_slice: wgpu::BufferSlice<'rp>, format: wgpu::IndexFormat){
@@ -243,6 +348,14 @@ impl<'rp, 'rpr> RenderPassPipeline<'rp, 'rpr>{
     }
 
 
+    pub fn set_stencil_reference(&mut self, reference: u32){
+        self.render_pass.render_pass.set_stencil_reference(reference);
+    }
+
+    pub fn set_scissor_rect(&mut self, rect: &rect::Rect){
+        self.render_pass.set_scissor_rect(rect);
+    }
+
     pub fn draw(&mut self, vertices: Range<u32>, instances: Range<u32>){
         self.render_pass.render_pass.draw(vertices, instances);
     }
@@ -281,6 +394,13 @@ impl<'rp> RenderPass<'rp>{
         }
     }
 
+    ///
+    /// Restricts drawing to rect, it has to lie inside the attachments.
+    ///
+    pub fn set_scissor_rect(&mut self, rect: &rect::Rect){
+        self.render_pass.set_scissor_rect(rect.min[0], rect.min[1], rect.width(), rect.height());
+    }
+
     /*
        #[inline]
        pub fn set_bind_group(&mut self, index: u32, bind_group: &'rp wgpu::BindGroup, offsets: &'rp [wgpu::DynamicOffset]){
@@ -291,12 +411,14 @@ impl<'rp> RenderPass<'rp>{
 
 pub struct RenderPassBuilder<'rp>{
     color_attachments: Vec<wgpu::RenderPassColorAttachment<'rp>>,
+    depth_stencil_attachment: Option<wgpu::RenderPassDepthStencilAttachment<'rp>>,
 }
 
 impl<'rp> RenderPassBuilder<'rp>{
     pub fn new() -> Self{
         Self{
             color_attachments: Vec::new(),
+            depth_stencil_attachment: None,
         }
     }
 
@@ -305,13 +427,17 @@ impl<'rp> RenderPassBuilder<'rp>{
         self
     }
 
-    // TODO: add depth_stencil_attachment
+    pub fn set_depth_stencil_attachment(mut self, depth_stencil_attachment: wgpu::RenderPassDepthStencilAttachment<'rp>) -> Self{
+        self.depth_stencil_attachment = Some(depth_stencil_attachment);
+        self
+    }
+
     pub fn begin(self, encoder: &'rp mut wgpu::CommandEncoder, label: Option<&'rp str>) -> RenderPass<'rp>{
         RenderPass{
             render_pass: encoder.begin_render_pass(&wgpu::RenderPassDescriptor{
                 label,
                 color_attachments: &self.color_attachments,
-                depth_stencil_attachment: None,
+                depth_stencil_attachment: self.depth_stencil_attachment,
             }),
         }
     }
@@ -322,20 +448,24 @@ pub fn shader_load(device: &wgpu::Device, path: &str, stage: naga::ShaderStage,
     let metadata = fs::metadata(path)?;
     let mut buffer = vec![0; metadata.len() as usize];
     f.read(&mut buffer)?;
-    let src = str::from_utf8(&buffer)?;
-
+    let src = str::from_utf8(&buffer)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
 
-    let extension = Path::new(path).extension().ok_or(anyhow!("No extension"))?;
+    let unsupported = |message: &str| Error::ShaderCompilation{
+        label: label.unwrap_or(path).to_string(),
+        message: message.to_string(),
+    };
 
+    let extension = Path::new(path).extension().ok_or_else(|| unsupported("No extension"))?;
 
-    let source = match extension.to_str().ok_or(anyhow!("string conversion"))?{
+    let source = match extension.to_str().ok_or_else(|| unsupported("Extension is not valid UTF-8"))?{
         "glsl" => wgpu::ShaderSource::Glsl{
             shader: Cow::from(src),
             stage,
             defines: naga::FastHashMap::default()
         },
         "wgsl" => wgpu::ShaderSource::Wgsl(Cow::from(src)),
-        _ => return Err(anyhow!("Unknown Extension")),
+        _ => return Err(unsupported("Unknown extension")),
     };
 
     Ok(device.create_shader_module(&wgpu::ShaderModuleDescriptor{
@@ -344,40 +474,100 @@ pub fn shader_load(device: &wgpu::Device, path: &str, stage: naga::ShaderStage,
     }))
 }
 
-pub fn shader_with_shaderc(device: &wgpu::Device, src: &str, kind: shaderc::ShaderKind, entry_point: &str, label: Option<&str>) -> Result<wgpu::ShaderModule>{
+///
+/// Describes the options set in compile_with_shaderc.
+/// Has to be changed together with them so cached SPIR-V is invalidated.
+///
+pub const SHADERC_OPTIONS: &str = "warnings_as_errors;vulkan;performance;debug_info";
+
+pub fn compile_with_shaderc(src: &str, kind: shaderc::ShaderKind, entry_point: &str, defines: &[(&str, Option<&str>)], label: Option<&str>) -> Result<Vec<u32>>{
+
+    let shader_error = |message: String| Error::ShaderCompilation{
+        label: label.unwrap_or("no_label").to_string(),
+        message,
+    };
 
-    let mut compiler = shaderc::Compiler::new().ok_or(anyhow!("error creating compiler"))?;
-    let mut options = shaderc::CompileOptions::new().ok_or(anyhow!("error creating shaderc options"))?;
+    let mut compiler = shaderc::Compiler::new().ok_or_else(|| shader_error("Failed to create the compiler".to_string()))?;
+    let mut options = shaderc::CompileOptions::new().ok_or_else(|| shader_error("Failed to create the compile options".to_string()))?;
 
     options.set_warnings_as_errors();
     options.set_target_env(shaderc::TargetEnv::Vulkan, 0);
     options.set_optimization_level(shaderc::OptimizationLevel::Performance);
     options.set_generate_debug_info();
 
-    let spirv = match label{
-        Some(label) => compiler.compile_into_spirv(src, kind, label, entry_point, None)?,
-        _ => compiler.compile_into_spirv(src, kind, "no_label", entry_point, None)?,
-    };
+    for (name, value) in defines{
+        options.add_macro_definition(name, *value);
+    }
+
+    let spirv = compiler.compile_into_spirv(src, kind, label.unwrap_or("no_label"), entry_point, Some(&options))
+        .map_err(|err| shader_error(err.to_string()))?;
 
-    let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor{
+    Ok(spirv.as_binary().to_vec())
+}
+
+pub fn shader_from_spirv(device: &wgpu::Device, spirv: &[u32], label: Option<&str>) -> wgpu::ShaderModule{
+    device.create_shader_module(&wgpu::ShaderModuleDescriptor{
         label,
-        source: wgpu::ShaderSource::SpirV(Cow::from(spirv.as_binary()))
-    });
+        source: wgpu::ShaderSource::SpirV(Cow::from(spirv))
+    })
+}
 
-    Ok(module)
+pub fn shader_with_shaderc(device: &wgpu::Device, src: &str, kind: shaderc::ShaderKind, entry_point: &str, label: Option<&str>) -> Result<wgpu::ShaderModule>{
+    let spirv = compile_with_shaderc(src, kind, entry_point, &[], label)?;
+
+    Ok(shader_from_spirv(device, &spirv, label))
 }
 
 pub struct RenderPipelineBuilder<'rpb>{
     label: Option<&'rpb str>,
+    schema: Option<PipelineSchema<'rpb>>,
     layout: Option<&'rpb PipelineLayout>,
     vertex: VertexState<'rpb>,
     fragment: FragmentState<'rpb>,
     primitive: wgpu::PrimitiveState,
-    depth_stencil: Option<wgpu::DepthStencilState>,
+    depth_stencil: DepthStencilSettings,
     multisample: wgpu::MultisampleState,
     multiview: Option<NonZeroU32>,
 }
 
+///
+/// Depth and stencil settings of a RenderPipelineBuilder, applied to the state of the depth
+/// stencil format when the pipeline is built.
+///
+#[derive(Clone, Debug, Default)]
+struct DepthStencilSettings{
+    state: Option<wgpu::DepthStencilState>,
+    depth_compare: Option<(wgpu::CompareFunction, bool)>,
+    stencil_faces: Option<(wgpu::StencilFaceState, wgpu::StencilFaceState)>,
+    stencil_masks: Option<(u32, u32)>,
+}
+
+impl DepthStencilSettings{
+    ///
+    /// The depth stencil state with the depth and stencil settings applied.
+    ///
+    fn resolve(&self) -> Result<Option<wgpu::DepthStencilState>>{
+        let mut depth_stencil = match self.state.clone(){
+            Some(depth_stencil) => depth_stencil,
+            None if self.depth_compare.is_none() && self.stencil_faces.is_none() && self.stencil_masks.is_none() => return Ok(None),
+            None => return Err(Error::NoDepthStencilFormat),
+        };
+        if let Some((depth_compare, depth_write_enabled)) = self.depth_compare{
+            depth_stencil.depth_compare = depth_compare;
+            depth_stencil.depth_write_enabled = depth_write_enabled;
+        }
+        if let Some((front, back)) = self.stencil_faces{
+            depth_stencil.stencil.front = front;
+            depth_stencil.stencil.back = back;
+        }
+        if let Some((read_mask, write_mask)) = self.stencil_masks{
+            depth_stencil.stencil.read_mask = read_mask;
+            depth_stencil.stencil.write_mask = write_mask;
+        }
+        Ok(Some(depth_stencil))
+    }
+}
+
 impl<'rpb> RenderPipelineBuilder<'rpb>{
 
     const DEFAULT_ENTRY_POINT: &'static str = "main";
@@ -394,7 +584,7 @@ impl<'rpb> RenderPipelineBuilder<'rpb>{
             unclipped_depth: false,
             conservative: false,
         };
-        let depth_stencil = None;
+        let depth_stencil = DepthStencilSettings::default();
         let multisample = wgpu::MultisampleState{
             count: 1,
             mask: !0,
@@ -403,6 +593,7 @@ impl<'rpb> RenderPipelineBuilder<'rpb>{
         let multiview = None;
         Self{
             label,
+            schema: None,
             layout,
             vertex,
             fragment,
@@ -413,12 +604,106 @@ impl<'rpb> RenderPipelineBuilder<'rpb>{
         }
     }
 
+    pub fn set_label(mut self, label: &'rpb str) -> Self{
+        self.label = Some(label);
+        self
+    }
+
     pub fn set_layout(mut self, layout: &'rpb PipelineLayout) -> Self{
         self.layout = Some(layout);
         self
     }
 
-    pub fn build(self, device: &wgpu::Device) -> RenderPipeline{
+    ///
+    /// Declares the names that will be set on the pipeline, checked by try_build.
+    ///
+    pub fn set_schema(mut self, schema: PipelineSchema<'rpb>) -> Self{
+        self.schema = Some(schema);
+        self
+    }
+
+    ///
+    /// Has to match the sample count of the attachments the pipeline renders to.
+    ///
+    pub fn set_sample_count(mut self, count: u32) -> Self{
+        self.multisample.count = count;
+        self
+    }
+
+    pub fn set_depth_stencil(mut self, depth_stencil: wgpu::DepthStencilState) -> Self{
+        self.depth_stencil.state = Some(depth_stencil);
+        self
+    }
+
+    ///
+    /// Enables the depth stencil attachment with a format.
+    /// The depth test and the stencil test are disabled until set with the functions below.
+    ///
+    pub fn set_depth_stencil_format(mut self, format: wgpu::TextureFormat) -> Self{
+        self.depth_stencil.state = Some(wgpu::DepthStencilState{
+            format,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Always,
+            stencil: wgpu::StencilState{
+                front: wgpu::StencilFaceState::IGNORE,
+                back: wgpu::StencilFaceState::IGNORE,
+                read_mask: !0,
+                write_mask: !0,
+            },
+            bias: wgpu::DepthBiasState::default(),
+        });
+        self
+    }
+
+    ///
+    /// Needs a depth stencil format, build fails without one.
+    ///
+    pub fn set_depth_compare(mut self, depth_compare: wgpu::CompareFunction, depth_write_enabled: bool) -> Self{
+        self.depth_stencil.depth_compare = Some((depth_compare, depth_write_enabled));
+        self
+    }
+
+    ///
+    /// Sets the same stencil state for front and back faces.
+    ///
+    pub fn set_stencil_face(self, stencil_face: wgpu::StencilFaceState) -> Self{
+        self.set_stencil_faces(stencil_face, stencil_face)
+    }
+
+    ///
+    /// Needs a depth stencil format, build fails without one.
+    ///
+    pub fn set_stencil_faces(mut self, front: wgpu::StencilFaceState, back: wgpu::StencilFaceState) -> Self{
+        self.depth_stencil.stencil_faces = Some((front, back));
+        self
+    }
+
+    ///
+    /// Needs a depth stencil format, build fails without one.
+    ///
+    pub fn set_stencil_masks(mut self, read_mask: u32, write_mask: u32) -> Self{
+        self.depth_stencil.stencil_masks = Some((read_mask, write_mask));
+        self
+    }
+
+    ///
+    /// Like build but fails if a name of the schema is not declared by the layout or the vertex
+    /// state.
+    ///
+    pub fn try_build(self, device: &wgpu::Device) -> Result<RenderPipeline>{
+        let schema = self.schema;
+        let render_pipeline = self.build(device)?;
+        if let Some(schema) = schema{
+            render_pipeline.check_schema(&schema)?;
+        }
+        Ok(render_pipeline)
+    }
+
+    ///
+    /// Fails if depth or stencil state was set without a depth stencil format.
+    ///
+    pub fn build(self, device: &wgpu::Device) -> Result<RenderPipeline>{
+        let depth_stencil = self.depth_stencil.resolve()?;
 
         /*
         let layout = match self.layout{
@@ -453,15 +738,161 @@ impl<'rpb> RenderPipelineBuilder<'rpb>{
             },
             fragment: Some(fragment),
             primitive: self.primitive,
-            depth_stencil: self.depth_stencil,
+            depth_stencil,
             multisample: self.multisample,
             multiview: self.multiview,
         });
 
-        RenderPipeline{
+        Ok(RenderPipeline{
             pipeline: render_pipeline,
             bind_group_names: layout.names.clone(),
             vertex_buffer_names: self.vertex.vertex_buffer_names.clone(),
+            label: self.label.map(|label| label.to_string()),
+        })
+    }
+}
+
+
+pub struct ComputePipeline{
+    pub pipeline: wgpu::ComputePipeline,
+    pub bind_group_names: Arc<HashMap<String, usize>>,
+    /// used in errors.
+    pub label: Option<String>,
+}
+
+impl ComputePipeline{
+    pub fn name(&self) -> &str{
+        self.label.as_deref().unwrap_or("unlabeled")
+    }
+
+    pub fn bind_group_index(&self, name: &str) -> Result<u32, BindingError>{
+        self.bind_group_names.get(name)
+            .map(|index| *index as u32)
+            .ok_or_else(|| BindingError::BindGroup{pipeline: self.name().to_string(), name: name.to_string()})
+    }
+}
+
+pub struct ComputePipelineBuilder<'cpb>{
+    label: Option<&'cpb str>,
+    layout: Option<&'cpb PipelineLayout>,
+    shader: &'cpb wgpu::ShaderModule,
+    entry_point: &'cpb str,
+}
+
+impl<'cpb> ComputePipelineBuilder<'cpb>{
+    pub fn new(shader: &'cpb wgpu::ShaderModule) -> Self{
+        Self{
+            label: None,
+            layout: None,
+            shader,
+            entry_point: "main",
+        }
+    }
+
+    pub fn set_label(mut self, label: &'cpb str) -> Self{
+        self.label = Some(label);
+        self
+    }
+
+    pub fn set_entry_point(mut self, entry_point: &'cpb str) -> Self{
+        self.entry_point = entry_point;
+        self
+    }
+
+    pub fn set_layout(mut self, layout: &'cpb PipelineLayout) -> Self{
+        self.layout = Some(layout);
+        self
+    }
+
+    pub fn build(self, device: &wgpu::Device) -> ComputePipeline{
+        let layout = self.layout.expect("no layout provided");
+
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor{
+            label: self.label,
+            layout: Some(&layout.layout),
+            module: self.shader,
+            entry_point: self.entry_point,
+        });
+
+        ComputePipeline{
+            pipeline: compute_pipeline,
+            bind_group_names: layout.names.clone(),
+            label: self.label.map(|label| label.to_string()),
+        }
+    }
+}
+
+///
+/// A Compute Pass with a Pipeline.
+///
+/// Like RenderPassPipeline it references the pipeline to set bind groups by name.
+///
+pub struct ComputePassPipeline<'cp, 'cpr>{
+    pub compute_pass: &'cpr mut ComputePass<'cp>,
+    pub pipeline: &'cp ComputePipeline,
+}
+
+impl<'cp, 'cpr> ComputePassPipeline<'cp, 'cpr>{
+    ///
+    /// Fails if the pipeline has no bind group called name.
+    ///
+    pub fn set_bind_group(&mut self, name: &str, bind_group: &'cp wgpu::BindGroup, offsets: &'cp [wgpu::DynamicOffset]) -> Result<(), BindingError>{
+        self.compute_pass.compute_pass.set_bind_group(
+            self.pipeline.bind_group_index(name)?,
+            bind_group, offsets
+        );
+        Ok(())
+    }
+
+    pub fn set_bind_groups(&mut self, bind_groups: &[&'cp wgpu::BindGroup]){
+        for (i, bind_group) in bind_groups.iter().enumerate(){
+            self.compute_pass.compute_pass.set_bind_group(
+                i as u32,
+                bind_group,
+                &[],
+            )
+        }
+    }
+
+    pub fn dispatch(&mut self, x: u32, y: u32, z: u32){
+        self.compute_pass.compute_pass.dispatch(x, y, z);
+    }
+
+    ///
+    /// Dispatches enough workgroups to cover size, e.g. one invocation per pixel of a texture.
+    /// workgroup_size has to match the local_size declared in the shader.
+    ///
+    pub fn dispatch_2d(&mut self, size: [u32; 2], workgroup_size: [u32; 2]){
+        self.dispatch(
+            size[0].div_ceil(workgroup_size[0]),
+            size[1].div_ceil(workgroup_size[1]),
+            1
+        );
+    }
+
+    pub fn dispatch_indirect(&mut self, indirect_buffer: &'cp wgpu::Buffer, indirect_offset: wgpu::BufferAddress){
+        self.compute_pass.compute_pass.dispatch_indirect(indirect_buffer, indirect_offset);
+    }
+}
+
+pub struct ComputePass<'cp>{
+    pub compute_pass: wgpu::ComputePass<'cp>,
+}
+
+impl<'cp> ComputePass<'cp>{
+    pub fn begin(encoder: &'cp mut wgpu::CommandEncoder, label: Option<&'cp str>) -> Self{
+        Self{
+            compute_pass: encoder.begin_compute_pass(&wgpu::ComputePassDescriptor{
+                label,
+            }),
+        }
+    }
+
+    pub fn set_pipeline(&mut self, pipeline: &'cp ComputePipeline) -> ComputePassPipeline<'cp, '_>{
+        self.compute_pass.set_pipeline(&pipeline.pipeline);
+        ComputePassPipeline{
+            compute_pass: self,
+            pipeline,
         }
     }
 }
@@ -471,3 +902,54 @@ impl<'rpb> RenderPipelineBuilder<'rpb>{
 
 // TODO:
 // Counting RenderPass
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn stencil_replace() -> wgpu::StencilFaceState{
+        wgpu::StencilFaceState{
+            compare: wgpu::CompareFunction::Always,
+            fail_op: wgpu::StencilOperation::Keep,
+            depth_fail_op: wgpu::StencilOperation::Keep,
+            pass_op: wgpu::StencilOperation::Replace,
+        }
+    }
+
+    #[test]
+    fn depth_stencil_state_needs_a_format(){
+        assert!(matches!(DepthStencilSettings::default().resolve(), Ok(None)));
+
+        let without_format = [
+            DepthStencilSettings{depth_compare: Some((wgpu::CompareFunction::Less, true)), ..Default::default()},
+            DepthStencilSettings{stencil_faces: Some((stencil_replace(), stencil_replace())), ..Default::default()},
+            DepthStencilSettings{stencil_masks: Some((0xff, 0x0f)), ..Default::default()},
+        ];
+        for settings in without_format{
+            assert!(matches!(settings.resolve(), Err(Error::NoDepthStencilFormat)), "{:?}", settings);
+        }
+    }
+
+    #[test]
+    fn depth_stencil_settings_apply_to_the_format(){
+        let mut settings = DepthStencilSettings{
+            depth_compare: Some((wgpu::CompareFunction::Less, true)),
+            stencil_faces: Some((stencil_replace(), wgpu::StencilFaceState::IGNORE)),
+            stencil_masks: Some((0xff, 0x0f)),
+            ..Default::default()
+        };
+        settings.state = Some(wgpu::DepthStencilState{
+            format: wgpu::TextureFormat::Depth24PlusStencil8,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Always,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        });
+
+        let state = settings.resolve().unwrap().unwrap();
+        assert_eq!(state.format, wgpu::TextureFormat::Depth24PlusStencil8);
+        assert_eq!((state.depth_compare, state.depth_write_enabled), (wgpu::CompareFunction::Less, true));
+        assert_eq!((state.stencil.front, state.stencil.back), (stencil_replace(), wgpu::StencilFaceState::IGNORE));
+        assert_eq!((state.stencil.read_mask, state.stencil.write_mask), (0xff, 0x0f));
+    }
+}
@@ -2,8 +2,12 @@
 use crate::{binding, pipeline};
 
 
-use anyhow::*;
+use crate::error::Result;
 pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, pipeline_layout: &pipeline::PipelineLayout, vertex_stage: &pipeline::VertexState, fragment_stage: &pipeline::FragmentState) -> Result<pipeline::RenderPipeline>{
+    new_multisampled(device, format, 1, pipeline_layout, vertex_stage, fragment_stage)
+}
+
+pub fn new_multisampled(device: &wgpu::Device, format: wgpu::TextureFormat, sample_count: u32, pipeline_layout: &pipeline::PipelineLayout, vertex_stage: &pipeline::VertexState, fragment_stage: &pipeline::FragmentState) -> Result<pipeline::RenderPipeline>{
 
     /*
     let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor{
@@ -27,10 +31,8 @@ pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, pipeline_layout:
             //targets: &fragment_stage.color_target_states,
             targets: &[wgpu::ColorTargetState{
                 format,
-                blend: Some(wgpu::BlendState{
-                    color: wgpu::BlendComponent::REPLACE,
-                    alpha: wgpu::BlendComponent::REPLACE,
-                }),
+                // replaces the target, without blend state so non blendable formats work too.
+                blend: None,
                 write_mask: wgpu::ColorWrites::all(),
             }]
         }),
@@ -46,7 +48,7 @@ pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, pipeline_layout:
         },
         depth_stencil: None,
         multisample: wgpu::MultisampleState{
-            count: 1,
+            count: sample_count,
             mask: !0,
             alpha_to_coverage_enabled: false,
         },
@@ -58,6 +60,7 @@ pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, pipeline_layout:
             pipeline: render_pipeline,
             bind_group_names: pipeline_layout.names.clone(),
             vertex_buffer_names: vertex_stage.vertex_buffer_names.clone(),
+            label: Some("Render Pipeline".to_string()),
         }
     )
 }
@@ -0,0 +1,152 @@
+///
+/// Axis aligned rectangle in texels with max being exclusive.
+/// The origin is the top left like for scissor rects and texture copies.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect{
+    pub min: [u32; 2],
+    pub max: [u32; 2],
+}
+
+impl Rect{
+    pub fn new(min: [u32; 2], max: [u32; 2]) -> Self{
+        Self{
+            min,
+            max,
+        }
+    }
+
+    pub fn full(size: [u32; 2]) -> Self{
+        Self::new([0, 0], size)
+    }
+
+    ///
+    /// Converts min and max corner in uv to the texels of a target of size.
+    /// v points up like f_bguv in vert_brush.glsl, the result is rounded outwards and clamped to
+    /// the target.
+    ///
+    pub fn from_uv_bounds(bounds: [[f32; 2]; 2], size: [u32; 2]) -> Self{
+        let w = size[0] as f32;
+        let h = size[1] as f32;
+
+        let x0 = (bounds[0][0] * w).floor().clamp(0.0, w) as u32;
+        let x1 = (bounds[1][0] * w).ceil().clamp(0.0, w) as u32;
+        let y0 = ((1.0 - bounds[1][1]) * h).floor().clamp(0.0, h) as u32;
+        let y1 = ((1.0 - bounds[0][1]) * h).ceil().clamp(0.0, h) as u32;
+
+        Self::new([x0, y0], [x1.max(x0), y1.max(y0)])
+    }
+
+    pub fn width(&self) -> u32{
+        self.max[0] - self.min[0]
+    }
+
+    pub fn height(&self) -> u32{
+        self.max[1] - self.min[1]
+    }
+
+    pub fn size(&self) -> [u32; 2]{
+        [self.width(), self.height()]
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.width() == 0 || self.height() == 0
+    }
+
+    ///
+    /// Smallest rectangle containing both, empty rectangles are ignored.
+    ///
+    pub fn union(&self, other: &Self) -> Self{
+        if self.is_empty(){
+            return *other;
+        }
+        if other.is_empty(){
+            return *self;
+        }
+        Self::new(
+            [self.min[0].min(other.min[0]), self.min[1].min(other.min[1])],
+            [self.max[0].max(other.max[0]), self.max[1].max(other.max[1])],
+        )
+    }
+
+    ///
+    /// Overlap of both, empty if they don't overlap.
+    ///
+    pub fn intersect(&self, other: &Self) -> Self{
+        let min = [self.min[0].max(other.min[0]), self.min[1].max(other.min[1])];
+        let max = [self.max[0].min(other.max[0]), self.max[1].min(other.max[1])];
+        Self::new(min, [max[0].max(min[0]), max[1].max(min[1])])
+    }
+}
+
+///
+/// Union of two optional rectangles, None stands for nothing.
+///
+pub fn union(a: Option<Rect>, b: Option<Rect>) -> Option<Rect>{
+    match (a, b){
+        (Some(a), Some(b)) => Some(a.union(&b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn union_ignores_empty_rects(){
+        let a = Rect::new([1, 2], [4, 5]);
+        let b = Rect::new([3, 0], [6, 3]);
+        assert_eq!(a.union(&b), Rect::new([1, 0], [6, 5]));
+        assert_eq!(b.union(&a), a.union(&b));
+        assert_eq!(a.union(&a), a);
+
+        // an empty rect far away doesn't grow the union.
+        let empty = Rect::new([100, 100], [100, 200]);
+        assert!(empty.is_empty());
+        assert_eq!(a.union(&empty), a);
+        assert_eq!(empty.union(&a), a);
+
+        assert_eq!(union(Some(a), None), Some(a));
+        assert_eq!(union(None, Some(b)), Some(b));
+        assert_eq!(union(None, None), None);
+        assert_eq!(union(Some(a), Some(b)), Some(a.union(&b)));
+    }
+
+    #[test]
+    fn intersect_of_disjoint_rects_is_empty(){
+        let a = Rect::new([0, 0], [4, 4]);
+        assert_eq!(a.intersect(&Rect::new([2, 1], [8, 3])), Rect::new([2, 1], [4, 3]));
+        assert_eq!(a.intersect(&Rect::new([1, 1], [2, 2])), Rect::new([1, 1], [2, 2]));
+        assert_eq!(a.intersect(&a), a);
+
+        // touching edges share no texels since max is exclusive.
+        assert!(a.intersect(&Rect::new([4, 0], [8, 4])).is_empty());
+        let disjoint = a.intersect(&Rect::new([6, 6], [8, 8]));
+        assert!(disjoint.is_empty());
+        assert_eq!(disjoint.size(), [0, 0]);
+    }
+
+    #[test]
+    fn uv_bounds_are_flipped_and_rounded_outwards(){
+        let size = [100, 50];
+        assert_eq!(Rect::from_uv_bounds([[0.0, 0.0], [1.0, 1.0]], size), Rect::full(size));
+        // v points up, rows start at the top.
+        assert_eq!(Rect::from_uv_bounds([[0.0, 0.0], [0.5, 0.2]], size), Rect::new([0, 40], [50, 50]));
+        assert_eq!(Rect::from_uv_bounds([[0.101, 0.5], [0.209, 0.509]], size), Rect::new([10, 24], [21, 25]));
+    }
+
+    #[test]
+    fn uv_bounds_are_clamped(){
+        let size = [100, 50];
+        assert_eq!(Rect::from_uv_bounds([[-1.0, -1.0], [2.0, 2.0]], size), Rect::full(size));
+        assert_eq!(Rect::from_uv_bounds([[0.9, -0.5], [1.5, 0.1]], size), Rect::new([90, 45], [100, 50]));
+        // bounds outside of the target or inverted ones give empty rects.
+        assert!(Rect::from_uv_bounds([[1.5, 0.0], [2.0, 1.0]], size).is_empty());
+        assert!(Rect::from_uv_bounds([[0.0, -2.0], [1.0, -1.0]], size).is_empty());
+        assert!(Rect::from_uv_bounds([[0.6, 0.0], [0.4, 1.0]], size).is_empty());
+        // a degenerate point still covers the texel it's in.
+        assert_eq!(Rect::from_uv_bounds([[0.505, 0.51], [0.505, 0.51]], size), Rect::new([50, 24], [51, 25]));
+    }
+}
@@ -0,0 +1,499 @@
+//!
+//! CPU reference renderer, the same math as the GPU paths without a device.
+//!
+//! It follows what the shaders and pipelines do rather than what they were meant to do, so it
+//! can serve as an oracle for the GPU output:
+//!
+//! * Layers are placed with layer::transforms, like Layer::draw, and sampled trilinearly from a
+//!   box filtered mip chain.
+//! * Strokes use the falloff of frag_brush01.glsl and are alpha blended onto their layer in
+//!   order. The shader ignores the pressure, so does this.
+//! * Layers are blended onto the composite with their BlendOp, starting from transparent black.
+//! * The composite is clamped and written to an sRGB 8 bit image like HeadlessCanvas::read_rgba8.
+//!
+//! Texture borders between tiles and the rounding of the half float layers are not modelled, so
+//! compare with a small tolerance. It is slow, but works where no adapter is available.
+//!
+use crate::brush;
+use crate::color;
+use crate::error::{Error, Result};
+use crate::layer;
+use crate::stroke_log::StrokeLog;
+use crate::texture;
+use crate::tile;
+
+pub type Rgba = [f32; 4];
+
+/// BlendOps the reference renderer knows.
+pub const BLEND_OPS: &[&str] = &["Add"];
+
+/// BrushOps the reference renderer knows.
+pub const BRUSH_OPS: &[&str] = &["default"];
+
+///
+/// Blends src onto dst like the BlendOp named op.
+///
+pub fn blend(op: &str, src: Rgba, dst: Rgba) -> Result<Rgba>{
+    match op{
+        // add.wgsl
+        "Add" => Ok([src[0] + dst[0], src[1] + dst[1], src[2] + dst[2], src[3] + dst[3]]),
+        _ => Err(Error::MissingBlendOp(op.to_string())),
+    }
+}
+
+fn fallofn(x: f32) -> f32{
+    (-(x * x)).exp()
+}
+
+fn falloft(t: f32) -> f32{
+    fallofn((t - 0.5) / 3.0)
+}
+
+///
+/// Alpha of the default brush painting stroke at uv, in the uv space of the strokes.
+/// Zero length strokes paint nothing, the shader divides by zero for them.
+///
+pub fn brush_strength(stroke: &brush::StrokeData, uv: [f32; 2]) -> f32{
+    let dir = [stroke.pos1[0] - stroke.pos0[0], stroke.pos1[1] - stroke.pos0[1]];
+    let len = (dir[0] * dir[0] + dir[1] * dir[1]).sqrt();
+    if len == 0.0{
+        return 0.0;
+    }
+    let n = [dir[0] / len, dir[1] / len];
+    let rel = [uv[0] - stroke.pos0[0], uv[1] - stroke.pos0[1]];
+    let t = n[0] * rel[0] + n[1] * rel[1];
+    let p = [t * n[0] - rel[0], t * n[1] - rel[1]];
+    let d = (p[0] * p[0] + p[1] * p[1]).sqrt();
+
+    fallofn(d * 50.0) * falloft(t / len)
+}
+
+///
+/// Paints the color of the default brush with alpha strength onto dst, like
+/// wgpu::BlendState::ALPHA_BLENDING.
+///
+pub fn paint(dst: Rgba, strength: f32) -> Rgba{
+    let src = [1.0, 0.0, 0.0];
+    let keep = 1.0 - strength;
+    [
+        src[0] * strength + dst[0] * keep,
+        src[1] * strength + dst[1] * keep,
+        src[2] * strength + dst[2] * keep,
+        strength + dst[3] * keep,
+    ]
+}
+
+fn lerp(a: Rgba, b: Rgba, t: f32) -> Rgba{
+    [0, 1, 2, 3].map(|i| a[i] + (b[i] - a[i]) * t)
+}
+
+///
+/// Pixels in image orientation, rows from the top.
+///
+#[derive(Clone, Debug)]
+struct Level{
+    size: [u32; 2],
+    pixels: Vec<Rgba>,
+}
+
+impl Level{
+    fn new(size: [u32; 2]) -> Self{
+        Self{
+            size,
+            pixels: vec![[0.0; 4]; (size[0] * size[1]) as usize],
+        }
+    }
+
+    fn get(&self, x: i64, y: i64) -> Rgba{
+        let x = x.clamp(0, self.size[0] as i64 - 1) as u32;
+        let y = y.clamp(0, self.size[1] as i64 - 1) as u32;
+        self.pixels[(y * self.size[0] + x) as usize]
+    }
+
+    ///
+    /// Halves the size, averaging 2x2 texels like the linear blit of mipmap::MipmapGenerator.
+    ///
+    fn downsample(&self) -> Self{
+        let size = [(self.size[0] / 2).max(1), (self.size[1] / 2).max(1)];
+        let mut level = Self::new(size);
+        for y in 0..size[1]{
+            for x in 0..size[0]{
+                let (sx, sy) = (2 * x as i64, 2 * y as i64);
+                let a = lerp(self.get(sx, sy), self.get(sx + 1, sy), 0.5);
+                let b = lerp(self.get(sx, sy + 1), self.get(sx + 1, sy + 1), 0.5);
+                level.pixels[(y * size[0] + x) as usize] = lerp(a, b, 0.5);
+            }
+        }
+        level
+    }
+
+    ///
+    /// Bilinear sample with clamp to edge, uv has v = 0 at the bottom like the layer textures.
+    ///
+    fn sample(&self, uv: [f32; 2]) -> Rgba{
+        let x = uv[0] * self.size[0] as f32 - 0.5;
+        let y = (1.0 - uv[1]) * self.size[1] as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = lerp(self.get(x0, y0), self.get(x0 + 1, y0), fx);
+        let bottom = lerp(self.get(x0, y0 + 1), self.get(x0 + 1, y0 + 1), fx);
+        lerp(top, bottom, fy)
+    }
+}
+
+///
+/// The 2d part of model * proj, maps positions on the [-1, 1] quad of a layer to clip space.
+///
+#[derive(Clone, Copy, Debug)]
+struct Affine{
+    m: [[f32; 2]; 2],
+    t: [f32; 2],
+}
+
+impl Affine{
+    fn of_layer(translation: &glm::Vec3, scale: &glm::Vec3, rotation: &glm::Vec4, view_size: [u32; 2]) -> Self{
+        let (model, proj) = layer::transforms(translation, scale, rotation, view_size);
+        let full = model * proj;
+        Self{
+            m: [[full[(0, 0)], full[(0, 1)]], [full[(1, 0)], full[(1, 1)]]],
+            t: [full[(0, 3)], full[(1, 3)]],
+        }
+    }
+
+    fn apply(&self, p: [f32; 2]) -> [f32; 2]{
+        [
+            self.m[0][0] * p[0] + self.m[0][1] * p[1] + self.t[0],
+            self.m[1][0] * p[0] + self.m[1][1] * p[1] + self.t[1],
+        ]
+    }
+
+    fn inverse(&self) -> Option<Self>{
+        let det = self.m[0][0] * self.m[1][1] - self.m[0][1] * self.m[1][0];
+        if det == 0.0 || !det.is_finite(){
+            return None;
+        }
+        let m = [
+            [self.m[1][1] / det, -self.m[0][1] / det],
+            [-self.m[1][0] / det, self.m[0][0] / det],
+        ];
+        let t = [
+            -(m[0][0] * self.t[0] + m[0][1] * self.t[1]),
+            -(m[1][0] * self.t[0] + m[1][1] * self.t[1]),
+        ];
+        Some(Self{m, t})
+    }
+}
+
+///
+/// Clip space position of the center of pixel (x, y) of an image read back from a view.
+/// Readbacks are flipped, so y grows with the row.
+///
+fn pixel_to_clip(x: u32, y: u32, view_size: [u32; 2]) -> [f32; 2]{
+    [
+        2.0 * (x as f32 + 0.5) / view_size[0] as f32 - 1.0,
+        2.0 * (y as f32 + 0.5) / view_size[1] as f32 - 1.0,
+    ]
+}
+
+///
+/// A layer::Layer on the CPU, stored in one piece instead of tiles.
+///
+#[derive(Clone, Debug)]
+pub struct ReferenceLayer{
+    pub translation: glm::Vec3,
+    pub scale: glm::Vec3,
+    pub rotation: glm::Vec4,
+    pub blendop: String,
+
+    image: Level,
+    strokes: Vec<brush::StrokeData>,
+}
+
+impl ReferenceLayer{
+    ///
+    /// An empty layer, transformed like the one of layer::Layer::new.
+    ///
+    pub fn new(size: [u32; 2], blendop: &str) -> Self{
+        Self{
+            translation: glm::vec3(0.0, 0.0, 0.0),
+            scale: glm::vec3(1000.0, 1000.0, 1000.0),
+            rotation: glm::vec4(0.0, 0.0, 1.0, 0.0),
+            blendop: blendop.to_string(),
+            image: Level::new(size),
+            strokes: Vec::new(),
+        }
+    }
+
+    ///
+    /// A layer of an sRGB encoded image, its values are stored in color_space.
+    ///
+    pub fn from_image(img: &image::RgbaImage, color_space: color::ColorSpace, blendop: &str) -> Self{
+        let mut layer = Self::new([img.width(), img.height()], blendop);
+        layer.scale = glm::vec3(1.0, 1.0, 1.0);
+        layer.image.pixels = img.pixels()
+            .map(|p| color::convert(p.0.map(|v| v as f32 / 255.0), color::ColorSpace::Srgb, color_space))
+            .collect();
+        layer
+    }
+
+    pub fn size(&self) -> [u32; 2]{
+        self.image.size
+    }
+
+    ///
+    /// The stored value of a pixel, rows from the top.
+    ///
+    pub fn pixel(&self, x: u32, y: u32) -> Rgba{
+        self.image.get(x as i64, y as i64)
+    }
+
+    ///
+    /// See layer::Layer::fit_to_pixels.
+    ///
+    pub fn fit_to_pixels(&mut self, size: [u32; 2]){
+        self.scale = glm::vec3(size[0] as f32 / 2.0, size[1] as f32 / 2.0, 1.0);
+    }
+
+    pub fn queue_stroke(&mut self, data: brush::StrokeData){
+        self.strokes.push(data);
+    }
+
+    fn affine(&self, view_size: [u32; 2]) -> Affine{
+        Affine::of_layer(&self.translation, &self.scale, &self.rotation, view_size)
+    }
+
+    ///
+    /// Paints the queued strokes, their positions are mapped through the layer transform for a
+    /// view of view_size like f_bguv in vert_brush.glsl.
+    ///
+    pub fn apply_strokes(&mut self, view_size: [u32; 2]){
+        if self.strokes.is_empty(){
+            return;
+        }
+        let affine = self.affine(view_size);
+        let size = self.image.size;
+
+        for y in 0..size[1]{
+            for x in 0..size[0]{
+                // Texel centers on the quad, textures are stored from the bottom so row y from
+                // the top is drawn where the brush pass puts texture row size - 1 - y.
+                let pos = [
+                    2.0 * (x as f32 + 0.5) / size[0] as f32 - 1.0,
+                    2.0 * (y as f32 + 0.5) / size[1] as f32 - 1.0,
+                ];
+                let clip = affine.apply(pos);
+                let bguv = [(clip[0] + 1.0) / 2.0, (clip[1] + 1.0) / 2.0];
+
+                let pixel = &mut self.image.pixels[(y * size[0] + x) as usize];
+                for stroke in &self.strokes{
+                    *pixel = paint(*pixel, brush_strength(stroke, bguv));
+                }
+            }
+        }
+        self.strokes.clear();
+    }
+
+    ///
+    /// Mip chain as the tiles have it, tiles stop at a 1x1 level of their own.
+    ///
+    fn mip_chain(&self) -> Vec<Level>{
+        let tile_size = [self.image.size[0].min(tile::TILE_SIZE), self.image.size[1].min(tile::TILE_SIZE)];
+        let count = texture::Texture::mip_level_count_for(tile_size);
+
+        let mut levels = vec![self.image.clone()];
+        for _ in 1..count{
+            let next = levels.last().unwrap().downsample();
+            levels.push(next);
+        }
+        levels
+    }
+
+    ///
+    /// The layer drawn into a cleared view of view_size like Layer::draw, rows from the top.
+    ///
+    pub fn draw(&self, view_size: [u32; 2]) -> Vec<Rgba>{
+        let mut dst = vec![[0.0; 4]; (view_size[0] * view_size[1]) as usize];
+        let to_quad = match self.affine(view_size).inverse(){
+            Some(to_quad) => to_quad,
+            None => return dst,
+        };
+        let levels = self.mip_chain();
+
+        // The transform is affine, so the level of detail is the same for every pixel.
+        let size = self.image.size;
+        let texels_per_pixel = |axis: usize| {
+            let d = [to_quad.m[0][axis] * 2.0 / view_size[axis] as f32, to_quad.m[1][axis] * 2.0 / view_size[axis] as f32];
+            let dx = d[0] * size[0] as f32 / 2.0;
+            let dy = d[1] * size[1] as f32 / 2.0;
+            (dx * dx + dy * dy).sqrt()
+        };
+        let lod = texels_per_pixel(0).max(texels_per_pixel(1)).log2()
+            .clamp(0.0, (levels.len() - 1) as f32);
+        let (lower, t) = (lod.floor() as usize, lod.fract());
+        let upper = (lower + 1).min(levels.len() - 1);
+
+        for y in 0..view_size[1]{
+            for x in 0..view_size[0]{
+                let pos = to_quad.apply(pixel_to_clip(x, y, view_size));
+                if pos[0].abs() > 1.0 || pos[1].abs() > 1.0{
+                    continue;
+                }
+                let uv = [(pos[0] + 1.0) / 2.0, (pos[1] + 1.0) / 2.0];
+                let color = lerp(levels[lower].sample(uv), levels[upper].sample(uv), t);
+                dst[(y * view_size[0] + x) as usize] = color;
+            }
+        }
+        dst
+    }
+}
+
+///
+/// A canvas::Canvas on the CPU.
+///
+#[derive(Clone, Debug)]
+pub struct ReferenceCanvas{
+    pub layers: Vec<ReferenceLayer>,
+    size: [u32; 2],
+    color_space: color::ColorSpace,
+}
+
+impl ReferenceCanvas{
+    pub fn new(size: [u32; 2], color_space: color::ColorSpace) -> Self{
+        Self{
+            layers: Vec::new(),
+            size,
+            color_space,
+        }
+    }
+
+    pub fn size(&self) -> [u32; 2]{
+        self.size
+    }
+
+    pub fn color_space(&self) -> color::ColorSpace{
+        self.color_space
+    }
+
+    fn check_blendop(blendop: &str) -> Result<()>{
+        if !BLEND_OPS.contains(&blendop){
+            return Err(Error::MissingBlendOp(blendop.to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn push_layer(&mut self, layer: ReferenceLayer) -> usize{
+        self.layers.push(layer);
+        self.layers.len() - 1
+    }
+
+    ///
+    /// Adds a layer showing img at its pixel size, like HeadlessCanvas::push_image.
+    ///
+    pub fn push_image(&mut self, img: &image::RgbaImage, blendop: &str) -> Result<usize>{
+        Self::check_blendop(blendop)?;
+        let mut layer = ReferenceLayer::from_image(img, self.color_space, blendop);
+        layer.fit_to_pixels([img.width(), img.height()]);
+        Ok(self.push_layer(layer))
+    }
+
+    ///
+    /// Adds an empty layer covering the canvas, like HeadlessCanvas::push_empty.
+    ///
+    pub fn push_empty(&mut self, blendop: &str) -> Result<usize>{
+        Self::check_blendop(blendop)?;
+        let mut layer = ReferenceLayer::new(self.size, blendop);
+        layer.fit_to_pixels(self.size);
+        Ok(self.push_layer(layer))
+    }
+
+    pub fn queue_stroke(&mut self, layer: usize, brushop: &str, data: brush::StrokeData) -> Result<()>{
+        if !BRUSH_OPS.contains(&brushop){
+            return Err(Error::MissingBrushOp(brushop.to_string()));
+        }
+        self.layers.get_mut(layer)
+            .ok_or(Error::MissingLayer(layer))?
+            .queue_stroke(data);
+        Ok(())
+    }
+
+    ///
+    /// Queues the strokes of a log, like StrokeLog::replay.
+    ///
+    pub fn replay(&mut self, log: &StrokeLog) -> Result<()>{
+        for stroke in &log.strokes{
+            stroke.check_params()?;
+        }
+        for stroke in &log.strokes{
+            for data in stroke.segments(){
+                self.queue_stroke(stroke.layer, &stroke.brush, data)?;
+            }
+        }
+        Ok(())
+    }
+
+    ///
+    /// Applies the queued strokes and blends all layers, rows from the top.
+    ///
+    pub fn composite(&mut self) -> Result<Vec<Rgba>>{
+        let mut composite = vec![[0.0; 4]; (self.size[0] * self.size[1]) as usize];
+        for layer in &mut self.layers{
+            layer.apply_strokes(self.size);
+            let drawn = layer.draw(self.size);
+            for (dst, src) in composite.iter_mut().zip(drawn){
+                *dst = blend(&layer.blendop, src, *dst)?;
+            }
+        }
+        Ok(composite)
+    }
+
+    ///
+    /// Composites and tonemaps into an sRGB 8 bit image like HeadlessCanvas::read_rgba8.
+    ///
+    pub fn render(&mut self) -> Result<image::RgbaImage>{
+        let composite = self.composite()?;
+        let color_space = self.color_space;
+        let to_u8 = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+        let bytes = composite.iter()
+            .flat_map(|rgba| {
+                let rgba = rgba.map(|v| v.clamp(0.0, 1.0));
+                color::convert(rgba, color_space, color::ColorSpace::Srgb).map(to_u8)
+            })
+            .collect();
+        Ok(image::RgbaImage::from_raw(self.size[0], self.size[1], bytes).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use brush::StrokeData;
+
+    fn segment(pos0: [f32; 2], pos1: [f32; 2]) -> StrokeData{
+        StrokeData{pos0, pos1, p0: 1.0, p1: 1.0}
+    }
+
+    #[test]
+    fn blend_add(){
+        let sum = blend("Add", [0.25, 0.5, 1.0, 0.5], [0.5, 0.75, 0.5, 1.0]).unwrap();
+        assert_eq!(sum, [0.75, 1.25, 1.5, 1.5]);
+        assert!(blend("Multiply", [0.0; 4], [0.0; 4]).is_err());
+    }
+
+    #[test]
+    fn brush_falloff(){
+        let stroke = segment([0.25, 0.5], [0.75, 0.5]);
+        // full strength in the middle of the segment.
+        assert!((brush_strength(&stroke, [0.5, 0.5]) - 1.0).abs() < 1e-6);
+        // falls off across the stroke, fallofn(d * 50.0).
+        let across = brush_strength(&stroke, [0.5, 0.51]);
+        assert!((across - (-0.25f32).exp()).abs() < 1e-5, "{}", across);
+        // nothing beyond RADIUS and EXTENT.
+        assert!(brush_strength(&stroke, [0.5, 0.5 + StrokeData::RADIUS]) < 1e-4);
+        assert!(brush_strength(&stroke, [0.5 + 0.5 * StrokeData::EXTENT, 0.5]) < 1e-4);
+        // zero length strokes paint nothing.
+        assert_eq!(brush_strength(&segment([0.5, 0.5], [0.5, 0.5]), [0.5, 0.5]), 0.0);
+    }
+}
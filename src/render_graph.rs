@@ -0,0 +1,437 @@
+use crate::color;
+use crate::error::{Error, Result};
+use crate::texture;
+use crate::texture_pool;
+
+///
+/// Handle to a texture used by the passes of a RenderGraph.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ResourceId(usize);
+
+///
+/// Errors of building and executing a RenderGraph.
+///
+#[derive(Debug, thiserror::Error)]
+pub enum GraphError{
+    #[error("Transient {0:?} is not allocated")]
+    Unallocated(ResourceId),
+    #[error("{0:?} is only a view")]
+    NotATexture(ResourceId),
+    #[error("Pass {0} scheduled twice")]
+    ScheduledTwice(usize),
+    #[error("RenderGraph pass {name:?} failed: {source}")]
+    Pass{name: String, source: Box<Error>},
+}
+
+///
+/// Description of a transient texture.
+/// Transients with the same description can share a texture if their lifetimes don't overlap.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextureDesc{
+    pub size: [u32; 2],
+    pub format: wgpu::TextureFormat,
+    pub color_space: color::ColorSpace,
+}
+
+impl TextureDesc{
+    fn key(&self) -> texture_pool::TextureKey{
+        texture_pool::TextureKey::new(self.size, self.format)
+    }
+}
+
+enum Resource<'a>{
+    Transient(TextureDesc),
+    Texture(&'a texture::Texture),
+    View(&'a wgpu::TextureView),
+}
+
+type PassFn<'a> = Box<dyn FnOnce(&mut wgpu::CommandEncoder, &PassResources) -> Result<()> + 'a>;
+
+struct Pass<'a>{
+    name: String,
+    inputs: Vec<ResourceId>,
+    outputs: Vec<ResourceId>,
+    exec: PassFn<'a>,
+}
+
+///
+/// The textures a pass declared, resolved to the textures they are backed by.
+///
+pub struct PassResources<'r>{
+    resources: &'r [Resource<'r>],
+    physical: &'r [Option<usize>],
+    textures: &'r [texture::Texture],
+}
+
+impl<'r> PassResources<'r>{
+    pub fn texture(&self, id: ResourceId) -> Result<&texture::Texture>{
+        match &self.resources[id.0]{
+            Resource::Transient(_) => {
+                let index = self.physical[id.0].ok_or(GraphError::Unallocated(id))?;
+                Ok(&self.textures[index])
+            },
+            Resource::Texture(texture) => Ok(texture),
+            Resource::View(_) => Err(GraphError::NotATexture(id).into()),
+        }
+    }
+
+    pub fn view(&self, id: ResourceId) -> Result<&wgpu::TextureView>{
+        match &self.resources[id.0]{
+            Resource::View(view) => Ok(view),
+            _ => Ok(&self.texture(id)?.view),
+        }
+    }
+}
+
+///
+/// A frame described as passes with declared inputs and outputs.
+///
+/// Passes have to be added in execution order, they run in the order they were added.
+/// Passes whose outputs are never used are culled and transient textures are acquired from a
+/// TexturePool, reusing textures whose last reader already ran. They are released back to the
+/// pool after execution.
+/// A pass reading a resource depends on the passes writing it that were added before it.
+/// Imported textures and views are never culled, so passes writing them always run.
+///
+pub struct RenderGraph<'a>{
+    resources: Vec<Resource<'a>>,
+    passes: Vec<Pass<'a>>,
+}
+
+impl<'a> RenderGraph<'a>{
+    pub fn new() -> Self{
+        Self{
+            resources: Vec::new(),
+            passes: Vec::new(),
+        }
+    }
+
+    fn push_resource(&mut self, resource: Resource<'a>) -> ResourceId{
+        self.resources.push(resource);
+        ResourceId(self.resources.len() - 1)
+    }
+
+    ///
+    /// Declares a texture that only lives during the execution of the graph.
+    /// Its content is undefined until a pass writes to it.
+    ///
+    pub fn create_texture(&mut self, desc: TextureDesc) -> ResourceId{
+        self.push_resource(Resource::Transient(desc))
+    }
+
+    pub fn import_texture(&mut self, texture: &'a texture::Texture) -> ResourceId{
+        self.push_resource(Resource::Texture(texture))
+    }
+
+    ///
+    /// Imports a view that can only be written to, like the surface.
+    ///
+    pub fn import_view(&mut self, view: &'a wgpu::TextureView) -> ResourceId{
+        self.push_resource(Resource::View(view))
+    }
+
+    pub fn add_pass<F>(&mut self, name: &str, inputs: &[ResourceId], outputs: &[ResourceId], exec: F)
+        where F: FnOnce(&mut wgpu::CommandEncoder, &PassResources) -> Result<()> + 'a
+    {
+        self.passes.push(Pass{
+            name: name.to_string(),
+            inputs: inputs.to_vec(),
+            outputs: outputs.to_vec(),
+            exec: Box::new(exec),
+        });
+    }
+
+    fn is_transient(&self, id: ResourceId) -> bool{
+        matches!(self.resources[id.0], Resource::Transient(_))
+    }
+
+    ///
+    /// For every pass the passes it has to run after.
+    ///
+    fn dependencies(&self) -> Vec<Vec<usize>>{
+        self.passes.iter().enumerate()
+            .map(|(i, pass)| {
+                self.passes[..i].iter().enumerate()
+                    .filter(|(_, prev)| {
+                        // read after write, write after read and write after write.
+                        pass.inputs.iter().any(|id| prev.outputs.contains(id))
+                            || pass.outputs.iter().any(|id| prev.inputs.contains(id) || prev.outputs.contains(id))
+                    })
+                    .map(|(j, _)| j)
+                    .collect()
+            })
+            .collect()
+    }
+
+    ///
+    /// Passes that contribute to an imported resource.
+    ///
+    fn live_passes(&self, dependencies: &[Vec<usize>]) -> Vec<bool>{
+        let mut live: Vec<bool> = self.passes.iter()
+            .map(|pass| pass.outputs.iter().any(|id| !self.is_transient(*id)))
+            .collect();
+
+        // Dependencies always point to earlier passes so one backwards sweep is enough.
+        for i in (0..self.passes.len()).rev(){
+            if live[i]{
+                for dep in &dependencies[i]{
+                    live[*dep] = true;
+                }
+            }
+        }
+        live
+    }
+
+    ///
+    /// Execution order of the live passes.
+    /// Dependencies always point to earlier passes so the order they were added in is kept.
+    ///
+    fn order(&self) -> Vec<usize>{
+        let live = self.live_passes(&self.dependencies());
+        (0..self.passes.len()).filter(|i| live[*i]).collect()
+    }
+
+    ///
+    /// Assigns a pool texture to every transient used by the passes in order.
+    /// Transients whose lifetimes don't overlap share textures.
+    ///
+    fn allocate(&self, device: &wgpu::Device, order: &[usize], pool: &mut texture_pool::TexturePool) -> (Vec<Option<usize>>, Vec<texture::Texture>){
+        let mut first_use: Vec<Option<usize>> = vec![None; self.resources.len()];
+        let mut last_use: Vec<Option<usize>> = vec![None; self.resources.len()];
+        for (step, pass) in order.iter().map(|i| &self.passes[*i]).enumerate(){
+            for id in pass.inputs.iter().chain(pass.outputs.iter()){
+                first_use[id.0].get_or_insert(step);
+                last_use[id.0] = Some(step);
+            }
+        }
+
+        let mut physical: Vec<Option<usize>> = vec![None; self.resources.len()];
+        let mut textures: Vec<texture::Texture> = Vec::new();
+        let mut descs: Vec<TextureDesc> = Vec::new();
+        let mut in_use: Vec<bool> = Vec::new();
+
+        for step in 0..order.len(){
+            for (id, resource) in self.resources.iter().enumerate(){
+                let desc = match resource{
+                    Resource::Transient(desc) if first_use[id] == Some(step) => desc,
+                    _ => continue,
+                };
+
+                let index = match (0..textures.len()).find(|i| !in_use[*i] && descs[*i] == *desc){
+                    Some(index) => index,
+                    None => {
+                        textures.push(pool.acquire(device, desc.key(), desc.color_space));
+                        descs.push(*desc);
+                        in_use.push(false);
+                        textures.len() - 1
+                    },
+                };
+                in_use[index] = true;
+                physical[id] = Some(index);
+            }
+
+            for id in 0..self.resources.len(){
+                if last_use[id] == Some(step){
+                    if let Some(index) = physical[id]{
+                        in_use[index] = false;
+                    }
+                }
+            }
+        }
+
+        (physical, textures)
+    }
+
+    pub fn execute(self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, pool: &mut texture_pool::TexturePool) -> Result<()>{
+        let order = self.order();
+        let (physical, textures) = self.allocate(device, &order, pool);
+
+        let Self{resources, passes} = self;
+        let mut passes: Vec<Option<Pass>> = passes.into_iter().map(Some).collect();
+
+        let result = (|| -> Result<()>{
+            let resources = PassResources{
+                resources: &resources,
+                physical: &physical,
+                textures: &textures,
+            };
+
+            for i in order{
+                let pass = passes[i].take().ok_or(GraphError::ScheduledTwice(i))?;
+                let name = pass.name;
+                (pass.exec)(encoder, &resources)
+                    .map_err(|err| GraphError::Pass{name, source: Box::new(err)})?;
+            }
+            Ok(())
+        })();
+
+        for texture in textures{
+            pool.release(texture);
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::cell::RefCell;
+
+    const DESC: TextureDesc = TextureDesc{
+        size: [4, 4],
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        color_space: color::ColorSpace::Linear,
+    };
+
+    fn noop(_: &mut wgpu::CommandEncoder, _: &PassResources) -> Result<()>{
+        Ok(())
+    }
+
+    ///
+    /// A device and a texture to import, None if there is no adapter.
+    ///
+    fn device() -> Option<(wgpu::Device, texture::Texture)>{
+        let (device, _queue) = match pollster::block_on(crate::HeadlessCanvas::request_device(true)){
+            Ok(device) => device,
+            Err(Error::NoAdapter) => {
+                println!("skipped, no adapter");
+                return None;
+            },
+            Err(err) => panic!("{}", err),
+        };
+        let texture = texture::Texture::new(&device, DESC.size, Some("Imported"), DESC.format, DESC.color_space, false, texture::Texture::DEFAULT_USAGE);
+        Some((device, texture))
+    }
+
+    #[test]
+    fn dependencies_follow_reads_and_writes(){
+        let mut graph = RenderGraph::new();
+        let [a, b, c] = [0; 3].map(|_| graph.create_texture(DESC));
+        graph.add_pass("write a", &[], &[a], noop);
+        graph.add_pass("read a", &[a], &[b], noop);
+        // write after read and write after write.
+        graph.add_pass("write a again", &[], &[a], noop);
+        graph.add_pass("read b", &[b], &[c], noop);
+        // reading and writing the same resource doesn't depend on itself.
+        graph.add_pass("read and write c", &[c], &[c], noop);
+
+        assert_eq!(graph.dependencies(), [vec![], vec![0], vec![0, 1], vec![1], vec![3]]);
+    }
+
+    #[test]
+    fn passes_not_reaching_an_import_are_culled(){
+        let (_device, imported) = match device(){
+            Some(device) => device,
+            None => return,
+        };
+        let mut graph = RenderGraph::new();
+        let target = graph.import_texture(&imported);
+        let [a, b, c] = [0; 3].map(|_| graph.create_texture(DESC));
+        graph.add_pass("write a", &[], &[a], noop);
+        graph.add_pass("write b", &[], &[b], noop);
+        graph.add_pass("composite", &[a], &[target], noop);
+        graph.add_pass("read b", &[b], &[c], noop);
+        // passes writing imports always run, even without inputs.
+        graph.add_pass("clear", &[], &[target], noop);
+
+        let dependencies = graph.dependencies();
+        assert_eq!(graph.live_passes(&dependencies), [true, false, true, false, true]);
+        assert_eq!(graph.order(), [0, 2, 4]);
+    }
+
+    #[test]
+    fn passes_run_in_the_order_they_were_added(){
+        let (_device, imported) = match device(){
+            Some(device) => device,
+            None => return,
+        };
+        let mut graph = RenderGraph::new();
+        let target = graph.import_texture(&imported);
+        let [a, b] = [0; 2].map(|_| graph.create_texture(DESC));
+        graph.add_pass("write a", &[], &[a], noop);
+        graph.add_pass("write b", &[], &[b], noop);
+        graph.add_pass("read b", &[b], &[target], noop);
+        graph.add_pass("read a", &[a], &[target], noop);
+        assert_eq!(graph.order(), [0, 1, 2, 3]);
+
+        // passes that look cyclic run like they were added, a pass only depends on earlier ones.
+        let mut graph = RenderGraph::new();
+        let target = graph.import_texture(&imported);
+        let [a, b] = [0; 2].map(|_| graph.create_texture(DESC));
+        graph.add_pass("a to b", &[a], &[b], noop);
+        graph.add_pass("b to a", &[b], &[a], noop);
+        graph.add_pass("read a", &[a], &[target], noop);
+        assert_eq!(graph.dependencies(), [vec![], vec![0], vec![1]]);
+        assert_eq!(graph.order(), [0, 1, 2]);
+    }
+
+    #[test]
+    fn transients_share_textures_when_their_lifetimes_dont_overlap(){
+        let (device, imported) = match device(){
+            Some(device) => device,
+            None => return,
+        };
+        let mut pool = texture_pool::TexturePool::new();
+        let mut graph = RenderGraph::new();
+        let target = graph.import_texture(&imported);
+        let [a, b, c] = [0; 3].map(|_| graph.create_texture(DESC));
+        let large = graph.create_texture(TextureDesc{size: [8, 8], ..DESC});
+        let unused = graph.create_texture(DESC);
+        graph.add_pass("write a", &[], &[a], noop);
+        graph.add_pass("a to b", &[a], &[b], noop);
+        graph.add_pass("b to large", &[b], &[large], noop);
+        graph.add_pass("large to c", &[large], &[c], noop);
+        graph.add_pass("c to target", &[c], &[target], noop);
+        graph.add_pass("culled", &[], &[unused], noop);
+
+        let order = graph.order();
+        let (physical, textures) = graph.allocate(&device, &order, &mut pool);
+        // a is released after b was acquired, so b needs a second texture, c reuses the one of a.
+        assert_eq!(physical[a.0], Some(0));
+        assert_eq!(physical[b.0], Some(1));
+        assert_eq!(physical[c.0], Some(0));
+        // textures of another size are never shared.
+        assert_eq!(physical[large.0], Some(2));
+        assert_eq!(textures[2].size, [8, 8]);
+        assert_eq!(textures.len(), 3);
+        // imports and transients of culled passes get no texture.
+        assert_eq!(physical[target.0], None);
+        assert_eq!(physical[unused.0], None);
+    }
+
+    #[test]
+    fn execute_runs_the_live_passes_and_reports_failures(){
+        let (device, imported) = match device(){
+            Some(device) => device,
+            None => return,
+        };
+        let mut pool = texture_pool::TexturePool::new();
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor{label: None});
+        let ran = RefCell::new(Vec::new());
+
+        let mut graph = RenderGraph::new();
+        let target = graph.import_texture(&imported);
+        let [a, unused] = [0; 2].map(|_| graph.create_texture(DESC));
+        graph.add_pass("culled", &[], &[unused], |_, _| {
+            ran.borrow_mut().push("culled");
+            Ok(())
+        });
+        graph.add_pass("write a", &[], &[a], |_, res| {
+            ran.borrow_mut().push("write a");
+            assert_eq!(res.texture(a)?.size, DESC.size);
+            Ok(())
+        });
+        graph.add_pass("fail", &[a], &[target], |_, res| {
+            ran.borrow_mut().push("fail");
+            assert!(res.view(target).is_ok());
+            Err(Error::InvalidArgument("failed".to_string()))
+        });
+
+        let err = graph.execute(&device, &mut encoder, &mut pool).unwrap_err();
+        assert!(matches!(&err, Error::RenderGraph(GraphError::Pass{name, ..}) if name == "fail"), "{}", err);
+        assert_eq!(*ran.borrow(), ["write a", "fail"]);
+    }
+}
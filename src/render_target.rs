@@ -1,4 +1,4 @@
-use anyhow::*;
+use crate::error::Result;
 use crate::texture;
 
 ///
@@ -16,6 +16,8 @@ pub trait ColorAttachment{
     fn color_attachment_clear(&self) -> wgpu::RenderPassColorAttachment;
     fn color_attachment_clear_with(&self, color: wgpu::Color) -> wgpu::RenderPassColorAttachment;
     fn color_attachment_load(&self) -> wgpu::RenderPassColorAttachment;
+    fn color_attachment_clear_resolve<'a>(&'a self, resolve_target: &'a wgpu::TextureView) -> wgpu::RenderPassColorAttachment<'a>;
+    fn color_attachment_load_resolve<'a>(&'a self, resolve_target: &'a wgpu::TextureView) -> wgpu::RenderPassColorAttachment<'a>;
 }
 
 impl ColorAttachment for wgpu::TextureView{
@@ -56,6 +58,99 @@ impl ColorAttachment for wgpu::TextureView{
             },
         }
     }
+
+    ///
+    /// Clears a multisampled view and resolves it into resolve_target at the end of the pass.
+    /// The multisampled content itself is not needed afterwards so it is not stored.
+    ///
+    fn color_attachment_clear_resolve<'a>(&'a self, resolve_target: &'a wgpu::TextureView) -> wgpu::RenderPassColorAttachment<'a>{
+        wgpu::RenderPassColorAttachment{
+            view: self,
+            resolve_target: Some(resolve_target),
+            ops: wgpu::Operations{
+                load: wgpu::LoadOp::Clear(wgpu::Color{
+                    r: 0.0,
+                    g: 0.0,
+                    b: 0.0,
+                    a: 0.0,
+                }),
+                store: false,
+            },
+        }
+    }
+
+    ///
+    /// Draws on top of the content of a multisampled view and resolves it into resolve_target.
+    ///
+    fn color_attachment_load_resolve<'a>(&'a self, resolve_target: &'a wgpu::TextureView) -> wgpu::RenderPassColorAttachment<'a>{
+        wgpu::RenderPassColorAttachment{
+            view: self,
+            resolve_target: Some(resolve_target),
+            ops: wgpu::Operations{
+                load: wgpu::LoadOp::Load,
+                store: false,
+            },
+        }
+    }
+}
+
+pub trait DepthStencilAttachment{
+    fn depth_attachment_clear(&self, depth: f32) -> wgpu::RenderPassDepthStencilAttachment<'_>;
+    fn depth_stencil_attachment_clear(&self, depth: f32, stencil: u32) -> wgpu::RenderPassDepthStencilAttachment<'_>;
+    fn stencil_attachment_clear(&self, stencil: u32) -> wgpu::RenderPassDepthStencilAttachment<'_>;
+    fn depth_stencil_attachment_load(&self) -> wgpu::RenderPassDepthStencilAttachment<'_>;
+}
+
+impl DepthStencilAttachment for wgpu::TextureView{
+    fn depth_attachment_clear(&self, depth: f32) -> wgpu::RenderPassDepthStencilAttachment<'_>{
+        wgpu::RenderPassDepthStencilAttachment{
+            view: self,
+            depth_ops: Some(wgpu::Operations{
+                load: wgpu::LoadOp::Clear(depth),
+                store: true,
+            }),
+            stencil_ops: None,
+        }
+    }
+
+    fn depth_stencil_attachment_clear(&self, depth: f32, stencil: u32) -> wgpu::RenderPassDepthStencilAttachment<'_>{
+        wgpu::RenderPassDepthStencilAttachment{
+            view: self,
+            depth_ops: Some(wgpu::Operations{
+                load: wgpu::LoadOp::Clear(depth),
+                store: true,
+            }),
+            stencil_ops: Some(wgpu::Operations{
+                load: wgpu::LoadOp::Clear(stencil),
+                store: true,
+            }),
+        }
+    }
+
+    fn stencil_attachment_clear(&self, stencil: u32) -> wgpu::RenderPassDepthStencilAttachment<'_>{
+        wgpu::RenderPassDepthStencilAttachment{
+            view: self,
+            depth_ops: None,
+            stencil_ops: Some(wgpu::Operations{
+                load: wgpu::LoadOp::Clear(stencil),
+                store: true,
+            }),
+        }
+    }
+
+    fn depth_stencil_attachment_load(&self) -> wgpu::RenderPassDepthStencilAttachment<'_>{
+        wgpu::RenderPassDepthStencilAttachment{
+            view: self,
+            depth_ops: Some(wgpu::Operations{
+                load: wgpu::LoadOp::Load,
+                store: true,
+            }),
+            stencil_ops: Some(wgpu::Operations{
+                load: wgpu::LoadOp::Load,
+                store: true,
+            }),
+        }
+    }
 }
 
 impl RenderTarget for wgpu::TextureView{
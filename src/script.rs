@@ -0,0 +1,521 @@
+//!
+//! Rhai bindings for automating canvases.
+//!
+//! Scripts get a global `canvas`. What they do is recorded as Commands and applied to a
+//! ScriptHost once the script finished, so a script that fails or refers to images that can't be
+//! decoded leaves the canvas untouched. render and save run while applying, if they fail the
+//! commands before them stay applied.
+//! Queries like `canvas.layer_count()` see the recorded changes.
+//!
+//! ```rhai
+//! let bg = canvas.add_image("assets/test1.jpg");
+//! let paint = canvas.add_layer("Add");
+//! paint.translation = [10, 0];
+//! for i in 0..10 {
+//!     paint.stroke(0.1 * i, 0.2, 0.1 * i + 0.1, 0.8);
+//! }
+//! canvas.render();
+//! canvas.save("out.png");
+//! ```
+//!
+//! Stroke positions are in canvas uv with (0, 0) at the bottom left, like brush::StrokeData.
+//!
+use crate::blendop;
+use crate::brush;
+use crate::canvas;
+use crate::error::{Error, Result};
+use crate::headless::HeadlessCanvas;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Scope, FLOAT, INT};
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+
+///
+/// What a script can do to a canvas.
+///
+/// Implemented by HeadlessCanvas for the command line and by the application for its window.
+///
+pub trait ScriptHost{
+    fn canvas(&mut self) -> &mut canvas::Canvas;
+    fn blendops(&self) -> &blendop::BlendOpManager;
+    fn brushops(&self) -> &brush::BrushOpManager;
+    ///
+    /// Adds a layer showing img at its pixel size, returns its index.
+    ///
+    fn push_image(&mut self, img: &image::DynamicImage, blendop: &str) -> Result<usize>;
+    ///
+    /// Adds an empty layer covering the canvas, returns its index.
+    ///
+    fn push_empty(&mut self, blendop: &str) -> Result<usize>;
+    ///
+    /// Applies queued strokes and composites.
+    ///
+    fn render(&mut self) -> Result<()>;
+    ///
+    /// Writes the last composite to an image file.
+    ///
+    fn save(&mut self, path: &Path) -> Result<()>;
+}
+
+impl ScriptHost for HeadlessCanvas{
+    fn canvas(&mut self) -> &mut canvas::Canvas{
+        &mut self.canvas
+    }
+
+    fn blendops(&self) -> &blendop::BlendOpManager{
+        &self.blendops
+    }
+
+    fn brushops(&self) -> &brush::BrushOpManager{
+        &self.brushops
+    }
+
+    fn push_image(&mut self, img: &image::DynamicImage, blendop: &str) -> Result<usize>{
+        HeadlessCanvas::push_image(self, img, blendop)
+    }
+
+    fn push_empty(&mut self, blendop: &str) -> Result<usize>{
+        HeadlessCanvas::push_empty(self, blendop)
+    }
+
+    fn render(&mut self) -> Result<()>{
+        HeadlessCanvas::render(self)
+    }
+
+    fn save(&mut self, path: &Path) -> Result<()>{
+        self.read_rgba8()?.save(path)?;
+        Ok(())
+    }
+}
+
+///
+/// A change of the canvas requested by a script.
+///
+#[derive(Clone, Debug)]
+pub enum Command{
+    AddImage{path: PathBuf, blendop: String},
+    AddLayer{blendop: String},
+    RemoveLayer(usize),
+    SetActiveLayer(usize),
+    SetTransform{layer: usize, translation: [f32; 3], scale: [f32; 3], rotation: [f32; 4]},
+    SetBlendOp{layer: usize, blendop: String},
+    Stroke{layer: usize, brushop: String, data: brush::StrokeData},
+    Render,
+    Save(PathBuf),
+}
+
+#[derive(Clone, Debug)]
+struct LayerState{
+    // stays the same when layers below are removed, see ScriptLayer.
+    id: u64,
+    translation: [f32; 3],
+    scale: [f32; 3],
+    rotation: [f32; 4],
+}
+
+///
+/// The commands of a script and the canvas as the script sees it.
+///
+struct Recording{
+    size: [u32; 2],
+    blendops: Vec<String>,
+    brushops: Vec<String>,
+    layers: Vec<LayerState>,
+    next_id: u64,
+    commands: Vec<Command>,
+}
+
+impl Recording{
+    fn new<H: ScriptHost>(host: &mut H) -> Self{
+        let blendops = host.blendops().names();
+        let brushops = host.brushops().names();
+        let canvas = host.canvas();
+        let layers: Vec<LayerState> = canvas.layers.iter().enumerate()
+            .map(|(i, layer)| {
+                let layer = layer.borrow();
+                LayerState{
+                    id: i as u64,
+                    translation: layer.translation.into(),
+                    scale: layer.scale.into(),
+                    rotation: layer.rotation.into(),
+                }
+            })
+            .collect();
+
+        Self{
+            size: canvas.size(),
+            blendops,
+            brushops,
+            next_id: layers.len() as u64,
+            layers,
+            commands: Vec::new(),
+        }
+    }
+
+    ///
+    /// Adds a layer on top and returns its id.
+    ///
+    fn push_layer(&mut self, scale: [f32; 3]) -> u64{
+        let id = self.next_id;
+        self.next_id += 1;
+        self.layers.push(LayerState{
+            id,
+            translation: [0.0; 3],
+            scale,
+            rotation: [0.0, 0.0, 1.0, 0.0],
+        });
+        id
+    }
+
+    ///
+    /// The current index of the layer with id.
+    ///
+    fn find_layer(&self, id: u64) -> std::result::Result<usize, Box<EvalAltResult>>{
+        self.layers.iter()
+            .position(|layer| layer.id == id)
+            .ok_or_else(|| "The layer was removed".into())
+    }
+
+    fn check_layer(&self, index: usize) -> std::result::Result<(), Box<EvalAltResult>>{
+        if index < self.layers.len(){
+            Ok(())
+        }
+        else{
+            Err(format!("Layer {} does not exist, the canvas has {}", index, self.layers.len()).into())
+        }
+    }
+
+    fn check_blendop(&self, name: &str) -> std::result::Result<(), Box<EvalAltResult>>{
+        if self.blendops.iter().any(|op| op == name){
+            Ok(())
+        }
+        else{
+            Err(format!("No BlendOp named {:?}, available are {:?}", name, self.blendops).into())
+        }
+    }
+
+    fn check_brushop(&self, name: &str) -> std::result::Result<(), Box<EvalAltResult>>{
+        if self.brushops.iter().any(|op| op == name){
+            Ok(())
+        }
+        else{
+            Err(format!("No BrushOp named {:?}, available are {:?}", name, self.brushops).into())
+        }
+    }
+}
+
+type ScriptResult<T> = std::result::Result<T, Box<EvalAltResult>>;
+
+///
+/// The canvas object of scripts.
+///
+#[derive(Clone)]
+pub struct ScriptCanvas{
+    recording: Rc<RefCell<Recording>>,
+}
+
+///
+/// A layer of the canvas.
+/// Keeps referring to the same layer when layers below it are removed, using it after its own
+/// layer was removed is an error.
+///
+#[derive(Clone)]
+pub struct ScriptLayer{
+    recording: Rc<RefCell<Recording>>,
+    id: u64,
+}
+
+fn number(value: &Dynamic) -> ScriptResult<f32>{
+    if let Some(v) = value.clone().try_cast::<FLOAT>(){
+        return Ok(v as f32);
+    }
+    if let Some(v) = value.clone().try_cast::<INT>(){
+        return Ok(v as f32);
+    }
+    Err(format!("Expected a number, got {}", value.type_name()).into())
+}
+
+fn index(value: INT) -> ScriptResult<usize>{
+    usize::try_from(value).map_err(|_| format!("Invalid index {}", value).into())
+}
+
+///
+/// Reads an array of 2 to n numbers, missing ones are taken from default.
+///
+fn vector<const N: usize>(array: &Array, default: [f32; N]) -> ScriptResult<[f32; N]>{
+    if array.len() < 2 || array.len() > N{
+        return Err(format!("Expected 2 to {} numbers, got {}", N, array.len()).into());
+    }
+    let mut v = default;
+    for (i, value) in array.iter().enumerate(){
+        v[i] = number(value)?;
+    }
+    Ok(v)
+}
+
+fn to_array(v: &[f32]) -> Array{
+    v.iter().map(|v| Dynamic::from(*v as FLOAT)).collect()
+}
+
+impl ScriptCanvas{
+    fn add_image(&mut self, path: &str, blendop: &str) -> ScriptResult<ScriptLayer>{
+        let mut recording = self.recording.borrow_mut();
+        recording.check_blendop(blendop)?;
+        // Only reads the header, the image is decoded when the commands are applied.
+        let (width, height) = image::image_dimensions(path)
+            .map_err(|e| format!("Can not read {:?}: {}", path, e))?;
+
+        let id = recording.push_layer([width as f32 / 2.0, height as f32 / 2.0, 1.0]);
+        recording.commands.push(Command::AddImage{path: PathBuf::from(path), blendop: blendop.to_string()});
+        Ok(ScriptLayer{recording: self.recording.clone(), id})
+    }
+
+    fn add_layer(&mut self, blendop: &str) -> ScriptResult<ScriptLayer>{
+        let mut recording = self.recording.borrow_mut();
+        recording.check_blendop(blendop)?;
+
+        let size = recording.size;
+        let id = recording.push_layer([size[0] as f32 / 2.0, size[1] as f32 / 2.0, 1.0]);
+        recording.commands.push(Command::AddLayer{blendop: blendop.to_string()});
+        Ok(ScriptLayer{recording: self.recording.clone(), id})
+    }
+
+    fn layer(&mut self, i: INT) -> ScriptResult<ScriptLayer>{
+        let i = index(i)?;
+        let recording = self.recording.borrow();
+        recording.check_layer(i)?;
+        Ok(ScriptLayer{recording: self.recording.clone(), id: recording.layers[i].id})
+    }
+
+    fn remove_layer(&mut self, i: INT) -> ScriptResult<()>{
+        let i = index(i)?;
+        let mut recording = self.recording.borrow_mut();
+        recording.check_layer(i)?;
+        recording.layers.remove(i);
+        recording.commands.push(Command::RemoveLayer(i));
+        Ok(())
+    }
+
+    fn set_active(&mut self, i: INT) -> ScriptResult<()>{
+        let i = index(i)?;
+        let mut recording = self.recording.borrow_mut();
+        recording.check_layer(i)?;
+        recording.commands.push(Command::SetActiveLayer(i));
+        Ok(())
+    }
+}
+
+impl ScriptLayer{
+    fn index(&self) -> ScriptResult<usize>{
+        self.recording.borrow().find_layer(self.id)
+    }
+
+    fn state(&self) -> ScriptResult<LayerState>{
+        let recording = self.recording.borrow();
+        let index = recording.find_layer(self.id)?;
+        Ok(recording.layers[index].clone())
+    }
+
+    fn update<F: FnOnce(&mut LayerState)>(&mut self, f: F) -> ScriptResult<()>{
+        let mut recording = self.recording.borrow_mut();
+        let index = recording.find_layer(self.id)?;
+        let state = &mut recording.layers[index];
+        f(state);
+        let command = Command::SetTransform{
+            layer: index,
+            translation: state.translation,
+            scale: state.scale,
+            rotation: state.rotation,
+        };
+        recording.commands.push(command);
+        Ok(())
+    }
+
+    fn set_blendop(&mut self, blendop: &str) -> ScriptResult<()>{
+        let mut recording = self.recording.borrow_mut();
+        let index = recording.find_layer(self.id)?;
+        recording.check_blendop(blendop)?;
+        recording.commands.push(Command::SetBlendOp{layer: index, blendop: blendop.to_string()});
+        Ok(())
+    }
+
+    fn stroke(&mut self, brushop: &str, p0: [f32; 3], p1: [f32; 3]) -> ScriptResult<()>{
+        let mut recording = self.recording.borrow_mut();
+        let index = recording.find_layer(self.id)?;
+        recording.check_brushop(brushop)?;
+        recording.commands.push(Command::Stroke{
+            layer: index,
+            brushop: brushop.to_string(),
+            data: brush::StrokeData{
+                pos0: [p0[0], p0[1]],
+                pos1: [p1[0], p1[1]],
+                p0: p0[2],
+                p1: p1[2],
+            },
+        });
+        Ok(())
+    }
+
+    ///
+    /// Strokes through points given as [x, y] or [x, y, pressure].
+    ///
+    fn polyline(&mut self, brushop: &str, points: Array) -> ScriptResult<()>{
+        let points = points.iter()
+            .map(|point| {
+                let point = point.clone().try_cast::<Array>()
+                    .ok_or_else(|| format!("Expected a point, got {}", point.type_name()))?;
+                vector(&point, [0.0, 0.0, 1.0])
+            })
+            .collect::<ScriptResult<Vec<[f32; 3]>>>()?;
+
+        for segment in points.windows(2){
+            self.stroke(brushop, segment[0], segment[1])?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// An engine with the canvas API registered.
+///
+pub fn engine() -> Engine{
+    let mut engine = Engine::new();
+
+    engine.register_type_with_name::<ScriptCanvas>("Canvas")
+        .register_get("width", |c: &mut ScriptCanvas| c.recording.borrow().size[0] as INT)
+        .register_get("height", |c: &mut ScriptCanvas| c.recording.borrow().size[1] as INT)
+        .register_fn("layer_count", |c: &mut ScriptCanvas| c.recording.borrow().layers.len() as INT)
+        .register_fn("blendops", |c: &mut ScriptCanvas| c.recording.borrow().blendops.iter().cloned().map(Dynamic::from).collect::<Array>())
+        .register_fn("brushops", |c: &mut ScriptCanvas| c.recording.borrow().brushops.iter().cloned().map(Dynamic::from).collect::<Array>())
+        .register_fn("add_image", |c: &mut ScriptCanvas, path: &str| c.add_image(path, "Add"))
+        .register_fn("add_image", ScriptCanvas::add_image)
+        .register_fn("add_layer", |c: &mut ScriptCanvas| c.add_layer("Add"))
+        .register_fn("add_layer", ScriptCanvas::add_layer)
+        .register_fn("layer", ScriptCanvas::layer)
+        .register_fn("remove_layer", ScriptCanvas::remove_layer)
+        .register_fn("set_active", ScriptCanvas::set_active)
+        .register_fn("render", |c: &mut ScriptCanvas| c.recording.borrow_mut().commands.push(Command::Render))
+        .register_fn("save", |c: &mut ScriptCanvas, path: &str| c.recording.borrow_mut().commands.push(Command::Save(PathBuf::from(path))));
+
+    engine.register_type_with_name::<ScriptLayer>("Layer")
+        .register_get("index", |l: &mut ScriptLayer| l.index().map(|i| i as INT))
+        .register_get("translation", |l: &mut ScriptLayer| l.state().map(|s| to_array(&s.translation)))
+        .register_set("translation", |l: &mut ScriptLayer, v: Array| {
+            let v = vector(&v, [0.0; 3])?;
+            l.update(|s| s.translation = v)
+        })
+        .register_get("scale", |l: &mut ScriptLayer| l.state().map(|s| to_array(&s.scale)))
+        .register_set("scale", |l: &mut ScriptLayer, v: Array| {
+            let v = vector(&v, [1.0; 3])?;
+            l.update(|s| s.scale = v)
+        })
+        // axis and angle in radians.
+        .register_get("rotation", |l: &mut ScriptLayer| l.state().map(|s| to_array(&s.rotation)))
+        .register_set("rotation", |l: &mut ScriptLayer, v: Array| {
+            if v.len() != 4{
+                return Err(format!("Expected axis and angle, got {} numbers", v.len()).into());
+            }
+            let v = vector(&v, [0.0; 4])?;
+            l.update(|s| s.rotation = v)
+        })
+        .register_set("blendop", |l: &mut ScriptLayer, blendop: &str| l.set_blendop(blendop))
+        .register_fn("stroke", |l: &mut ScriptLayer, x0: Dynamic, y0: Dynamic, x1: Dynamic, y1: Dynamic| {
+            l.stroke("default", [number(&x0)?, number(&y0)?, 1.0], [number(&x1)?, number(&y1)?, 1.0])
+        })
+        .register_fn("stroke", |l: &mut ScriptLayer, x0: Dynamic, y0: Dynamic, p0: Dynamic, x1: Dynamic, y1: Dynamic, p1: Dynamic| {
+            l.stroke("default", [number(&x0)?, number(&y0)?, number(&p0)?], [number(&x1)?, number(&y1)?, number(&p1)?])
+        })
+        .register_fn("stroke", |l: &mut ScriptLayer, brushop: &str, p0: Array, p1: Array| {
+            l.stroke(brushop, vector(&p0, [0.0, 0.0, 1.0])?, vector(&p1, [0.0, 0.0, 1.0])?)
+        })
+        .register_fn("polyline", |l: &mut ScriptLayer, points: Array| l.polyline("default", points))
+        .register_fn("polyline", ScriptLayer::polyline);
+
+    engine
+}
+
+///
+/// Applies recorded commands to host in order.
+/// Images are decoded before anything is applied, so failing to read one leaves host untouched.
+///
+pub fn apply<H: ScriptHost>(host: &mut H, commands: &[Command]) -> Result<()>{
+    let mut images = commands.iter()
+        .filter_map(|command| match command{
+            Command::AddImage{path, ..} => Some(image::open(path)),
+            _ => None,
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?
+        .into_iter();
+
+    for command in commands{
+        match command{
+            Command::AddImage{blendop, ..} => {
+                let img = images.next().expect("decoded above");
+                host.push_image(&img, blendop)?;
+            },
+            Command::AddLayer{blendop} => {
+                host.push_empty(blendop)?;
+            },
+            Command::RemoveLayer(index) => host.canvas().remove_layer(*index),
+            Command::SetActiveLayer(index) => host.canvas().set_active_layer(*index)?,
+            Command::SetTransform{layer, translation, scale, rotation} => {
+                let canvas = host.canvas();
+                let mut layer = canvas.layers.get(*layer).ok_or(Error::MissingLayer(*layer))?.borrow_mut();
+                layer.translation = glm::Vec3::from(*translation);
+                layer.scale = glm::Vec3::from(*scale);
+                layer.rotation = glm::Vec4::from(*rotation);
+            },
+            Command::SetBlendOp{layer, blendop} => {
+                let blendop = host.blendops().arc_to(blendop)?;
+                let canvas = host.canvas();
+                canvas.layers.get(*layer).ok_or(Error::MissingLayer(*layer))?.borrow_mut().set_blendop(blendop);
+                canvas.invalidate();
+            },
+            Command::Stroke{layer, brushop, data} => {
+                let brushop: Arc<brush::BrushOp> = host.brushops().arc_to(brushop)?;
+                let canvas = host.canvas();
+                canvas.layers.get(*layer).ok_or(Error::MissingLayer(*layer))?.borrow_mut()
+                    .queue_stroke(brush::Stroke::new(brushop, *data));
+            },
+            Command::Render => host.render()?,
+            Command::Save(path) => host.save(path)?,
+        }
+    }
+    Ok(())
+}
+
+///
+/// Runs a script and returns the commands it recorded, without changing host.
+///
+pub fn record<H: ScriptHost>(host: &mut H, src: &str) -> Result<Vec<Command>>{
+    let recording = Rc::new(RefCell::new(Recording::new(host)));
+
+    let mut scope = Scope::new();
+    scope.push("canvas", ScriptCanvas{recording: recording.clone()});
+
+    engine().run_with_scope(&mut scope, src)
+        .map_err(|e| Error::Script(e.to_string()))?;
+
+    // The scope still holds the canvas.
+    drop(scope);
+    let commands = std::mem::take(&mut recording.borrow_mut().commands);
+    Ok(commands)
+}
+
+///
+/// Runs a script on host.
+///
+pub fn run<H: ScriptHost>(host: &mut H, src: &str) -> Result<()>{
+    let commands = record(host, src)?;
+    apply(host, &commands)
+}
+
+pub fn run_file<H: ScriptHost>(host: &mut H, path: &Path) -> Result<()>{
+    let src = std::fs::read_to_string(path)?;
+    run(host, &src)
+        .map_err(|e| match e{
+            Error::Script(message) => Error::Script(format!("{}: {}", path.display(), message)),
+            e => e,
+        })
+}
@@ -0,0 +1,131 @@
+//!
+//! Offline checks for the shaders in src/shaders.
+//!
+//! Every shader is parsed and validated with naga and the resource bindings of the shaders used
+//! by BlendOp, BrushOp and Layer are compared against the layouts they push into their
+//! PipelineLayoutBuilder. None of this needs a GPU.
+//!
+use crate::blendop::BlendOp;
+use crate::brush::BrushOp;
+use crate::layer::Layer;
+use crate::mipmap::MipmapGenerator;
+use crate::tonemap::Tonemap;
+use crate::pipeline::NamedBindGroupLayouts;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const SHADER_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders");
+
+fn shader_path(name: &str) -> PathBuf{
+    Path::new(SHADER_DIR).join(name)
+}
+
+/// GLSL has no stage annotations so it is taken from the file name prefix.
+fn glsl_stage(path: &Path) -> naga::ShaderStage{
+    let name = path.file_name().unwrap().to_str().unwrap();
+    if name.starts_with("vert_"){
+        naga::ShaderStage::Vertex
+    }
+    else if name.starts_with("frag_"){
+        naga::ShaderStage::Fragment
+    }
+    else if name.starts_with("comp_"){
+        naga::ShaderStage::Compute
+    }
+    else{
+        panic!("can not derive shader stage of {:?}", path);
+    }
+}
+
+fn parse(path: &Path) -> naga::Module{
+    let src = fs::read_to_string(path).unwrap();
+    match path.extension().and_then(|e| e.to_str()){
+        Some("glsl") => {
+            let options = naga::front::glsl::Options::from(glsl_stage(path));
+            naga::front::glsl::Parser::default().parse(&options, &src)
+                .unwrap_or_else(|e| panic!("{:?}: {:?}", path, e))
+        },
+        Some("wgsl") => {
+            naga::front::wgsl::parse_str(&src)
+                .unwrap_or_else(|e| panic!("{:?}: {}", path, e.emit_to_string(&src)))
+        },
+        _ => panic!("unknown shader extension {:?}", path),
+    }
+}
+
+fn validate(path: &Path, module: &naga::Module){
+    naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::empty())
+        .validate(module)
+        .unwrap_or_else(|e| panic!("{:?}: {:?}", path, e));
+}
+
+///
+/// Checks that a binding used by the shader exists in the layout with a compatible type.
+///
+fn check_binding(path: &Path, module: &naga::Module, var: &naga::GlobalVariable, layouts: &NamedBindGroupLayouts){
+    let binding = var.binding.as_ref().unwrap();
+    let (name, layout) = layouts.get(binding.group as usize)
+        .unwrap_or_else(|| panic!("{:?}: {:?} uses set {} which is not in the pipeline layout", path, var.name, binding.group));
+    let entry = layout.entries().iter()
+        .find(|entry| entry.binding == binding.binding)
+        .unwrap_or_else(|| panic!("{:?}: {:?} uses binding {} which is not in bind group {:?}", path, var.name, binding.binding, name));
+
+    let matches = match (&module.types[var.ty].inner, &entry.ty){
+        (naga::TypeInner::Image{..}, wgpu::BindingType::Texture{..}) => true,
+        (naga::TypeInner::Image{..}, wgpu::BindingType::StorageTexture{..}) => true,
+        (naga::TypeInner::Sampler{..}, wgpu::BindingType::Sampler(..)) => true,
+        (_, wgpu::BindingType::Buffer{ty: wgpu::BufferBindingType::Uniform, ..}) => var.class == naga::StorageClass::Uniform,
+        (_, wgpu::BindingType::Buffer{ty: wgpu::BufferBindingType::Storage{..}, ..}) => matches!(var.class, naga::StorageClass::Storage{..}),
+        _ => false,
+    };
+    assert!(matches, "{:?}: {:?} at ({}, {}) does not match {:?} in bind group {:?}", path, var.name, binding.group, binding.binding, entry.ty, name);
+}
+
+fn check_pipeline(shaders: &[&str], layouts: NamedBindGroupLayouts){
+    for shader in shaders{
+        let path = shader_path(shader);
+        let module = parse(&path);
+        validate(&path, &module);
+
+        for (_, var) in module.global_variables.iter().filter(|(_, var)| var.binding.is_some()){
+            check_binding(&path, &module, var, &layouts);
+        }
+    }
+}
+
+#[test]
+fn all_shaders_validate(){
+    let mut count = 0;
+    for entry in fs::read_dir(SHADER_DIR).unwrap(){
+        let path = entry.unwrap().path();
+        let module = parse(&path);
+        validate(&path, &module);
+        count += 1;
+    }
+    assert_gt!(count, 0);
+}
+
+#[test]
+fn blendop_layout(){
+    check_pipeline(&["vert_screen.glsl", "frag_add.glsl"], BlendOp::bind_group_layouts());
+}
+
+#[test]
+fn brushop_layout(){
+    check_pipeline(&["vert_brush.glsl", "frag_brush01.glsl"], BrushOp::bind_group_layouts());
+}
+
+#[test]
+fn layer_layout(){
+    check_pipeline(&["vert_model.glsl", "frag_forward.glsl"], Layer::bind_group_layouts());
+}
+
+#[test]
+fn mipmap_layout(){
+    check_pipeline(&["vert_screen.glsl", "frag_blit.glsl"], MipmapGenerator::bind_group_layouts());
+}
+
+#[test]
+fn tonemap_layout(){
+    check_pipeline(&["vert_screen.glsl", "frag_tonemap.glsl"], Tonemap::bind_group_layouts());
+}
@@ -32,5 +32,7 @@ var s_dst: sampler;
 
 [[stage(fragment)]]
 fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32>{
-    return textureSample(t_src, s_src, in.uv) + textureSample(t_dst, s_dst, in.uv);
+    // uv of the screen quad starts at the bottom, textures start at the top.
+    let uv = vec2<f32>(in.uv.x, 1.0 - in.uv.y);
+    return textureSample(t_src, s_src, uv) + textureSample(t_dst, s_dst, uv);
 }
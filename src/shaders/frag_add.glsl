@@ -18,6 +18,8 @@ layout(set = 1, binding = 0) uniform texture2D t_dst;
 layout(set = 1, binding = 1) uniform sampler s_dst;
 
 void main(){
-    o_color = texture(sampler2D(t_src, s_src), f_uv) + texture(sampler2D(t_dst, s_dst), f_uv);
+    // uv of vert_screen starts at the bottom, textures start at the top.
+    vec2 uv = vec2(f_uv.x, 1.0 - f_uv.y);
+    o_color = texture(sampler2D(t_src, s_src), uv) + texture(sampler2D(t_dst, s_dst), uv);
     //o_color = vec4(f_uv, 0.0, 1.0);
 }
@@ -0,0 +1,14 @@
+#version 460
+
+layout(location = 0) out vec4 o_color;
+
+layout(location = 0) in vec2 f_pos;
+layout(location = 1) in vec2 f_uv;
+
+layout(set = 0, binding = 0) uniform texture2D t_src;
+layout(set = 0, binding = 1) uniform sampler s_src;
+
+void main(){
+    // uv of vert_screen starts at the bottom, textures start at the top.
+    o_color = texture(sampler2D(t_src, s_src), vec2(f_uv.x, 1.0 - f_uv.y));
+}
@@ -7,6 +7,9 @@ layout(location = 0) out vec4 o_color;
 layout(location = 0) in vec2 f_pos;
 layout(location = 1) in vec2 f_uv;
 layout(location = 2) in vec2 f_bguv;
+layout(location = 3) flat in vec2 f_pos0;
+layout(location = 4) flat in vec2 f_pos1;
+layout(location = 5) flat in vec2 f_pressure;
 
 layout(set = 0, binding = 0) uniform transforms{
     mat4 model;
@@ -14,19 +17,8 @@ layout(set = 0, binding = 0) uniform transforms{
     mat4 proj;
 };
 
-layout(set = 1, binding = 0) uniform texture2D t_self;
-layout(set = 1, binding = 1) uniform sampler s_self;
-
-layout(set = 2, binding = 0) uniform Stroke{
-    vec2 pos0;
-    vec2 pos1;
-    float p0;
-    float p1;
-}stroke;
-
-
-layout(set = 3, binding = 0) uniform texture2D t_background;
-layout(set = 3, binding = 1) uniform sampler s_background;
+layout(set = 1, binding = 0) uniform texture2D t_background;
+layout(set = 1, binding = 1) uniform sampler s_background;
 
 float fallofn(float x){
     return exp(-(x * x));
@@ -46,13 +38,13 @@ void main(){
 
     vec2 uv = f_bguv;
 
-    vec2 n = normalize(stroke.pos1 - stroke.pos0);
-    float t = dot(n, uv - stroke.pos0);
-    vec2 p = t * n + stroke.pos0;
+    vec2 n = normalize(f_pos1 - f_pos0);
+    float t = dot(n, uv - f_pos0);
+    vec2 p = t * n + f_pos0;
     float d = length(p - uv);
 
-    float brush_strength = fallofn(d * 50.0) * falloft(t / length(stroke.pos1 - stroke.pos0));
+    float brush_strength = fallofn(d * 50.0) * falloft(t / length(f_pos1 - f_pos0));
 
-    o_color = texture(sampler2D(t_self, s_self), f_uv) * (1.0 - brush_strength) + vec4(1.0, 0.0, 0.0, 1.0) * brush_strength;
-    //o_color = texture(sampler2D(t_self, s_self), f_uv) + vec4(1.0, 0.0, 0.0, 0.0) * brush_strength;
+    // Strokes are alpha blended onto the layer in the order they were drawn.
+    o_color = vec4(1.0, 0.0, 0.0, brush_strength);
 }
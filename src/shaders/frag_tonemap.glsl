@@ -0,0 +1,24 @@
+#version 460
+
+layout(location = 0) out vec4 o_color;
+
+layout(location = 0) in vec2 f_pos;
+layout(location = 1) in vec2 f_uv;
+
+layout(set = 0, binding = 0) uniform texture2D t_src;
+layout(set = 0, binding = 1) uniform sampler s_src;
+
+#ifdef ENCODE_SRGB
+vec3 linear_to_srgb(vec3 c){
+    return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, step(vec3(0.0031308), c));
+}
+#endif
+
+void main(){
+    // uv of vert_screen starts at the bottom, textures start at the top.
+    // the composite may exceed 1.0, the surface can't.
+    o_color = clamp(texture(sampler2D(t_src, s_src), vec2(f_uv.x, 1.0 - f_uv.y)), 0.0, 1.0);
+#ifdef ENCODE_SRGB
+    o_color.rgb = linear_to_srgb(o_color.rgb);
+#endif
+}
@@ -3,6 +3,12 @@
 layout(location = 0) in vec2 i_pos;
 layout(location = 1) in vec2 i_uv;
 
+// per stroke instance
+layout(location = 2) in vec2 i_pos0;
+layout(location = 3) in vec2 i_pos1;
+layout(location = 4) in float i_p0;
+layout(location = 5) in float i_p1;
+
 struct Transforms{
     mat4 model;
     mat4 view;
@@ -12,21 +18,21 @@ struct Transforms{
 layout(location = 0) out vec2 f_pos;
 layout(location = 1) out vec2 f_uv;
 layout(location = 2) out vec2 f_bguv;
+layout(location = 3) flat out vec2 f_pos0;
+layout(location = 4) flat out vec2 f_pos1;
+layout(location = 5) flat out vec2 f_pressure;
 
 layout(set = 0, binding = 0) uniform transforms{
     mat4 model;
     mat4 view;
     mat4 proj;
 };
-layout(set = 2, binding = 0) uniform Stroke{
-    vec2 pos0;
-    vec2 pos1;
-    float p0;
-    float p1;
-}stroke;
 
 void main(){
     f_pos = i_pos;
+    f_pos0 = i_pos0;
+    f_pos1 = i_pos1;
+    f_pressure = vec2(i_p0, i_p1);
     // have to invert y axis of uv
     f_uv = vec2(i_uv.x, 1-i_uv.y);
 
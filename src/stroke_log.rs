@@ -0,0 +1,347 @@
+//!
+//! Recording of input samples and their deterministic replay.
+//!
+//! A StrokeRecorder turns samples into the stroke segments that are painted and keeps every
+//! sample in a StrokeLog. Replaying the log queues the same segments on the same layers, so the
+//! document is reproduced independent of the timing of the replay.
+//!
+//! Logs are stored as text, floats are written with the shortest representation that reads back
+//! to the same value:
+//!
+//! ```text
+//! wgpu01-strokes 1
+//! # stroke <layer> <brush> [<param>=<value>...]
+//! stroke 0 default
+//! # sample <time> <x> <y> <pressure> <tilt x> <tilt y>
+//! sample 0 0.25 0.5 1 0 0
+//! sample 0.016 0.26 0.5 0.9 0 0
+//! end
+//! ```
+//!
+//! Time is in seconds since the recording started, positions are canvas uv with (0, 0) at the
+//! bottom left like brush::StrokeData.
+//!
+use crate::brush;
+use crate::canvas;
+use crate::error::{Error, Result};
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::time::Instant;
+
+/// first token of every log.
+pub const MAGIC: &str = "wgpu01-strokes";
+/// version written, older versions are still read.
+pub const VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sample{
+    pub time: f64,
+    pub pos: [f32; 2],
+    pub pressure: f32,
+    pub tilt: [f32; 2],
+}
+
+///
+/// The samples of one stroke and what it paints with.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedStroke{
+    pub layer: usize,
+    /// name of the BrushOp in the BrushOpManager, without whitespace.
+    pub brush: String,
+    /// parameters of the brush at the time of the stroke, by name.
+    pub params: BTreeMap<String, f32>,
+    pub samples: Vec<Sample>,
+}
+
+impl RecordedStroke{
+    pub fn new(layer: usize, brush: &str, params: BTreeMap<String, f32>) -> Self{
+        Self{
+            layer,
+            brush: brush.to_string(),
+            params,
+            samples: Vec::new(),
+        }
+    }
+
+    ///
+    /// The segment painted when sample is appended after prev.
+    ///
+    pub fn segment(prev: &Sample, sample: &Sample) -> brush::StrokeData{
+        brush::StrokeData{
+            pos0: prev.pos,
+            pos1: sample.pos,
+            p0: prev.pressure,
+            p1: sample.pressure,
+        }
+    }
+
+    pub fn segments(&self) -> impl Iterator<Item = brush::StrokeData> + '_{
+        self.samples.windows(2).map(|s| Self::segment(&s[0], &s[1]))
+    }
+
+    ///
+    /// Brushes take no parameters yet, a stroke recorded with some can't be reproduced.
+    ///
+    pub fn check_params(&self) -> Result<()>{
+        match self.params.keys().next(){
+            Some(name) => Err(Error::InvalidArgument(format!("BrushOp {:?} has no parameter {:?}", self.brush, name))),
+            None => Ok(()),
+        }
+    }
+}
+
+///
+/// Names are written as single tokens, so they can't be empty or hold whitespace or `=`.
+///
+fn check_name(what: &str, name: &str) -> Result<()>{
+    if name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == '='){
+        return Err(Error::InvalidArgument(format!("Invalid {} name {:?}", what, name)));
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StrokeLog{
+    pub strokes: Vec<RecordedStroke>,
+}
+
+fn invalid(line: usize, message: String) -> Error{
+    Error::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("line {}: {}", line + 1, message)))
+}
+
+fn parse<T: std::str::FromStr>(line: usize, token: Option<&str>, what: &str) -> Result<T>
+    where T::Err: std::fmt::Display
+{
+    let token = token.ok_or_else(|| invalid(line, format!("Missing {}", what)))?;
+    token.parse().map_err(|e| invalid(line, format!("Invalid {} {:?}: {}", what, token, e)))
+}
+
+impl StrokeLog{
+    pub fn new() -> Self{
+        Self::default()
+    }
+
+    ///
+    /// Fails without writing anything if a brush or parameter name can't be read back.
+    ///
+    pub fn write<W: Write>(&self, mut w: W) -> Result<()>{
+        for stroke in &self.strokes{
+            check_name("brush", &stroke.brush)?;
+            for name in stroke.params.keys(){
+                check_name("parameter", name)?;
+            }
+        }
+
+        writeln!(w, "{} {}", MAGIC, VERSION)?;
+        for stroke in &self.strokes{
+            write!(w, "stroke {} {}", stroke.layer, stroke.brush)?;
+            for (name, value) in &stroke.params{
+                write!(w, " {}={}", name, value)?;
+            }
+            writeln!(w)?;
+            for s in &stroke.samples{
+                writeln!(w, "sample {} {} {} {} {} {}", s.time, s.pos[0], s.pos[1], s.pressure, s.tilt[0], s.tilt[1])?;
+            }
+            writeln!(w, "end")?;
+        }
+        Ok(())
+    }
+
+    pub fn read<R: BufRead>(r: R) -> Result<Self>{
+        let mut lines = r.lines().enumerate();
+
+        let (_, header) = lines.next().ok_or_else(|| invalid(0, "Empty stroke log".to_string()))?;
+        let header = header?;
+        let mut tokens = header.split_whitespace();
+        if tokens.next() != Some(MAGIC){
+            return Err(invalid(0, format!("Not a stroke log, it has to start with {:?}", MAGIC)));
+        }
+        let version: u32 = parse(0, tokens.next(), "version")?;
+        if version == 0 || version > VERSION{
+            return Err(invalid(0, format!("Stroke log version {} is not supported, {} is the latest", version, VERSION)));
+        }
+
+        let mut log = Self::new();
+        // and the line it started on.
+        let mut current: Option<(usize, RecordedStroke)> = None;
+        for (i, line) in lines{
+            let line = line?;
+            let mut tokens = line.split_whitespace();
+            match tokens.next(){
+                None => {},
+                Some(comment) if comment.starts_with('#') => {},
+                Some("stroke") => {
+                    if current.is_some(){
+                        return Err(invalid(i, "stroke before end of the previous one".to_string()));
+                    }
+                    let layer = parse(i, tokens.next(), "layer")?;
+                    let brush = tokens.next().ok_or_else(|| invalid(i, "Missing brush".to_string()))?;
+                    let params = tokens
+                        .map(|param| -> Result<(String, f32)>{
+                            let (name, value) = param.split_once('=')
+                                .ok_or_else(|| invalid(i, format!("Expected <param>=<value>, got {:?}", param)))?;
+                            Ok((name.to_string(), parse(i, Some(value), name)?))
+                        })
+                        .collect::<Result<BTreeMap<String, f32>>>()?;
+                    current = Some((i, RecordedStroke::new(layer, brush, params)));
+                },
+                Some("sample") => {
+                    let (_, stroke) = current.as_mut().ok_or_else(|| invalid(i, "sample outside of a stroke".to_string()))?;
+                    stroke.samples.push(Sample{
+                        time: parse(i, tokens.next(), "time")?,
+                        pos: [parse(i, tokens.next(), "x")?, parse(i, tokens.next(), "y")?],
+                        pressure: parse(i, tokens.next(), "pressure")?,
+                        tilt: [parse(i, tokens.next(), "tilt x")?, parse(i, tokens.next(), "tilt y")?],
+                    });
+                },
+                Some("end") => {
+                    let (_, stroke) = current.take().ok_or_else(|| invalid(i, "end outside of a stroke".to_string()))?;
+                    log.strokes.push(stroke);
+                },
+                Some(other) => return Err(invalid(i, format!("Unknown record {:?}", other))),
+            }
+        }
+        if let Some((i, _)) = current{
+            return Err(invalid(i, "The stroke is not ended".to_string()));
+        }
+        Ok(log)
+    }
+
+    pub fn load(path: &Path) -> Result<Self>{
+        let file = std::fs::File::open(path)?;
+        Self::read(std::io::BufReader::new(file))
+            .map_err(|e| match e{
+                Error::Io(e) if e.kind() == std::io::ErrorKind::InvalidData => Error::Io(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("{}: {}", path.display(), e),
+                )),
+                e => e,
+            })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()>{
+        let mut w = std::io::BufWriter::new(std::fs::File::create(path)?);
+        self.write(&mut w)?;
+        w.flush()?;
+        Ok(())
+    }
+
+    ///
+    /// True if the file starts like a stroke log.
+    ///
+    pub fn is_log(path: &Path) -> bool{
+        let mut header = String::new();
+        std::fs::File::open(path)
+            .map(|file| std::io::BufReader::new(file).read_line(&mut header).is_ok())
+            .unwrap_or(false)
+            && header.split_whitespace().next() == Some(MAGIC)
+    }
+
+    ///
+    /// Queues the segments of all strokes on their layers, in the order they were recorded.
+    /// They are painted by the next Canvas::draw.
+    /// Fails before queueing anything if a stroke has parameters, see RecordedStroke::check_params.
+    ///
+    pub fn replay(&self, canvas: &canvas::Canvas, brushops: &brush::BrushOpManager) -> Result<()>{
+        for stroke in &self.strokes{
+            stroke.check_params()?;
+        }
+        for stroke in &self.strokes{
+            let brushop = brushops.arc_to(&stroke.brush)?;
+            let mut layer = canvas.layers.get(stroke.layer)
+                .ok_or(Error::MissingLayer(stroke.layer))?
+                .borrow_mut();
+            for data in stroke.segments(){
+                layer.queue_stroke(brush::Stroke::new(brushop.clone(), data));
+            }
+        }
+        Ok(())
+    }
+}
+
+///
+/// Records input samples while they are painted.
+///
+pub struct StrokeRecorder{
+    start: Instant,
+    log: StrokeLog,
+    current: Option<RecordedStroke>,
+}
+
+impl StrokeRecorder{
+    pub fn new() -> Self{
+        Self{
+            start: Instant::now(),
+            log: StrokeLog::new(),
+            current: None,
+        }
+    }
+
+    ///
+    /// Seconds since the recorder was created.
+    ///
+    pub fn time(&self) -> f64{
+        self.start.elapsed().as_secs_f64()
+    }
+
+    ///
+    /// Starts a stroke, ending the current one.
+    ///
+    pub fn begin(&mut self, layer: usize, brush: &str, params: BTreeMap<String, f32>){
+        self.end();
+        self.current = Some(RecordedStroke::new(layer, brush, params));
+    }
+
+    pub fn is_recording(&self) -> bool{
+        self.current.is_some()
+    }
+
+    ///
+    /// The layer of the current stroke, None if no stroke was begun.
+    ///
+    pub fn layer(&self) -> Option<usize>{
+        self.current.as_ref().map(|stroke| stroke.layer)
+    }
+
+    ///
+    /// Appends a sample to the current stroke and returns the segment to paint, the same one
+    /// replaying the log queues. None for the first sample or if no stroke was begun.
+    ///
+    pub fn sample(&mut self, sample: Sample) -> Option<brush::StrokeData>{
+        let stroke = self.current.as_mut()?;
+        let segment = stroke.samples.last().map(|prev| RecordedStroke::segment(prev, &sample));
+        stroke.samples.push(sample);
+        segment
+    }
+
+    pub fn end(&mut self){
+        if let Some(stroke) = self.current.take(){
+            if !stroke.samples.is_empty(){
+                self.log.strokes.push(stroke);
+            }
+        }
+    }
+
+    ///
+    /// The finished strokes.
+    ///
+    pub fn log(&self) -> &StrokeLog{
+        &self.log
+    }
+
+    ///
+    /// Ends the current stroke and returns everything recorded.
+    ///
+    pub fn finish(mut self) -> StrokeLog{
+        self.end();
+        self.log
+    }
+}
+
+impl Default for StrokeRecorder{
+    fn default() -> Self{
+        Self::new()
+    }
+}
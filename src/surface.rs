@@ -4,7 +4,7 @@ use crate::binding::GetBindGroupLayout;
 use crate::mesh;
 use crate::blendop;
 use crate::texture;
-use anyhow::*;
+use crate::error::Result;
 use std::sync::Arc;
 
 pub struct Surface{
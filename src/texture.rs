@@ -1,12 +1,144 @@
 use image::GenericImageView;
-use anyhow::*;
+use crate::error::{Error, Result};
 use crate::render_target::*;
 use crate::binding;
 use crate::binding::*;
+use crate::color;
+use crate::rect;
 use std::fs;
 use std::fs::File;
 use std::io::Read;
 
+///
+/// Size of a texel for the formats a Texture can be created with.
+///
+pub fn bytes_per_pixel(format: wgpu::TextureFormat) -> Result<u32>{
+    match format{
+        wgpu::TextureFormat::Rgba8Unorm     => Ok(4),
+        wgpu::TextureFormat::Rgba8UnormSrgb => Ok(4),
+        wgpu::TextureFormat::Bgra8Unorm     => Ok(4),
+        wgpu::TextureFormat::Bgra8UnormSrgb => Ok(4),
+        wgpu::TextureFormat::Rgba16Float    => Ok(8),
+        wgpu::TextureFormat::Rgba32Float    => Ok(16),
+        _ => Err(Error::UnsupportedFormat(format)),
+    }
+}
+
+///
+/// Features to request for a device on adapter.
+/// Rgba32Float is only filterable with adapter specific format features on adapters that can
+/// filter it. The feature is only requested then, so check_filterable can tell from the device.
+///
+pub fn device_features(adapter: &wgpu::Adapter) -> wgpu::Features{
+    let features = adapter.features() & wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES;
+    if adapter.get_texture_format_features(wgpu::TextureFormat::Rgba32Float).filterable{
+        features
+    }
+    else{
+        wgpu::Features::empty()
+    }
+}
+
+///
+/// Layers and composites are sampled with a linear filter and strokes are blended onto them,
+/// which wgpu only allows for filterable formats.
+/// Rgba32Float is filterable on devices requested with device_features if the adapter supports it.
+///
+pub fn check_filterable(device: &wgpu::Device, format: wgpu::TextureFormat) -> Result<()>{
+    let filterable = format.describe().guaranteed_format_features.filterable
+        || (format == wgpu::TextureFormat::Rgba32Float
+            && device.features().contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES));
+    if filterable{
+        Ok(())
+    }
+    else{
+        Err(Error::UnsupportedFormat(format))
+    }
+}
+
+///
+/// Encodes rgba floats into the texel layout of format.
+///
+pub fn rgba_f32_to_bytes(rgba: &[f32], format: wgpu::TextureFormat) -> Result<Vec<u8>>{
+    let unorm = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    match format{
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb =>
+            Ok(rgba.iter().map(|v| unorm(*v)).collect()),
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb =>
+            Ok(rgba.chunks(4).flat_map(|p| [unorm(p[2]), unorm(p[1]), unorm(p[0]), unorm(p[3])]).collect()),
+        wgpu::TextureFormat::Rgba16Float => {
+            let halfs: Vec<u16> = rgba.iter()
+                .map(|v| half::f16::from_f32(*v).to_bits())
+                .collect();
+            Ok(bytemuck::cast_slice(&halfs).to_vec())
+        },
+        wgpu::TextureFormat::Rgba32Float => Ok(bytemuck::cast_slice(rgba).to_vec()),
+        _ => Err(Error::UnsupportedFormat(format)),
+    }
+}
+
+///
+/// The space texel values of a texture are in.
+/// sRGB formats always hold sRGB values, the GPU converts when sampling and rendering.
+///
+pub fn storage_color_space(format: wgpu::TextureFormat, color_space: color::ColorSpace) -> color::ColorSpace{
+    if color::is_srgb_format(format){
+        color::ColorSpace::Srgb
+    }
+    else{
+        color_space
+    }
+}
+
+///
+/// Converts an sRGB encoded image into the texel layout of format, storing values in color_space.
+/// Images are flipped since textures are addressed from the bottom.
+///
+pub fn image_to_bytes(img: &image::DynamicImage, format: wgpu::TextureFormat, color_space: color::ColorSpace) -> Result<Vec<u8>>{
+    let img = img.flipv();
+    let color_space = storage_color_space(format, color_space);
+
+    if color_space == color::ColorSpace::Srgb{
+        match format{
+            wgpu::TextureFormat::Rgba8Unorm     => return Ok(img.to_rgba8().into_raw()),
+            wgpu::TextureFormat::Rgba8UnormSrgb => return Ok(img.to_rgba8().into_raw()),
+            wgpu::TextureFormat::Bgra8Unorm     => return Ok(img.to_bgra8().into_raw()),
+            wgpu::TextureFormat::Bgra8UnormSrgb => return Ok(img.to_bgra8().into_raw()),
+            _ => {},
+        }
+    }
+
+    // image upcasts 8 bit channels by shifting, which would turn 255 into less than 1.0.
+    let channels: Vec<f32> = if img.color().bytes_per_pixel() > img.color().channel_count(){
+        img.to_rgba16().into_raw().iter().map(|v| *v as f32 / u16::MAX as f32).collect()
+    }
+    else{
+        img.to_rgba8().into_raw().iter().map(|v| *v as f32 / u8::MAX as f32).collect()
+    };
+    let rgba: Vec<f32> = channels.chunks(4)
+        .flat_map(|p| color::convert([p[0], p[1], p[2], p[3]], color::ColorSpace::Srgb, color_space))
+        .collect();
+
+    rgba_f32_to_bytes(&rgba, format)
+}
+
+///
+/// Decodes one texel of format into rgba.
+///
+pub fn texel_to_rgba_f32(texel: &[u8], format: wgpu::TextureFormat) -> Result<[f32; 4]>{
+    let unorm = |v: u8| v as f32 / 255.0;
+    let half = |i: usize| half::f16::from_bits(u16::from_le_bytes([texel[2 * i], texel[2 * i + 1]])).to_f32();
+    let float = |i: usize| f32::from_le_bytes([texel[4 * i], texel[4 * i + 1], texel[4 * i + 2], texel[4 * i + 3]]);
+    match format{
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb =>
+            Ok([unorm(texel[0]), unorm(texel[1]), unorm(texel[2]), unorm(texel[3])]),
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb =>
+            Ok([unorm(texel[2]), unorm(texel[1]), unorm(texel[0]), unorm(texel[3])]),
+        wgpu::TextureFormat::Rgba16Float => Ok([half(0), half(1), half(2), half(3)]),
+        wgpu::TextureFormat::Rgba32Float => Ok([float(0), float(1), float(2), float(3)]),
+        _ => Err(Error::UnsupportedFormat(format)),
+    }
+}
 
 ///
 /// 
@@ -17,6 +149,12 @@ pub struct Texture{
     pub sampler: wgpu::Sampler,
     pub format: wgpu::TextureFormat,
     pub size: [u32; 2],
+    pub usage: wgpu::TextureUsages,
+    /// 1 if the texture has no mip chain.
+    /// Textures with a mip chain can't be used as RenderTarget, only level 0 can be written.
+    pub mip_level_count: u32,
+    /// The space the texel values are in if the format does not convert itself.
+    pub color_space: color::ColorSpace,
 
     pub bind_group_layout: BindGroupLayoutWithDesc,
     pub bind_group: wgpu::BindGroup,
@@ -29,6 +167,8 @@ impl Texture{
         path: &str,
         label: Option<&str>,
         format: wgpu::TextureFormat,
+        color_space: color::ColorSpace,
+        mipmapped: bool,
     ) -> Result<Self>{
         let mut f = File::open(path)?;
         let metadata = fs::metadata(path)?;
@@ -39,65 +179,92 @@ impl Texture{
             queue,
             &buffer,
             label,
-            format
+            format,
+            color_space,
+            mipmapped,
         )
     }
-    pub fn new_black(
+
+    ///
+    /// Number of mip levels down to 1x1.
+    ///
+    pub fn mip_level_count_for(size: [u32; 2]) -> u32{
+        32 - size[0].max(size[1]).max(1).leading_zeros()
+    }
+
+    ///
+    /// Usage of textures created by the constructors that don't take one.
+    ///
+    pub const DEFAULT_USAGE: wgpu::TextureUsages = wgpu::TextureUsages::TEXTURE_BINDING
+        .union(wgpu::TextureUsages::COPY_DST)
+        .union(wgpu::TextureUsages::COPY_SRC)
+        .union(wgpu::TextureUsages::RENDER_ATTACHMENT);
+
+    fn create(
+        device: &wgpu::Device,
         size: [u32; 2],
+        label: Option<&str>,
+        format: wgpu::TextureFormat,
+        color_space: color::ColorSpace,
+        mipmapped: bool,
+    ) -> Self{
+        Self::new(device, size, label, format, color_space, mipmapped, Self::DEFAULT_USAGE)
+    }
+
+    ///
+    /// Creates the texture and everything needed to sample it without uploading data.
+    /// The content is undefined until it is written or cleared.
+    ///
+    /// Mipmapped textures get a trilinear sampler.
+    /// Their mip chain has to be generated with a mipmap::MipmapGenerator after level 0 changed.
+    /// The sampler filters linearly, so format has to pass check_filterable.
+    ///
+    pub fn new(
         device: &wgpu::Device,
-        queue: &wgpu::Queue,
+        size: [u32; 2],
         label: Option<&str>,
-        format: wgpu::TextureFormat
-    ) -> Result<Self>{
-        let data: Vec<u8> = vec![0; (size[0] * size[1] * 4) as usize];
+        format: wgpu::TextureFormat,
+        color_space: color::ColorSpace,
+        mipmapped: bool,
+        usage: wgpu::TextureUsages,
+    ) -> Self{
+        let mip_level_count = if mipmapped {Self::mip_level_count_for(size)} else {1};
 
-        let extent = wgpu::Extent3d{
-            width: size[0],
-            height: size[1],
-            depth_or_array_layers: 1,
-        };
         let texture = device.create_texture(
             &wgpu::TextureDescriptor{
                 label,
-                size: extent,
-                mip_level_count: 1,
+                size: wgpu::Extent3d{
+                    width: size[0],
+                    height: size[1],
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count,
                 sample_count: 1,
                 dimension: wgpu::TextureDimension::D2,
                 format,
-                usage: wgpu::TextureUsages::TEXTURE_BINDING
-                    | wgpu::TextureUsages::COPY_DST
-                    | wgpu::TextureUsages::COPY_SRC
-                    | wgpu::TextureUsages::RENDER_ATTACHMENT
+                usage,
             }
         );
-        queue.write_texture(
-            wgpu::ImageCopyTexture{
-                aspect: wgpu::TextureAspect::All,
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-            },
-            &data,
-            wgpu::ImageDataLayout{
-                offset: 0,
-                bytes_per_row: std::num::NonZeroU32::new(4 * size[0]),
-                rows_per_image: std::num::NonZeroU32::new(size[1]),
-            },
-            extent,
-        );
         let texture_view_desc = wgpu::TextureViewDescriptor{
             format: Some(format),
             ..Default::default()
         };
         let view = texture.create_view(&texture_view_desc);
+
+        let (min_filter, mipmap_filter) = if mipmapped{
+            (wgpu::FilterMode::Linear, wgpu::FilterMode::Linear)
+        }
+        else{
+            (wgpu::FilterMode::Nearest, wgpu::FilterMode::Nearest)
+        };
         let sampler = device.create_sampler(
             &wgpu::SamplerDescriptor{
                 address_mode_u: wgpu::AddressMode::ClampToEdge,
                 address_mode_v: wgpu::AddressMode::ClampToEdge,
                 address_mode_w: wgpu::AddressMode::ClampToEdge,
                 mag_filter: wgpu::FilterMode::Linear,
-                min_filter: wgpu::FilterMode::Nearest,
-                mipmap_filter: wgpu::FilterMode::Nearest,
+                min_filter,
+                mipmap_filter,
                 ..Default::default()
             }
         );
@@ -109,107 +276,104 @@ impl Texture{
             .sampler(&sampler)
             .create(device, Some("Texture BindGroup"));
 
-        Ok(Self{
+        Self{
             texture,
             view,
             sampler,
             format,
+            size,
+            usage,
+            mip_level_count,
+            color_space,
             bind_group,
             bind_group_layout,
-            size,
-        })
+        }
     }
 
-    pub fn from_image(
-        device: &wgpu::Device,
-        queue: &wgpu::Queue,
-        img: &image::DynamicImage,
-        label: Option<&str>,
-        format: wgpu::TextureFormat
-    ) -> Result<Self>{
-        let img_data: Vec<u8> = match format{
-            wgpu::TextureFormat::Rgba8Unorm     => img.flipv().to_rgba8().into_raw(),
-            wgpu::TextureFormat::Rgba8UnormSrgb => img.flipv().to_rgba8().into_raw(),
-            wgpu::TextureFormat::Bgra8Unorm     => img.flipv().to_bgra8().into_raw(),
-            wgpu::TextureFormat::Bgra8UnormSrgb => img.flipv().to_bgra8().into_raw(),
-            _ => {
-                return Err(anyhow!("Format not supported"));
-            }
-        };
-
-        let dims = img.dimensions();
-
-        let extent = wgpu::Extent3d{
-            width: dims.0,
-            height: dims.1,
-            depth_or_array_layers: 1,
-        };
-        let texture = device.create_texture(
-            &wgpu::TextureDescriptor{
-                label,
-                size: extent,
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: wgpu::TextureDimension::D2,
-                format,
-                usage: wgpu::TextureUsages::TEXTURE_BINDING
-                    | wgpu::TextureUsages::COPY_DST
-                    | wgpu::TextureUsages::COPY_SRC
-                    | wgpu::TextureUsages::RENDER_ATTACHMENT
-            }
-        );
-
+    ///
+    /// Writes data to mip level 0 of the whole texture.
+    ///
+    fn write_all(&self, queue: &wgpu::Queue, data: &[u8]) -> Result<()>{
+        let bytes_per_pixel = bytes_per_pixel(self.format)?;
         queue.write_texture(
             wgpu::ImageCopyTexture{
                 aspect: wgpu::TextureAspect::All,
-                texture: &texture,
+                texture: &self.texture,
                 mip_level: 0,
                 origin: wgpu::Origin3d::ZERO,
             },
-            &img_data,
+            data,
             wgpu::ImageDataLayout{
                 offset: 0,
-                bytes_per_row: std::num::NonZeroU32::new(4 * dims.0),
-                rows_per_image: std::num::NonZeroU32::new(dims.1),
+                bytes_per_row: std::num::NonZeroU32::new(bytes_per_pixel * self.size[0]),
+                rows_per_image: std::num::NonZeroU32::new(self.size[1]),
+            },
+            wgpu::Extent3d{
+                width: self.size[0],
+                height: self.size[1],
+                depth_or_array_layers: 1,
             },
-            extent,
         );
-        let texture_view_desc = wgpu::TextureViewDescriptor{
-            format: Some(format),
-            ..Default::default()
-        };
+        Ok(())
+    }
 
-        let view = texture.create_view(&texture_view_desc);
-        let sampler = device.create_sampler(
-            &wgpu::SamplerDescriptor{
-                address_mode_u: wgpu::AddressMode::ClampToEdge,
-                address_mode_v: wgpu::AddressMode::ClampToEdge,
-                address_mode_w: wgpu::AddressMode::ClampToEdge,
-                mag_filter: wgpu::FilterMode::Linear,
-                min_filter: wgpu::FilterMode::Nearest,
-                mipmap_filter: wgpu::FilterMode::Nearest,
+    pub fn new_black(
+        size: [u32; 2],
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        label: Option<&str>,
+        format: wgpu::TextureFormat,
+        color_space: color::ColorSpace,
+        mipmapped: bool,
+    ) -> Result<Self>{
+        let texture = Self::create(device, size, label, format, color_space, mipmapped);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor{
+            label: Some("Clear Encoder"),
+        });
+        texture.clear(&mut encoder);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        Ok(texture)
+    }
+
+    ///
+    /// Clears every mip level to transparent black with clear render passes.
+    ///
+    pub fn clear(&self, encoder: &mut wgpu::CommandEncoder){
+        for level in 0..self.mip_level_count{
+            let view = self.texture.create_view(&wgpu::TextureViewDescriptor{
+                label: Some("Clear View"),
+                format: Some(self.format),
+                base_mip_level: level,
+                mip_level_count: std::num::NonZeroU32::new(1),
                 ..Default::default()
-            }
-        );
+            });
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor{
+                label: Some("Clear"),
+                color_attachments: &[view.color_attachment_clear()],
+                depth_stencil_attachment: None,
+            });
+        }
+    }
 
-        let bind_group_layout = Self::create_bind_group_layout(device, Some("Texture BindGroupLayout"));
+    pub fn from_image(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        label: Option<&str>,
+        format: wgpu::TextureFormat,
+        color_space: color::ColorSpace,
+        mipmapped: bool,
+    ) -> Result<Self>{
+        let img_data = image_to_bytes(img, format, color_space)?;
 
-        let bind_group = BindGroupBuilder::new(&bind_group_layout)
-            .texture(&view)
-            .sampler(&sampler)
-            .create(device, Some("Texture Bind Group"));
+        let dims = img.dimensions();
 
-        let size = [dims.0, dims.1];
+        let texture = Self::create(device, [dims.0, dims.1], label, format, color_space, mipmapped);
+        texture.write_all(queue, &img_data)?;
 
-        Ok(Self{
-            texture,
-            view,
-            sampler,
-            format,
-            bind_group,
-            bind_group_layout,
-            size,
-        })
+        Ok(texture)
     }
 
     pub fn from_bytes(
@@ -217,35 +381,239 @@ impl Texture{
         queue: &wgpu::Queue,
         bytes: &[u8],
         label: Option<&str>,
-        format: wgpu::TextureFormat
+        format: wgpu::TextureFormat,
+        color_space: color::ColorSpace,
+        mipmapped: bool,
     ) -> Result<Self>{
         let img = image::load_from_memory(bytes)?;
-        Self::from_image(device, queue, &img, label, format)
+        Self::from_image(device, queue, &img, label, format, color_space, mipmapped)
+    }
+
+    ///
+    /// Layout of the bind group every texture provides (texture_2d and sampler).
+    /// Can be inspected without a device.
+    ///
+    pub fn bind_group_layout_builder() -> BindGroupLayoutBuilder{
+        BindGroupLayoutBuilder::new()
+            .push_entry_all(binding::wgsl::texture_2d())
+            .push_entry_all(binding::wgsl::sampler())
+    }
+
+    ///
+    /// Reads back mip level 0 as rgba floats in image orientation.
+    /// Blocks until the GPU finished all submitted work.
+    ///
+    pub fn read_rgba_f32(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Vec<f32>>{
+        let bytes_per_pixel = bytes_per_pixel(self.format)?;
+        let unpadded_bytes_per_row = bytes_per_pixel * self.size[0];
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = (unpadded_bytes_per_row + align - 1) / align * align;
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor{
+            label: Some("Texture Readback Buffer"),
+            size: (padded_bytes_per_row * self.size[1]) as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor{
+            label: Some("Texture Readback Encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture{
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer{
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout{
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row),
+                    rows_per_image: std::num::NonZeroU32::new(self.size[1]),
+                },
+            },
+            wgpu::Extent3d{
+                width: self.size[0],
+                height: self.size[1],
+                depth_or_array_layers: 1,
+            }
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        pollster::block_on(mapping)?;
+
+        let mut rgba = Vec::with_capacity((self.size[0] * self.size[1] * 4) as usize);
+        {
+            let data = slice.get_mapped_range();
+            // Rows are stored bottom up, see image_to_bytes.
+            for row in data.chunks(padded_bytes_per_row as usize).rev(){
+                for texel in row[..unpadded_bytes_per_row as usize].chunks(bytes_per_pixel as usize){
+                    rgba.extend_from_slice(&texel_to_rgba_f32(texel, self.format)?);
+                }
+            }
+        }
+        buffer.unmap();
+
+        Ok(rgba)
+    }
+
+    ///
+    /// Reads back mip level 0 as an sRGB encoded 8 bit image, values outside of [0, 1] are clamped.
+    ///
+    pub fn read_to_image(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<image::RgbaImage>{
+        let color_space = storage_color_space(self.format, self.color_space);
+        let data: Vec<u8> = self.read_rgba_f32(device, queue)?.chunks(4)
+            .flat_map(|p| color::convert([p[0], p[1], p[2], p[3]], color_space, color::ColorSpace::Srgb))
+            .map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8)
+            .collect();
+        let expected = (self.size[0] * self.size[1] * 4) as usize;
+        let actual = data.len();
+        image::RgbaImage::from_raw(self.size[0], self.size[1], data).ok_or(Error::DataSize{expected, actual})
     }
 
     pub fn copy_all_to(&self, dst: &mut Texture, encoder: &mut wgpu::CommandEncoder){
+        self.copy_region_to(dst, encoder, &rect::Rect::full(self.size));
+    }
+
+    ///
+    /// Copies region of mip level 0 to the same region of dst.
+    ///
+    pub fn copy_region_to(&self, dst: &mut Texture, encoder: &mut wgpu::CommandEncoder, region: &rect::Rect){
+        if region.is_empty(){
+            return;
+        }
+
+        let origin = wgpu::Origin3d{
+            x: region.min[0],
+            y: region.min[1],
+            z: 0,
+        };
+
         encoder.copy_texture_to_texture(
             wgpu::ImageCopyTexture{
                 texture: &self.texture,
                 mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
+                origin,
                 aspect: wgpu::TextureAspect::All
             },
             wgpu::ImageCopyTexture{
                 texture: &dst.texture,
                 mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
+                origin,
                 aspect: wgpu::TextureAspect::All,
             },
             wgpu::Extent3d{
-                width: self.size[0],
-                height: self.size[1],
+                width: region.width(),
+                height: region.height(),
                 depth_or_array_layers: 1,
             }
         );
     }
 }
 
+///
+/// A depth and/or stencil texture.
+/// Has no sampler or bind group since it is only used as an attachment.
+/// sample_count has to match the one of the color attachments it is used with.
+///
+pub struct DepthTexture{
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub format: wgpu::TextureFormat,
+    pub size: [u32; 2],
+    pub sample_count: u32,
+}
+
+impl DepthTexture{
+    pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+    pub const DEPTH_STENCIL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24PlusStencil8;
+
+    pub fn new(
+        device: &wgpu::Device,
+        size: [u32; 2],
+        label: Option<&str>,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self{
+        let texture = device.create_texture(
+            &wgpu::TextureDescriptor{
+                label,
+                size: wgpu::Extent3d{
+                    width: size[0],
+                    height: size[1],
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            }
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self{
+            texture,
+            view,
+            format,
+            size,
+            sample_count,
+        }
+    }
+}
+
+///
+/// A multisampled render target.
+/// Can't be sampled, it has to be resolved into a Texture of the same size and format.
+///
+pub struct MultisampledTexture{
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub format: wgpu::TextureFormat,
+    pub size: [u32; 2],
+    pub sample_count: u32,
+}
+
+impl MultisampledTexture{
+    pub fn new(
+        device: &wgpu::Device,
+        size: [u32; 2],
+        label: Option<&str>,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self{
+        let texture = device.create_texture(
+            &wgpu::TextureDescriptor{
+                label,
+                size: wgpu::Extent3d{
+                    width: size[0],
+                    height: size[1],
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            }
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self{
+            texture,
+            view,
+            format,
+            size,
+            sample_count,
+        }
+    }
+}
+
 impl GetBindGroup for Texture{
     fn get_bind_group<'l>(&'l self) -> &'l wgpu::BindGroup {
         &self.bind_group
@@ -263,9 +631,7 @@ impl RenderTarget for Texture{
 
 impl ToBindGroupLayout for Texture{
     fn create_bind_group_layout(device: &wgpu::Device, label: Option<&str>) -> BindGroupLayoutWithDesc{
-        BindGroupLayoutBuilder::new()
-            .push_entry_all(binding::wgsl::texture_2d())
-            .push_entry_all(binding::wgsl::sampler())
+        Self::bind_group_layout_builder()
             .create(device, label)
     }
 }
@@ -285,3 +651,119 @@ impl GetBindGroupLayout for Texture{
         &self.bind_group_layout
     }
 }
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use wgpu::TextureFormat;
+
+    const FORMATS: [TextureFormat; 6] = [
+        TextureFormat::Rgba8Unorm,
+        TextureFormat::Rgba8UnormSrgb,
+        TextureFormat::Bgra8Unorm,
+        TextureFormat::Bgra8UnormSrgb,
+        TextureFormat::Rgba16Float,
+        TextureFormat::Rgba32Float,
+    ];
+
+    fn decode(bytes: &[u8], format: TextureFormat) -> Vec<[f32; 4]>{
+        let bpp = bytes_per_pixel(format).unwrap() as usize;
+        bytes.chunks(bpp).map(|texel| texel_to_rgba_f32(texel, format).unwrap()).collect()
+    }
+
+    #[test]
+    fn bytes_per_pixel_of_formats(){
+        assert_eq!(FORMATS.map(|format| bytes_per_pixel(format).unwrap()), [4, 4, 4, 4, 8, 16]);
+        assert!(matches!(bytes_per_pixel(TextureFormat::R8Unorm), Err(Error::UnsupportedFormat(_))));
+        assert!(rgba_f32_to_bytes(&[0.0; 4], TextureFormat::R8Unorm).is_err());
+        assert!(texel_to_rgba_f32(&[0; 4], TextureFormat::R8Unorm).is_err());
+    }
+
+    #[test]
+    fn texels_round_trip(){
+        let rgba = [0.0, 0.25, 0.5, 1.0, 1.0, 0.75, 0.125, 0.0];
+        for format in FORMATS{
+            let bytes = rgba_f32_to_bytes(&rgba, format).unwrap();
+            assert_eq!(bytes.len(), 2 * bytes_per_pixel(format).unwrap() as usize, "{:?}", format);
+
+            // unorm formats are off by at most half a step.
+            let tolerance = match format{
+                TextureFormat::Rgba16Float | TextureFormat::Rgba32Float => 0.0,
+                _ => 0.5 / 255.0 + 1e-6,
+            };
+            let decoded: Vec<f32> = decode(&bytes, format).concat();
+            for (value, expected) in decoded.iter().zip(rgba.iter()){
+                assert!((value - expected).abs() <= tolerance, "{:?}: {} != {}", format, value, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn bgra_swaps_red_and_blue(){
+        let bytes = rgba_f32_to_bytes(&[1.0, 0.5, 0.0, 0.25], TextureFormat::Bgra8Unorm).unwrap();
+        assert_eq!(bytes, [0, 128, 255, 64]);
+    }
+
+    #[test]
+    fn unorm_clamps_and_floats_do_not(){
+        let rgba = [1.5, -0.5, 2.5, -1.0];
+        assert_eq!(rgba_f32_to_bytes(&rgba, TextureFormat::Rgba8Unorm).unwrap(), [255, 0, 255, 0]);
+        assert_eq!(decode(&rgba_f32_to_bytes(&rgba, TextureFormat::Rgba16Float).unwrap(), TextureFormat::Rgba16Float), [rgba]);
+        assert_eq!(decode(&rgba_f32_to_bytes(&rgba, TextureFormat::Rgba32Float).unwrap(), TextureFormat::Rgba32Float), [rgba]);
+    }
+
+    #[test]
+    fn half_floats_round_to_nearest_even(){
+        let half = |v: f32| decode(&rgba_f32_to_bytes(&[v; 4], TextureFormat::Rgba16Float).unwrap(), TextureFormat::Rgba16Float)[0][0];
+        // halves have 10 fraction bits, so the step above 1.0 is 2^-10.
+        let step = 2f32.powi(-10);
+        assert_eq!(half(1.0 + step), 1.0 + step);
+        // a tie rounds to the even neighbour, above it rounds up.
+        assert_eq!(half(1.0 + step / 2.0), 1.0);
+        assert_eq!(half(1.0 + 3.0 * step / 2.0), 1.0 + 2.0 * step);
+        assert_eq!(half(1.0 + 3.0 * step / 4.0), 1.0 + step);
+        // relative error is at most half a step.
+        let third = half(1.0 / 3.0);
+        assert!((third - 1.0 / 3.0).abs() <= step / 4.0, "{}", third);
+        // out of range values become infinite.
+        assert_eq!(half(65520.0), f32::INFINITY);
+        assert_eq!(half(65504.0), 65504.0);
+    }
+
+    #[test]
+    fn images_are_flipped_and_converted(){
+        // top row red, bottom row half grey with half alpha.
+        let img = image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(1, 2, |_, y| {
+            if y == 0 {image::Rgba([255, 0, 0, 255])} else {image::Rgba([128, 128, 128, 128])}
+        }));
+
+        // sRGB storage keeps the 8 bit values.
+        assert_eq!(image_to_bytes(&img, TextureFormat::Rgba8UnormSrgb, color::ColorSpace::Linear).unwrap(), [128, 128, 128, 128, 255, 0, 0, 255]);
+        assert_eq!(image_to_bytes(&img, TextureFormat::Bgra8Unorm, color::ColorSpace::Srgb).unwrap(), [128, 128, 128, 128, 0, 0, 255, 255]);
+
+        let grey = 128.0 / 255.0;
+        let linear_grey = color::convert([grey; 4], color::ColorSpace::Srgb, color::ColorSpace::Linear)[0];
+        for format in [TextureFormat::Rgba16Float, TextureFormat::Rgba32Float]{
+            let texels = decode(&image_to_bytes(&img, format, color::ColorSpace::Linear).unwrap(), format);
+            assert_eq!(texels.len(), 2);
+            // alpha is not converted.
+            for (value, expected) in texels[0].iter().zip([linear_grey, linear_grey, linear_grey, grey]){
+                assert!((value - expected).abs() < 1e-3, "{:?}: {:?}", format, texels[0]);
+            }
+            assert_eq!(texels[1], [1.0, 0.0, 0.0, 1.0]);
+
+            let srgb = decode(&image_to_bytes(&img, format, color::ColorSpace::Srgb).unwrap(), format);
+            assert!((srgb[0][0] - grey).abs() < 1e-3, "{:?}: {:?}", format, srgb[0]);
+        }
+
+        // 8 bit channels are not upcast by shifting, white stays 1.0.
+        let white = image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(1, 1, image::Rgb([255, 255, 255])));
+        assert_eq!(decode(&image_to_bytes(&white, TextureFormat::Rgba16Float, color::ColorSpace::Linear).unwrap(), TextureFormat::Rgba16Float), [[1.0; 4]]);
+        let white16 = image::DynamicImage::ImageRgba16(image::ImageBuffer::from_pixel(1, 1, image::Rgba([u16::MAX; 4])));
+        assert_eq!(decode(&image_to_bytes(&white16, TextureFormat::Rgba32Float, color::ColorSpace::Linear).unwrap(), TextureFormat::Rgba32Float), [[1.0; 4]]);
+
+        // linear values in 8 bit lose precision but round to the nearest step.
+        let linear8 = image_to_bytes(&img, TextureFormat::Rgba8Unorm, color::ColorSpace::Linear).unwrap();
+        assert_eq!(linear8[..4], [(linear_grey * 255.0).round() as u8, (linear_grey * 255.0).round() as u8, (linear_grey * 255.0).round() as u8, 128]);
+    }
+}
@@ -0,0 +1,130 @@
+use crate::color;
+use crate::texture;
+use std::collections::HashMap;
+
+///
+/// What pooled textures are matched by.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TextureKey{
+    pub size: [u32; 2],
+    pub format: wgpu::TextureFormat,
+    pub usage: wgpu::TextureUsages,
+}
+
+impl TextureKey{
+    pub fn new(size: [u32; 2], format: wgpu::TextureFormat) -> Self{
+        Self{
+            size,
+            format,
+            usage: texture::Texture::DEFAULT_USAGE,
+        }
+    }
+
+    pub fn of(texture: &texture::Texture) -> Self{
+        Self{
+            size: texture.size,
+            format: texture.format,
+            usage: texture.usage,
+        }
+    }
+}
+
+///
+/// Hands out temporary textures and takes them back so they can be reused instead of being
+/// recreated.
+///
+/// Pooled textures have no mip chain. Their content is undefined when acquired.
+///
+pub struct TexturePool{
+    free: HashMap<TextureKey, Vec<texture::Texture>>,
+}
+
+impl TexturePool{
+    pub fn new() -> Self{
+        Self{
+            free: HashMap::new(),
+        }
+    }
+
+    pub fn acquire(&mut self, device: &wgpu::Device, key: TextureKey, color_space: color::ColorSpace) -> texture::Texture{
+        match self.free.get_mut(&key).and_then(|free| free.pop()){
+            Some(mut texture) => {
+                texture.color_space = color_space;
+                texture
+            },
+            None => texture::Texture::new(device, key.size, Some("Pooled Texture"), key.format, color_space, false, key.usage),
+        }
+    }
+
+    ///
+    /// Returns a texture to the pool.
+    /// Textures with a mip chain are dropped since acquire never hands those out.
+    ///
+    pub fn release(&mut self, texture: texture::Texture){
+        if texture.mip_level_count != 1{
+            return;
+        }
+        self.free.entry(TextureKey::of(&texture)).or_default().push(texture);
+    }
+
+    ///
+    /// Drops all free textures, e.g. after a resize made their size useless.
+    ///
+    pub fn clear(&mut self){
+        self.free.clear();
+    }
+}
+
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::error::Error;
+
+    fn device() -> Option<wgpu::Device>{
+        match pollster::block_on(crate::HeadlessCanvas::request_device(true)){
+            Ok((device, _queue)) => Some(device),
+            Err(Error::NoAdapter) => {
+                println!("skipped, no adapter");
+                None
+            },
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    fn num_free(pool: &TexturePool) -> usize{
+        pool.free.values().map(|free| free.len()).sum()
+    }
+
+    #[test]
+    fn textures_are_reused_by_key_until_cleared(){
+        let device = match device(){
+            Some(device) => device,
+            None => return,
+        };
+        let mut pool = TexturePool::new();
+        let key = TextureKey::new([4, 4], wgpu::TextureFormat::Rgba8Unorm);
+        let texture = pool.acquire(&device, key, color::ColorSpace::Linear);
+        assert_eq!(TextureKey::of(&texture), key);
+        pool.release(texture);
+        assert_eq!(num_free(&pool), 1);
+
+        // the free texture is handed out again with the requested color space.
+        let texture = pool.acquire(&device, key, color::ColorSpace::Srgb);
+        assert_eq!(num_free(&pool), 0);
+        assert_eq!(texture.color_space, color::ColorSpace::Srgb);
+        pool.release(texture);
+
+        // another key gets a new texture and leaves the free one in the pool.
+        let other = TextureKey::new([8, 4], wgpu::TextureFormat::Rgba8Unorm);
+        let texture = pool.acquire(&device, other, color::ColorSpace::Linear);
+        assert_eq!(TextureKey::of(&texture), other);
+        assert_eq!(num_free(&pool), 1);
+        pool.release(texture);
+        assert_eq!(num_free(&pool), 2);
+
+        pool.clear();
+        assert_eq!(num_free(&pool), 0);
+    }
+}
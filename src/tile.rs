@@ -0,0 +1,274 @@
+use crate::buffer;
+use crate::color;
+use crate::mesh::ModelTransforms;
+use crate::mipmap;
+use crate::texture;
+use crate::error::Result;
+use image::GenericImageView;
+use std::collections::HashMap;
+
+pub const TILE_SIZE: u32 = 256;
+
+///
+/// A part of a TiledTexture.
+/// Tiles at the right and top edge of a texture are smaller than TILE_SIZE.
+///
+pub struct Tile{
+    pub texture: texture::Texture,
+    pub mip_chain: mipmap::MipChain,
+    /// Transforms of the layer with the projection restricted to the tile.
+    pub transforms: buffer::UniformBindGroup<ModelTransforms>,
+}
+
+impl Tile{
+    fn new(device: &wgpu::Device, texture: texture::Texture) -> Self{
+        let mip_chain = mipmap::MipChain::new(device, &texture);
+        let transforms = buffer::UniformBindGroup::new(device);
+
+        Self{
+            texture,
+            mip_chain,
+            transforms,
+        }
+    }
+}
+
+///
+/// Sparse texture made of TILE_SIZE tiles that are only allocated once something is drawn to them.
+///
+/// Tile coordinates and texel rows are in texture space like texture::Texture, where row 0 is the
+/// bottom of the image.
+///
+pub struct TiledTexture{
+    tiles: HashMap<[u32; 2], Tile>,
+    pub size: [u32; 2],
+    pub format: wgpu::TextureFormat,
+    pub color_space: color::ColorSpace,
+}
+
+impl TiledTexture{
+    pub fn new(size: [u32; 2], format: wgpu::TextureFormat, color_space: color::ColorSpace) -> Self{
+        Self{
+            tiles: HashMap::new(),
+            size,
+            format,
+            color_space,
+        }
+    }
+
+    ///
+    /// Splits an image into tiles, tiles that are fully transparent are not allocated.
+    ///
+    pub fn from_image(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        format: wgpu::TextureFormat,
+        color_space: color::ColorSpace,
+    ) -> Result<Self>{
+        let dims = img.dimensions();
+        let mut tiled = Self::new([dims.0, dims.1], format, color_space);
+
+        for coord in tiled.coords(){
+            let (origin, size) = tiled.tile_rect(coord);
+            // The image starts at the top, textures at the bottom.
+            let tile_img = img.crop_imm(origin[0], dims.1 - origin[1] - size[1], size[0], size[1]);
+
+            if tile_img.to_rgba8().pixels().all(|p| p[3] == 0){
+                continue;
+            }
+
+            let tile_texture = texture::Texture::from_image(device, queue, &tile_img, Some("Tile"), format, color_space, true)?;
+            tiled.tiles.insert(coord, Tile::new(device, tile_texture));
+        }
+
+        Ok(tiled)
+    }
+
+    pub fn grid_size(&self) -> [u32; 2]{
+        [
+            (self.size[0] + TILE_SIZE - 1) / TILE_SIZE,
+            (self.size[1] + TILE_SIZE - 1) / TILE_SIZE,
+        ]
+    }
+
+    ///
+    /// All tile coordinates, allocated or not.
+    ///
+    pub fn coords(&self) -> impl Iterator<Item = [u32; 2]>{
+        let grid_size = self.grid_size();
+        (0..grid_size[1]).flat_map(move |y| (0..grid_size[0]).map(move |x| [x, y]))
+    }
+
+    ///
+    /// Coordinates of the tiles overlapping bounds, the min and max corner in uv of the whole
+    /// texture. Bounds outside of the texture are clamped.
+    ///
+    pub fn coords_in(&self, bounds: [[f32; 2]; 2]) -> impl Iterator<Item = [u32; 2]>{
+        let mut range = [(0, 0); 2];
+        for i in 0..2{
+            let size = self.size[i] as f32;
+            let min = (bounds[0][i] * size).floor().clamp(0.0, size) as u32;
+            let max = (bounds[1][i] * size).ceil().clamp(0.0, size) as u32;
+            if min < max{
+                range[i] = (min / TILE_SIZE, (max + TILE_SIZE - 1) / TILE_SIZE);
+            }
+        }
+        let [x, y] = range;
+        (y.0..y.1).flat_map(move |cy| (x.0..x.1).map(move |cx| [cx, cy]))
+    }
+
+    ///
+    /// Maps uv bounds of the whole texture to the uv of the tile at coord.
+    ///
+    pub fn tile_uv_bounds(&self, coord: [u32; 2], bounds: [[f32; 2]; 2]) -> [[f32; 2]; 2]{
+        let (origin, size) = self.tile_rect(coord);
+        bounds.map(|corner| [0, 1].map(|i| (corner[i] * self.size[i] as f32 - origin[i] as f32) / size[i] as f32))
+    }
+
+    ///
+    /// Origin and size in texels of the tile at coord.
+    ///
+    pub fn tile_rect(&self, coord: [u32; 2]) -> ([u32; 2], [u32; 2]){
+        let origin = [coord[0] * TILE_SIZE, coord[1] * TILE_SIZE];
+        let size = [
+            TILE_SIZE.min(self.size[0] - origin[0]),
+            TILE_SIZE.min(self.size[1] - origin[1]),
+        ];
+        (origin, size)
+    }
+
+    ///
+    /// Maps the [-1, 1] quad of a tile to its area in the [-1, 1] quad of the whole texture.
+    ///
+    pub fn tile_local(&self, coord: [u32; 2]) -> glm::Mat4{
+        let (origin, size) = self.tile_rect(coord);
+        let w = self.size[0] as f32;
+        let h = self.size[1] as f32;
+
+        let center = glm::vec3(
+            -1.0 + (2.0 * origin[0] as f32 + size[0] as f32) / w,
+            -1.0 + (2.0 * origin[1] as f32 + size[1] as f32) / h,
+            0.0
+        );
+        let scale = glm::vec3(size[0] as f32 / w, size[1] as f32 / h, 1.0);
+
+        glm::Mat4::new_translation(&center) * glm::Mat4::new_nonuniform_scaling(&scale)
+    }
+
+    pub fn get(&self, coord: [u32; 2]) -> Option<&Tile>{
+        self.tiles.get(&coord)
+    }
+
+    pub fn get_mut(&mut self, coord: [u32; 2]) -> Option<&mut Tile>{
+        self.tiles.get_mut(&coord)
+    }
+
+    pub fn tiles(&self) -> impl Iterator<Item = (&[u32; 2], &Tile)>{
+        self.tiles.iter()
+    }
+
+    pub fn tiles_mut(&mut self) -> impl Iterator<Item = (&[u32; 2], &mut Tile)>{
+        self.tiles.iter_mut()
+    }
+
+    pub fn num_allocated(&self) -> usize{
+        self.tiles.len()
+    }
+
+    ///
+    /// Allocates the tile at coord if it doesn't exist.
+    /// The clear is recorded into encoder.
+    ///
+    pub fn alloc(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, coord: [u32; 2]){
+        if !self.tiles.contains_key(&coord){
+            let (_, size) = self.tile_rect(coord);
+            let tile_texture = texture::Texture::new(device, size, Some("Tile"), self.format, self.color_space, true, texture::Texture::DEFAULT_USAGE);
+            tile_texture.clear(encoder);
+            self.tiles.insert(coord, Tile::new(device, tile_texture));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn tiled(size: [u32; 2]) -> TiledTexture{
+        TiledTexture::new(size, wgpu::TextureFormat::Rgba8Unorm, color::ColorSpace::Linear)
+    }
+
+    #[test]
+    fn edge_tiles_are_smaller(){
+        let tiles = tiled([600, 256]);
+        assert_eq!(tiles.grid_size(), [3, 1]);
+        assert_eq!(tiles.coords().collect::<Vec<_>>(), [[0, 0], [1, 0], [2, 0]]);
+        assert_eq!(tiles.tile_rect([0, 0]), ([0, 0], [256, 256]));
+        assert_eq!(tiles.tile_rect([2, 0]), ([512, 0], [88, 256]));
+
+        let tiles = tiled([1, 257]);
+        assert_eq!(tiles.grid_size(), [1, 2]);
+        assert_eq!(tiles.tile_rect([0, 1]), ([0, 256], [1, 1]));
+        assert_eq!(tiled([0, 0]).coords().count(), 0);
+    }
+
+    #[test]
+    fn tile_local_covers_the_tile(){
+        let tiles = tiled([512, 384]);
+        let m = tiles.tile_local([1, 1]);
+        // the corners of the tile quad land on the texel corners of the tile.
+        let min = m * glm::vec4(-1.0, -1.0, 0.0, 1.0);
+        let max = m * glm::vec4(1.0, 1.0, 0.0, 1.0);
+        assert!((min.x - 0.0).abs() < 1e-6 && (min.y - (2.0 * 256.0 / 384.0 - 1.0)).abs() < 1e-6, "{:?}", min);
+        assert!((max.x - 1.0).abs() < 1e-6 && (max.y - 1.0).abs() < 1e-6, "{:?}", max);
+    }
+
+    #[test]
+    fn coords_in_bounds(){
+        let tiles = tiled([600, 512]);
+        let coords = |bounds| tiles.coords_in(bounds).collect::<Vec<_>>();
+
+        assert_eq!(coords([[0.0, 0.0], [1.0, 1.0]]), tiles.coords().collect::<Vec<_>>());
+        assert_eq!(coords([[0.5, 0.1], [0.55, 0.2]]), [[1, 0]]);
+        // the right edge of a tile is exclusive.
+        assert_eq!(coords([[0.0, 0.0], [256.0 / 600.0, 0.1]]), [[0, 0]]);
+        assert_eq!(coords([[0.4, 0.4], [0.9, 0.6]]), [[0, 0], [1, 0], [2, 0], [0, 1], [1, 1], [2, 1]]);
+        // bounds outside of the texture are clamped, or cover nothing.
+        assert_eq!(coords([[-2.0, 0.9], [0.1, 3.0]]), [[0, 1]]);
+        assert_eq!(tiles.coords_in([[1.5, 0.0], [2.0, 1.0]]).count(), 0);
+        assert_eq!(tiles.coords_in([[0.5, 0.5], [0.4, 0.6]]).count(), 0);
+    }
+
+    #[test]
+    fn uv_bounds_of_tiles(){
+        let tiles = tiled([600, 512]);
+        let bounds = [[0.5, 0.25], [1.0, 0.75]];
+        assert_eq!(tiles.tile_uv_bounds([0, 0], [[0.0, 0.0], [1.0, 1.0]]), [[0.0, 0.0], [600.0 / 256.0, 2.0]]);
+        assert_eq!(tiles.tile_uv_bounds([1, 1], bounds), [[44.0 / 256.0, -0.5], [344.0 / 256.0, 0.5]]);
+        // the last column is 88 texels wide.
+        assert_eq!(tiles.tile_uv_bounds([2, 0], bounds), [[-212.0 / 88.0, 0.5], [1.0, 1.5]]);
+    }
+
+    #[test]
+    fn tiles_are_allocated_on_demand(){
+        let (device, _queue) = match pollster::block_on(crate::HeadlessCanvas::request_device(true)){
+            Ok(device) => device,
+            Err(crate::Error::NoAdapter) => {
+                println!("skipped, no adapter");
+                return;
+            },
+            Err(err) => panic!("{}", err),
+        };
+        let mut tiles = tiled([300, 100]);
+        assert_eq!(tiles.num_allocated(), 0);
+        assert!(tiles.get([1, 0]).is_none());
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor{label: None});
+        tiles.alloc(&device, &mut encoder, [1, 0]);
+        tiles.alloc(&device, &mut encoder, [1, 0]);
+        assert_eq!(tiles.num_allocated(), 1);
+        assert!(tiles.get([0, 0]).is_none());
+        // the edge tile is only as large as the rest of the texture.
+        assert_eq!(tiles.get([1, 0]).unwrap().texture.size, [44, 100]);
+    }
+}
@@ -0,0 +1,84 @@
+use crate::error::Result;
+use crate::cache;
+use crate::color;
+use crate::mesh;
+use crate::mesh::Drawable;
+use crate::pipeline;
+use crate::program;
+use crate::render_target::ColorAttachment;
+use crate::texture;
+use crate::vert;
+use std::sync::Arc;
+
+///
+/// Maps the composite in the working format of the canvas to the surface format.
+///
+pub struct Tonemap{
+    drawable: mesh::Mesh<vert::Vert2>,
+    render_pipeline: Arc<pipeline::RenderPipeline>,
+}
+
+impl Tonemap{
+    ///
+    /// color_space is the space of the composite.
+    /// Linear composites are encoded to sRGB unless format does it itself.
+    ///
+    pub fn new(device: &wgpu::Device, cache: &cache::PipelineCache, format: wgpu::TextureFormat, color_space: color::ColorSpace) -> Result<Self>{
+        let drawable = mesh::Mesh::<vert::Vert2>::new(device, &vert::Vert2::QUAD_VERTS, &vert::Vert2::QUAD_IDXS)?;
+
+        let encode_srgb = color_space == color::ColorSpace::Linear && !color::is_srgb_format(format);
+        let defines: &[(&str, Option<&str>)] = if encode_srgb {&[("ENCODE_SRGB", None)]} else {&[]};
+
+        let render_pipeline = cache.render_pipeline(&format!("Tonemap {:?} {:?}", format, defines), |device|{
+            let bind_group_layouts = pipeline::create_named_bind_group_layouts(device, Self::bind_group_layouts());
+
+            let render_pipeline_layout = pipeline::PipelineLayoutBuilder::new()
+                .push_all_named(&bind_group_layouts)
+                .create(device, None);
+
+            let vert_shader = cache.shader_with_shaderc(include_str!("shaders/vert_screen.glsl"), shaderc::ShaderKind::Vertex, "main", &[], Some("VertexShader"))?;
+            let frag_shader = cache.shader_with_shaderc(include_str!("shaders/frag_tonemap.glsl"), shaderc::ShaderKind::Fragment, "main", defines, Some("FragmentShader"))?;
+
+            let vertex_state = pipeline::VertexStateBuilder::new(&vert_shader)
+                .push_named("model", drawable.vert_buffer_layout())
+                .set_entry_point("main")
+                .build();
+
+            let fragment_state = pipeline::FragmentStateBuilder::new(&frag_shader)
+                .set_entry_point("main")
+                .build();
+
+            program::new(
+                device,
+                format,
+                &render_pipeline_layout,
+                &vertex_state,
+                &fragment_state,
+            )
+        })?;
+
+        Ok(Self{
+            drawable,
+            render_pipeline,
+        })
+    }
+
+    pub fn bind_group_layouts() -> pipeline::NamedBindGroupLayouts{
+        vec![
+            ("src", texture::Texture::bind_group_layout_builder()),
+        ]
+    }
+
+    pub fn draw(&self, encoder: &mut wgpu::CommandEncoder, dst: &wgpu::TextureView, src: &wgpu::BindGroup) -> Result<()>{
+        let mut render_pass = pipeline::RenderPassBuilder::new()
+            .push_color_attachment(dst.color_attachment_clear())
+            .begin(encoder, Some("Tonemap"));
+        let mut render_pass_pipeline = render_pass.set_pipeline(&self.render_pipeline);
+
+        render_pass_pipeline.try_set_bind_group("src", src, &[])?;
+
+        self.drawable.draw(&mut render_pass_pipeline);
+
+        Ok(())
+    }
+}
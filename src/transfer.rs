@@ -0,0 +1,547 @@
+use crate::color;
+use crate::error::{Error, Result};
+use crate::rect;
+use crate::texture;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+type SubmittedFuture = Pin<Box<dyn Future<Output = std::result::Result<(), wgpu::BufferAsyncError>> + Send>>;
+
+///
+/// Set by Transfers::submit once the commands of a transfer were submitted.
+///
+#[derive(Default)]
+struct Signal{
+    future: Option<SubmittedFuture>,
+    // kept so polling again after completion doesn't poll the finished future.
+    result: Option<std::result::Result<(), wgpu::BufferAsyncError>>,
+    // of a poll before submission, woken by Transfers::submit.
+    waker: Option<Waker>,
+}
+
+impl Signal{
+    fn submit(&mut self, future: SubmittedFuture){
+        self.future = Some(future);
+        if let Some(waker) = self.waker.take(){
+            waker.wake();
+        }
+    }
+}
+
+enum Queued{
+    Download(Arc<wgpu::Buffer>, wgpu::BufferAddress, Arc<Mutex<Signal>>),
+    Upload(Arc<Mutex<Signal>>),
+}
+
+fn noop_waker() -> Waker{
+    fn clone(_: *const ()) -> RawWaker{
+        RawWaker::new(std::ptr::null(), &VTABLE)
+    }
+    fn noop(_: *const ()){}
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+
+    unsafe{Waker::from_raw(RawWaker::new(std::ptr::null(), &VTABLE))}
+}
+
+///
+/// Polls a future once without blocking.
+/// wgpu only makes progress when the device is polled, e.g. with wgpu::Maintain::Poll.
+///
+fn poll_now<F: Future + Unpin + ?Sized>(future: &mut F) -> Poll<F::Output>{
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    Pin::new(future).poll(&mut cx)
+}
+
+fn poll_signal(signal: &Mutex<Signal>, cx: &mut Context) -> Poll<Result<()>>{
+    let mut signal = signal.lock().unwrap();
+    if let Some(result) = &signal.result{
+        return Poll::Ready(result.clone().map_err(Error::from));
+    }
+    match signal.future.as_mut(){
+        Some(future) => match future.as_mut().poll(cx){
+            Poll::Ready(result) => {
+                signal.future = None;
+                signal.result = Some(result.clone());
+                Poll::Ready(result.map_err(Error::from))
+            },
+            Poll::Pending => Poll::Pending,
+        },
+        None => {
+            signal.waker = Some(cx.waker().clone());
+            Poll::Pending
+        },
+    }
+}
+
+///
+/// Mip level 0 of a texture read back by a Download.
+/// Rows are tightly packed and in image orientation, top row first.
+///
+pub struct TextureData{
+    pub size: [u32; 2],
+    pub format: wgpu::TextureFormat,
+    pub color_space: color::ColorSpace,
+    pub bytes: Vec<u8>,
+}
+
+impl TextureData{
+    pub fn to_rgba_f32(&self) -> Result<Vec<f32>>{
+        let bytes_per_pixel = texture::bytes_per_pixel(self.format)? as usize;
+        let mut rgba = Vec::with_capacity((self.size[0] * self.size[1] * 4) as usize);
+        for texel in self.bytes.chunks(bytes_per_pixel){
+            rgba.extend_from_slice(&texture::texel_to_rgba_f32(texel, self.format)?);
+        }
+        Ok(rgba)
+    }
+
+    ///
+    /// sRGB encoded 8 bit image, values outside of [0, 1] are clamped.
+    ///
+    pub fn to_image(&self) -> Result<image::RgbaImage>{
+        let color_space = texture::storage_color_space(self.format, self.color_space);
+        let data: Vec<u8> = self.to_rgba_f32()?.chunks(4)
+            .flat_map(|p| color::convert([p[0], p[1], p[2], p[3]], color_space, color::ColorSpace::Srgb))
+            .map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8)
+            .collect();
+        let expected = (self.size[0] * self.size[1] * 4) as usize;
+        let actual = data.len();
+        image::RgbaImage::from_raw(self.size[0], self.size[1], data).ok_or(Error::DataSize{expected, actual})
+    }
+}
+
+///
+/// Handle to a texture readback.
+///
+/// Can be awaited or polled with try_take from the render loop.
+/// Completes after the encoder was submitted with Transfers::submit and the device was polled.
+/// The buffer goes back to the Transfers once the data was taken.
+///
+pub struct Download{
+    buffer: Arc<wgpu::Buffer>,
+    // the part of buffer the texture was copied to.
+    len: wgpu::BufferAddress,
+    capacity: wgpu::BufferAddress,
+    pool: Arc<Mutex<Vec<(Arc<wgpu::Buffer>, wgpu::BufferAddress)>>>,
+    signal: Arc<Mutex<Signal>>,
+    size: [u32; 2],
+    format: wgpu::TextureFormat,
+    color_space: color::ColorSpace,
+    padded_bytes_per_row: u32,
+    taken: bool,
+}
+
+impl Download{
+    fn read(&mut self) -> Result<TextureData>{
+        let bytes_per_pixel = texture::bytes_per_pixel(self.format)?;
+        let unpadded_bytes_per_row = (bytes_per_pixel * self.size[0]) as usize;
+
+        let mut bytes = Vec::with_capacity(unpadded_bytes_per_row * self.size[1] as usize);
+        {
+            let data = self.buffer.slice(..self.len).get_mapped_range();
+            // Rows are stored bottom up, see texture::image_to_bytes.
+            for row in data.chunks(self.padded_bytes_per_row as usize).rev(){
+                bytes.extend_from_slice(&row[..unpadded_bytes_per_row]);
+            }
+        }
+        self.buffer.unmap();
+        self.taken = true;
+
+        let mut pool = self.pool.lock().unwrap();
+        if pool.len() < Transfers::POOL_SIZE{
+            pool.push((self.buffer.clone(), self.capacity));
+        }
+
+        Ok(TextureData{
+            size: self.size,
+            format: self.format,
+            color_space: self.color_space,
+            bytes,
+        })
+    }
+
+    ///
+    /// Returns the data if the readback finished, without blocking.
+    ///
+    pub fn try_take(&mut self) -> Result<Option<TextureData>>{
+        match poll_now(self){
+            Poll::Ready(result) => result.map(Some),
+            Poll::Pending => Ok(None),
+        }
+    }
+}
+
+impl Future for Download{
+    type Output = Result<TextureData>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output>{
+        let this = self.get_mut();
+        if this.taken{
+            return Poll::Ready(Err(Error::InvalidArgument("Download was already taken".to_string())));
+        }
+        match poll_signal(&this.signal, cx){
+            Poll::Ready(Ok(())) => Poll::Ready(this.read()),
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+///
+/// Handle to a region upload, completes when the GPU executed the copy.
+/// The data is copied into the staging belt immediately, so waiting is only needed to know when
+/// the texture holds it.
+///
+pub struct Upload{
+    signal: Arc<Mutex<Signal>>,
+}
+
+impl Upload{
+    pub fn is_done(&mut self) -> Result<bool>{
+        match poll_now(self){
+            Poll::Ready(result) => result.map(|_| true),
+            Poll::Pending => Ok(false),
+        }
+    }
+}
+
+impl Future for Upload{
+    type Output = Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output>{
+        poll_signal(&self.signal, cx)
+    }
+}
+
+///
+/// Uploads and readbacks that are recorded into the frame's encoder instead of stalling it.
+///
+/// Uploads go through a wgpu::util::StagingBelt into a buffer that is kept and grown to the
+/// largest upload, and are copied from there into the texture. Readbacks go into mapped buffers
+/// that are reused once their data was taken.
+/// The encoders transfers were recorded into have to be submitted with submit, in the order they
+/// were recorded.
+///
+pub struct Transfers{
+    belt: wgpu::util::StagingBelt,
+    // the belt can only copy to buffers, uploads are copied to textures from there.
+    upload: Option<(wgpu::Buffer, wgpu::BufferAddress)>,
+    // free download buffers and their size.
+    downloads: Arc<Mutex<Vec<(Arc<wgpu::Buffer>, wgpu::BufferAddress)>>>,
+    queued: Vec<Queued>,
+    recalls: Vec<Pin<Box<dyn Future<Output = ()> + Send>>>,
+}
+
+impl Transfers{
+    pub const CHUNK_SIZE: wgpu::BufferAddress = 1 << 20;
+
+    /// Download buffers kept for reuse.
+    pub const POOL_SIZE: usize = 4;
+
+    pub fn new() -> Self{
+        Self{
+            belt: wgpu::util::StagingBelt::new(Self::CHUNK_SIZE),
+            upload: None,
+            downloads: Arc::new(Mutex::new(Vec::new())),
+            queued: Vec::new(),
+            recalls: Vec::new(),
+        }
+    }
+
+    fn padded_bytes_per_row(format: wgpu::TextureFormat, width: u32) -> Result<u32>{
+        let unpadded_bytes_per_row = texture::bytes_per_pixel(format)? * width;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        Ok((unpadded_bytes_per_row + align - 1) / align * align)
+    }
+
+    ///
+    /// Uploads data to region of mip level 0.
+    /// data holds the rows of region tightly packed in texture order, starting at region.min[1].
+    ///
+    pub fn upload_region(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, texture: &texture::Texture, region: &rect::Rect, data: &[u8]) -> Result<Upload>{
+        let bytes_per_pixel = texture::bytes_per_pixel(texture.format)?;
+        let unpadded_bytes_per_row = (bytes_per_pixel * region.width()) as usize;
+        let expected = unpadded_bytes_per_row * region.height() as usize;
+        if data.len() != expected{
+            return Err(Error::DataSize{expected, actual: data.len()});
+        }
+        if region.intersect(&rect::Rect::full(texture.size)) != *region{
+            return Err(Error::OutOfBounds(*region));
+        }
+
+        let signal = Arc::new(Mutex::new(Signal::default()));
+        if region.is_empty(){
+            signal.lock().unwrap().submit(Box::pin(async {std::result::Result::Ok(())}));
+            return Ok(Upload{signal});
+        }
+
+        let padded_bytes_per_row = Self::padded_bytes_per_row(texture.format, region.width())?;
+        let size = (padded_bytes_per_row * region.height()) as wgpu::BufferAddress;
+
+        // Copies in an encoder run in order, so every upload can start at the beginning.
+        if self.upload.as_ref().map_or(true, |(_, capacity)| *capacity < size){
+            let capacity = size.max(Self::CHUNK_SIZE);
+            self.upload = Some((device.create_buffer(&wgpu::BufferDescriptor{
+                label: Some("Upload Buffer"),
+                size: capacity,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }), capacity));
+        }
+        let buffer = &self.upload.as_ref().unwrap().0;
+
+        {
+            let mut view = self.belt.write_buffer(encoder, buffer, 0, wgpu::BufferSize::new(size).unwrap(), device);
+            for (dst, src) in view.chunks_mut(padded_bytes_per_row as usize).zip(data.chunks(unpadded_bytes_per_row)){
+                dst[..unpadded_bytes_per_row].copy_from_slice(src);
+            }
+        }
+
+        encoder.copy_buffer_to_texture(
+            wgpu::ImageCopyBuffer{
+                buffer,
+                layout: wgpu::ImageDataLayout{
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row),
+                    rows_per_image: std::num::NonZeroU32::new(region.height()),
+                },
+            },
+            wgpu::ImageCopyTexture{
+                texture: &texture.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d{
+                    x: region.min[0],
+                    y: region.min[1],
+                    z: 0,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::Extent3d{
+                width: region.width(),
+                height: region.height(),
+                depth_or_array_layers: 1,
+            }
+        );
+
+        self.queued.push(Queued::Upload(signal.clone()));
+        Ok(Upload{signal})
+    }
+
+    ///
+    /// Uploads an image to the whole texture, see texture::image_to_bytes.
+    ///
+    pub fn upload_image(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, texture: &texture::Texture, img: &image::DynamicImage) -> Result<Upload>{
+        let data = texture::image_to_bytes(img, texture.format, texture.color_space)?;
+        self.upload_region(device, encoder, texture, &rect::Rect::full(texture.size), &data)
+    }
+
+    ///
+    /// Reads back mip level 0 of texture.
+    ///
+    pub fn download(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, texture: &texture::Texture) -> Result<Download>{
+        let padded_bytes_per_row = Self::padded_bytes_per_row(texture.format, texture.size[0])?;
+
+        let len = (padded_bytes_per_row * texture.size[1]) as wgpu::BufferAddress;
+
+        let reused = {
+            let mut pool = self.downloads.lock().unwrap();
+            let fitting = pool.iter().enumerate()
+                .filter(|(_, (_, capacity))| *capacity >= len)
+                .min_by_key(|(_, (_, capacity))| *capacity)
+                .map(|(i, _)| i);
+            fitting.map(|i| pool.swap_remove(i))
+        };
+        let (buffer, capacity) = reused.unwrap_or_else(|| (Arc::new(device.create_buffer(&wgpu::BufferDescriptor{
+            label: Some("Download Buffer"),
+            size: len,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })), len));
+
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture{
+                texture: &texture.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer{
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout{
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row),
+                    rows_per_image: std::num::NonZeroU32::new(texture.size[1]),
+                },
+            },
+            wgpu::Extent3d{
+                width: texture.size[0],
+                height: texture.size[1],
+                depth_or_array_layers: 1,
+            }
+        );
+
+        let signal = Arc::new(Mutex::new(Signal::default()));
+        self.queued.push(Queued::Download(buffer.clone(), len, signal.clone()));
+
+        Ok(Download{
+            buffer,
+            len,
+            capacity,
+            pool: self.downloads.clone(),
+            signal,
+            size: texture.size,
+            format: texture.format,
+            color_space: texture.color_space,
+            padded_bytes_per_row,
+            taken: false,
+        })
+    }
+
+    ///
+    /// Submits the encoder the transfers were recorded into.
+    /// Starts mapping the readbacks, wakes the transfers that were awaited before and reclaims
+    /// staging buffers the GPU is done with.
+    ///
+    pub fn submit(&mut self, queue: &wgpu::Queue, encoder: wgpu::CommandEncoder){
+        self.belt.finish();
+        queue.submit(std::iter::once(encoder.finish()));
+
+        for queued in self.queued.drain(..){
+            match queued{
+                Queued::Download(buffer, len, signal) => {
+                    let map = buffer.slice(..len).map_async(wgpu::MapMode::Read);
+                    // The future borrows nothing but has to keep the buffer alive.
+                    signal.lock().unwrap().submit(Box::pin(async move{
+                        let result = map.await;
+                        drop(buffer);
+                        result
+                    }));
+                },
+                Queued::Upload(signal) => {
+                    let done = queue.on_submitted_work_done();
+                    signal.lock().unwrap().submit(Box::pin(async move{
+                        done.await;
+                        std::result::Result::Ok(())
+                    }));
+                },
+            }
+        }
+
+        // recalled chunks are reused by the belt, finished recalls are dropped.
+        self.recalls.push(Box::pin(self.belt.recall()));
+        self.recalls.retain_mut(|recall| poll_now(recall).is_pending());
+    }
+}
+
+impl Default for Transfers{
+    fn default() -> Self{
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::task::Wake;
+
+    struct Flag(AtomicBool);
+
+    impl Wake for Flag{
+        fn wake(self: Arc<Self>){
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn uploads_polled_before_submission_are_woken_and_read_back(){
+        let (device, queue) = match pollster::block_on(crate::HeadlessCanvas::request_device(true)){
+            Ok(device) => device,
+            Err(Error::NoAdapter) => {
+                println!("skipped, no adapter");
+                return;
+            },
+            Err(err) => panic!("{}", err),
+        };
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        let tex = texture::Texture::new(&device, [3, 2], None, format, color::ColorSpace::Linear, false, texture::Texture::DEFAULT_USAGE);
+        let mut transfers = Transfers::new();
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor{label: None});
+        let data: Vec<u8> = (0..24).collect();
+        let mut upload = transfers.upload_region(&device, &mut encoder, &tex, &rect::Rect::full([3, 2]), &data).unwrap();
+        let mut download = transfers.download(&device, &mut encoder, &tex).unwrap();
+
+        let flag = Arc::new(Flag(AtomicBool::new(false)));
+        let waker = Waker::from(flag.clone());
+        assert!(Pin::new(&mut upload).poll(&mut Context::from_waker(&waker)).is_pending());
+        assert!(!flag.0.load(Ordering::SeqCst));
+
+        transfers.submit(&queue, encoder);
+        assert!(flag.0.load(Ordering::SeqCst));
+
+        device.poll(wgpu::Maintain::Wait);
+        assert!(upload.is_done().unwrap());
+        // rows come back top row first, the upload was in texture order.
+        let read = download.try_take().unwrap().unwrap();
+        assert_eq!(read.bytes, [&data[12..], &data[..12]].concat());
+        assert!(download.try_take().is_err());
+    }
+
+    #[test]
+    fn uploads_are_checked(){
+        let (device, _queue) = match pollster::block_on(crate::HeadlessCanvas::request_device(true)){
+            Ok(device) => device,
+            Err(Error::NoAdapter) => {
+                println!("skipped, no adapter");
+                return;
+            },
+            Err(err) => panic!("{}", err),
+        };
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        let tex = texture::Texture::new(&device, [4, 4], None, format, color::ColorSpace::Linear, false, texture::Texture::DEFAULT_USAGE);
+        let mut transfers = Transfers::new();
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor{label: None});
+
+        let region = rect::Rect::new([2, 2], [5, 3]);
+        assert!(matches!(transfers.upload_region(&device, &mut encoder, &tex, &region, &[0; 12]), Err(Error::OutOfBounds(_))));
+        assert!(matches!(transfers.upload_region(&device, &mut encoder, &tex, &rect::Rect::full([4, 4]), &[0; 4]), Err(Error::DataSize{expected: 64, actual: 4})));
+        // empty uploads are done right away.
+        let mut empty = transfers.upload_region(&device, &mut encoder, &tex, &rect::Rect::new([1, 1], [1, 3]), &[]).unwrap();
+        assert!(empty.is_done().unwrap());
+    }
+
+    #[test]
+    fn buffers_are_reused(){
+        let (device, queue) = match pollster::block_on(crate::HeadlessCanvas::request_device(true)){
+            Ok(device) => device,
+            Err(Error::NoAdapter) => {
+                println!("skipped, no adapter");
+                return;
+            },
+            Err(err) => panic!("{}", err),
+        };
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        let big = texture::Texture::new(&device, [64, 64], None, format, color::ColorSpace::Linear, false, texture::Texture::DEFAULT_USAGE);
+        let small = texture::Texture::new(&device, [2, 2], None, format, color::ColorSpace::Linear, false, texture::Texture::DEFAULT_USAGE);
+        let mut transfers = Transfers::new();
+
+        for (i, tex) in [&big, &small, &big].into_iter().enumerate(){
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor{label: None});
+            let data = vec![i as u8; (tex.size[0] * tex.size[1] * 4) as usize];
+            let upload = transfers.upload_region(&device, &mut encoder, tex, &rect::Rect::full(tex.size), &data).unwrap();
+            let download = transfers.download(&device, &mut encoder, tex).unwrap();
+            // the buffer of the previous download was taken from the pool.
+            assert!(transfers.downloads.lock().unwrap().is_empty());
+            transfers.submit(&queue, encoder);
+
+            device.poll(wgpu::Maintain::Wait);
+            pollster::block_on(upload).unwrap();
+            assert_eq!(pollster::block_on(download).unwrap().bytes, data);
+            assert_eq!(transfers.downloads.lock().unwrap().len(), 1);
+            assert_eq!(transfers.upload.as_ref().unwrap().1, Transfers::CHUNK_SIZE);
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use bytemuck::*;
 use crate::buffer::*;
+use crate::error::Result;
 use wgpu::util::DeviceExt;
 
 pub trait Vert: bytemuck::Pod 
@@ -45,7 +46,7 @@ impl Vert for Vert2{
 }
 
 impl<V: Vert> ToBuffer for &[V]{
-    fn create_buffer(&self, device: &wgpu::Device, usage: wgpu::BufferUsages) -> anyhow::Result<wgpu::Buffer> {
+    fn create_buffer(&self, device: &wgpu::Device, usage: wgpu::BufferUsages) -> Result<wgpu::Buffer> {
         let buffer = device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor{
                 label: Some("Vertex Buffer"),
@@ -59,7 +60,7 @@ impl<V: Vert> ToBuffer for &[V]{
 }
 
 impl<V: Vert> ToVertBuffer for &[V]{
-    fn create_vert_buffer(&self, device: &wgpu::Device) -> anyhow::Result<wgpu::Buffer>{
+    fn create_vert_buffer(&self, device: &wgpu::Device) -> Result<wgpu::Buffer>{
         self.create_buffer(device, wgpu::BufferUsages::VERTEX)
     }
 }
@@ -0,0 +1,125 @@
+//!
+//! Runs the subcommands of the binary end to end on assets/test1.jpg.
+//! Tests that need a device pass without checking anything if there is no adapter.
+//!
+use image::GenericImageView;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use wgpu01::Error;
+
+const MANIFEST_DIR: &str = env!("CARGO_MANIFEST_DIR");
+const BIN: &str = env!("CARGO_BIN_EXE_wgpu01");
+
+fn asset(name: &str) -> String{
+    Path::new(MANIFEST_DIR).join("assets").join(name).display().to_string()
+}
+
+fn out_dir(test: &str) -> PathBuf{
+    let dir = std::env::temp_dir().join("wgpu01_cli").join(test);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn command(args: &[&str]) -> Output{
+    Command::new(BIN).args(args).output().unwrap()
+}
+
+///
+/// Runs a command, false if there is no adapter.
+///
+fn run(args: &[&str]) -> bool{
+    let output = command(args);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if output.status.success(){
+        return true;
+    }
+    if stderr.contains(&Error::NoAdapter.to_string()){
+        println!("skipped, no adapter");
+        return false;
+    }
+    panic!("{:?} failed: {}", args, stderr);
+}
+
+#[test]
+fn rejects_invalid_arguments(){
+    let cases: &[&[&str]] = &[
+        &["render", "in.png"],
+        &["render", "-o", "out.png"],
+        &["convert", "in.png"],
+        &["apply-strokes", "in.png", "-o", "out.png"],
+        &["info", "in.png", "--size", "10"],
+        &["info", "in.png", "--frobnicate"],
+        &["unknown"],
+        &["--samples"],
+        &["--samples", "four"],
+        &["--frobnicate"],
+    ];
+    // usage errors exit with 2 and print the usage.
+    for case in cases{
+        let output = command(case);
+        assert_eq!(output.status.code(), Some(2), "{:?} was accepted", case);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("usage:"), "{:?}", case);
+    }
+}
+
+#[test]
+fn convert_keeps_size(){
+    let out = out_dir("convert_keeps_size").join("test1.png");
+    if !run(&["convert", &asset("test1.jpg"), out.to_str().unwrap()]){
+        return;
+    }
+
+    let input = image::open(asset("test1.jpg")).unwrap();
+    let output = image::open(&out).unwrap();
+    assert_eq!(output.dimensions(), input.dimensions());
+}
+
+#[test]
+fn convert_keeps_bit_depth(){
+    let dir = out_dir("convert_keeps_bit_depth");
+    let input = dir.join("deep.png");
+    let output = dir.join("deep.tiff");
+    let img = image::ImageBuffer::from_fn(8, 4, |x, y| image::Rgba([x as u16 * 8000, y as u16 * 16000, 1, 65535]));
+    image::DynamicImage::ImageRgba16(img.clone()).save(&input).unwrap();
+
+    run(&["convert", input.to_str().unwrap(), output.to_str().unwrap()]);
+    match image::open(&output).unwrap(){
+        image::DynamicImage::ImageRgba16(converted) => assert_eq!(converted, img),
+        converted => panic!("converted to {:?}", converted.color()),
+    }
+}
+
+#[test]
+fn info_runs_on_the_cpu(){
+    run(&["info", &asset("test1.jpg"), &asset("test2.jpg"), "--cpu"]);
+}
+
+#[test]
+fn apply_strokes_paints(){
+    let dir = out_dir("apply_strokes_paints");
+    let plain = dir.join("plain.png");
+    let painted = dir.join("painted.png");
+    let strokes = dir.join("strokes.txt");
+    std::fs::write(&strokes, "0.2 0.5 1\n0.8 0.5 1\n").unwrap();
+
+    if !run(&["convert", &asset("test1.jpg"), plain.to_str().unwrap()]){
+        return;
+    }
+    run(&["apply-strokes", &asset("test1.jpg"), strokes.to_str().unwrap(), "-o", painted.to_str().unwrap()]);
+
+    let plain = image::open(&plain).unwrap().to_rgba8();
+    let painted = image::open(&painted).unwrap().to_rgba8();
+    assert_eq!(plain.dimensions(), painted.dimensions());
+    assert_ne!(plain.as_raw(), painted.as_raw(), "the strokes did not change the image");
+}
+
+#[test]
+fn render_and_info(){
+    let out = out_dir("render_and_info").join("flat.png");
+    if !run(&["render", &asset("test1.jpg"), &asset("test2.jpg"), "--size", "64x48", "-o", out.to_str().unwrap()]){
+        return;
+    }
+    assert_eq!(image::open(&out).unwrap().dimensions(), (64, 48));
+
+    run(&["info", &asset("test1.jpg"), &asset("test2.jpg")]);
+}
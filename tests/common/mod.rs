@@ -0,0 +1,76 @@
+//!
+//! Helpers shared by the rendering tests.
+//!
+#![allow(dead_code)]
+
+use wgpu01::{Error, HeadlessCanvas};
+
+pub const MANIFEST_DIR: &str = env!("CARGO_MANIFEST_DIR");
+
+///
+/// A headless canvas on the fallback (software) adapter so results don't depend on the GPU.
+/// None if the platform has no fallback adapter, the test is skipped then.
+///
+pub fn headless(size: [u32; 2]) -> Option<HeadlessCanvas>{
+    let (device, queue) = match pollster::block_on(HeadlessCanvas::request_device(true)){
+        Ok(device) => device,
+        Err(Error::NoAdapter) => {
+            println!("skipped, no fallback adapter");
+            return None;
+        },
+        Err(err) => panic!("{}", err),
+    };
+    Some(HeadlessCanvas::with_device(device, queue, size).unwrap())
+}
+
+///
+/// Result of comparing two images channel by channel.
+///
+pub struct Comparison{
+    /// largest difference of any channel.
+    pub max_diff: u8,
+    /// pixels with a channel differing by more than the tolerance.
+    pub failed: usize,
+    ///
+    /// Differences scaled by 4 in rgb, pixels over the tolerance are opaque, the others
+    /// transparent.
+    ///
+    pub diff: image::RgbaImage,
+}
+
+impl Comparison{
+    pub fn passed(&self) -> bool{
+        self.failed == 0
+    }
+}
+
+///
+/// Compares actual with expected, channels may differ by tolerance.
+/// Panics if the sizes differ.
+///
+pub fn compare(actual: &image::RgbaImage, expected: &image::RgbaImage, tolerance: u8) -> Comparison{
+    assert_eq!(actual.dimensions(), expected.dimensions(), "image sizes differ");
+
+    let mut max_diff = 0;
+    let mut failed = 0;
+    let diff = image::RgbaImage::from_fn(actual.width(), actual.height(), |x, y| {
+        let a = actual.get_pixel(x, y).0;
+        let e = expected.get_pixel(x, y).0;
+        let d = [0, 1, 2, 3].map(|i| (a[i] as i16 - e[i] as i16).unsigned_abs() as u8);
+        let pixel_max = d.iter().copied().max().unwrap();
+
+        max_diff = max_diff.max(pixel_max);
+        let over = pixel_max > tolerance;
+        if over{
+            failed += 1;
+        }
+        image::Rgba([
+            d[0].saturating_mul(4),
+            d[1].saturating_mul(4),
+            d[2].saturating_mul(4).max(d[3].saturating_mul(4)),
+            if over {255} else {0},
+        ])
+    });
+
+    Comparison{max_diff, failed, diff}
+}
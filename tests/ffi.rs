@@ -0,0 +1,68 @@
+//!
+//! Builds tests/ffi/smoke.c against include/wgpu01.h and the cdylib of this crate and runs it.
+//! Uses the C compiler in CC, or cc.
+//!
+//! With the header feature include/wgpu01.h is also compared with the header cbindgen generates,
+//! update it after changing the C API with
+//!
+//! ```text
+//! WGPU01_BLESS=1 cargo test --features header --test ffi
+//! ```
+//!
+#![cfg(unix)]
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const MANIFEST_DIR: &str = env!("CARGO_MANIFEST_DIR");
+
+///
+/// The directory cargo put the cdylib in, next to this test binary.
+///
+fn deps_dir() -> PathBuf{
+    std::env::current_exe().unwrap().parent().unwrap().to_path_buf()
+}
+
+#[test]
+fn c_smoke_test(){
+    let deps = deps_dir();
+    let lib = if cfg!(target_os = "macos") {"libwgpu01.dylib"} else {"libwgpu01.so"};
+    assert!(deps.join(lib).exists(), "{} was not built into {:?}", lib, deps);
+
+    let exe = deps.join("wgpu01_ffi_smoke");
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(&cc)
+        .arg("-std=c99")
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg(Path::new(MANIFEST_DIR).join("tests/ffi/smoke.c"))
+        .arg("-I").arg(Path::new(MANIFEST_DIR).join("include"))
+        .arg("-L").arg(&deps)
+        .arg("-lwgpu01")
+        .arg(format!("-Wl,-rpath,{}", deps.display()))
+        .arg("-o").arg(&exe)
+        .status()
+        .unwrap_or_else(|e| panic!("failed to run {}: {}", cc, e));
+    assert!(status.success(), "compiling the smoke test failed");
+
+    let output = Command::new(&exe)
+        .arg(Path::new(MANIFEST_DIR).join("assets/test1.jpg"))
+        .output()
+        .unwrap();
+    println!("{}", String::from_utf8_lossy(&output.stdout));
+    assert!(output.status.success(), "smoke test failed: {}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[cfg(feature = "header")]
+#[test]
+fn header_is_up_to_date(){
+    let generated = include_str!(concat!(env!("OUT_DIR"), "/wgpu01.h"));
+    let header = Path::new(MANIFEST_DIR).join("include/wgpu01.h");
+    if std::env::var_os("WGPU01_BLESS").map_or(false, |v| v != "0"){
+        std::fs::write(&header, generated).unwrap();
+        println!("wrote {:?}", header);
+        return;
+    }
+    let checked_in = std::fs::read_to_string(&header).unwrap();
+    assert!(checked_in == generated, "{:?} is out of date, bless it with WGPU01_BLESS=1", header);
+}
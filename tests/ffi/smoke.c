@@ -0,0 +1,126 @@
+/*
+ * Smoke test of the C API, built and run by tests/ffi.rs.
+ * Usage: smoke <image>
+ * Exits with 0 if everything worked or no adapter is available.
+ */
+#include <stdio.h>
+#include <stdlib.h>
+#include "wgpu01.h"
+
+#define WIDTH 64
+#define HEIGHT 48
+
+static int check(Wgpu01Result result, const char *call){
+    if(result != WGPU01_RESULT_OK){
+        const char *message = wgpu01_last_error_message();
+        fprintf(stderr, "%s failed with %d: %s\n", call, (int)result, message ? message : "(no message)");
+        return 0;
+    }
+    return 1;
+}
+
+static unsigned char *read_file(const char *path, size_t *len){
+    FILE *f = fopen(path, "rb");
+    if(!f){
+        return NULL;
+    }
+    fseek(f, 0, SEEK_END);
+    *len = (size_t)ftell(f);
+    fseek(f, 0, SEEK_SET);
+    unsigned char *data = malloc(*len);
+    if(data && fread(data, 1, *len, f) != *len){
+        free(data);
+        data = NULL;
+    }
+    fclose(f);
+    return data;
+}
+
+int main(int argc, char **argv){
+    if(argc != 2){
+        fprintf(stderr, "usage: %s <image>\n", argv[0]);
+        return 2;
+    }
+
+    /* Errors are reported for invalid handles instead of crashing. */
+    if(wgpu01_canvas_composite(NULL) != WGPU01_RESULT_NULL_POINTER){
+        fprintf(stderr, "null canvas was not rejected\n");
+        return 1;
+    }
+
+    Wgpu01Canvas *canvas = NULL;
+    Wgpu01Result result = wgpu01_canvas_create(WIDTH, HEIGHT, &canvas);
+    if(result == WGPU01_RESULT_NO_ADAPTER){
+        printf("skipped, no adapter: %s\n", wgpu01_last_error_message());
+        return 0;
+    }
+    if(!check(result, "wgpu01_canvas_create")){
+        return 1;
+    }
+
+    int ok = 0;
+    unsigned char *pixels = NULL;
+    size_t len = 0;
+    unsigned char *image = read_file(argv[1], &len);
+    if(!image){
+        fprintf(stderr, "can not read %s\n", argv[1]);
+        goto done;
+    }
+
+    uint32_t layer = 0;
+    if(!check(wgpu01_canvas_add_layer(canvas, image, len, &layer), "wgpu01_canvas_add_layer")){
+        goto done;
+    }
+
+    if(wgpu01_canvas_queue_stroke(canvas, layer + 1, (Wgpu01StrokePoint){0.0f, 0.0f, 1.0f}, (Wgpu01StrokePoint){1.0f, 1.0f, 1.0f}) != WGPU01_RESULT_MISSING_LAYER){
+        fprintf(stderr, "missing layer was not rejected\n");
+        goto done;
+    }
+
+    Wgpu01StrokePoint p0 = {0.25f, 0.5f, 1.0f};
+    Wgpu01StrokePoint p1 = {0.75f, 0.5f, 0.5f};
+    if(!check(wgpu01_canvas_queue_stroke(canvas, layer, p0, p1), "wgpu01_canvas_queue_stroke")){
+        goto done;
+    }
+
+    if(!check(wgpu01_canvas_composite(canvas), "wgpu01_canvas_composite")){
+        goto done;
+    }
+
+    uint32_t width = 0, height = 0;
+    if(!check(wgpu01_canvas_size(canvas, &width, &height), "wgpu01_canvas_size")){
+        goto done;
+    }
+    if(width != WIDTH || height != HEIGHT){
+        fprintf(stderr, "size is %ux%u\n", width, height);
+        goto done;
+    }
+
+    size_t pixels_len = (size_t)width * height * 4;
+    pixels = malloc(pixels_len);
+    if(wgpu01_canvas_read_rgba(canvas, pixels, pixels_len - 1) != WGPU01_RESULT_INVALID_ARGUMENT){
+        fprintf(stderr, "short buffer was not rejected\n");
+        goto done;
+    }
+    if(!check(wgpu01_canvas_read_rgba(canvas, pixels, pixels_len), "wgpu01_canvas_read_rgba")){
+        goto done;
+    }
+
+    /* The image covers the whole canvas, so it can not be black everywhere. */
+    unsigned long sum = 0;
+    for(size_t i = 0; i < pixels_len; i += 4){
+        sum += pixels[i] + pixels[i + 1] + pixels[i + 2];
+    }
+    if(sum == 0){
+        fprintf(stderr, "composite is black\n");
+        goto done;
+    }
+
+    ok = 1;
+
+done:
+    free(pixels);
+    free(image);
+    wgpu01_canvas_destroy(canvas);
+    return ok ? 0 : 1;
+}
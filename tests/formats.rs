@@ -0,0 +1,58 @@
+//!
+//! Layer formats that need adapter support.
+//!
+use std::sync::Arc;
+use wgpu01::{texture, BlendOpManager, BrushOpManager, Canvas, ColorSpace, Error, HeadlessCanvas, PipelineCache};
+
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Float;
+
+///
+/// Rgba32Float works where the adapter can filter it and fails with UnsupportedFormat elsewhere,
+/// instead of failing validation in the middle of a frame.
+///
+#[test]
+fn rgba32float_depends_on_the_adapter(){
+    let (device, queue) = match pollster::block_on(HeadlessCanvas::request_device(true)){
+        Ok(device) => device,
+        Err(Error::NoAdapter) => {
+            println!("skipped, no adapter");
+            return;
+        },
+        Err(err) => panic!("{}", err),
+    };
+    let device = Arc::new(device);
+    let cache = PipelineCache::new(device.clone());
+    let filterable = texture::check_filterable(&device, FORMAT).is_ok();
+    println!("Rgba32Float filterable: {}", filterable);
+
+    let blendops = BlendOpManager::new(&device, &queue, &cache, &FORMAT);
+    let brushops = BrushOpManager::new(&device, &queue, &cache, FORMAT, 1);
+    if !filterable{
+        assert!(matches!(blendops, Err(Error::UnsupportedFormat(FORMAT))));
+        assert!(matches!(brushops, Err(Error::UnsupportedFormat(FORMAT))));
+        return;
+    }
+
+    let blendops = Arc::new(blendops.unwrap());
+    brushops.unwrap();
+    let canvas = Canvas::new(&device, &queue, &cache, FORMAT, HeadlessCanvas::TARGET_FORMAT, ColorSpace::Linear, blendops, [16, 16]);
+    assert!(canvas.is_ok());
+}
+
+#[test]
+fn unfilterable_formats_are_rejected(){
+    let (device, queue) = match pollster::block_on(HeadlessCanvas::request_device(true)){
+        Ok(device) => device,
+        Err(Error::NoAdapter) => {
+            println!("skipped, no adapter");
+            return;
+        },
+        Err(err) => panic!("{}", err),
+    };
+    let device = Arc::new(device);
+    let cache = PipelineCache::new(device.clone());
+    let format = wgpu::TextureFormat::R32Uint;
+    assert!(matches!(texture::check_filterable(&device, format), Err(Error::UnsupportedFormat(_))));
+    assert!(matches!(BrushOpManager::new(&device, &queue, &cache, format, 1), Err(Error::UnsupportedFormat(_))));
+    assert!(texture::check_filterable(&device, HeadlessCanvas::FORMAT).is_ok());
+}
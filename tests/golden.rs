@@ -0,0 +1,170 @@
+//!
+//! Golden image tests, every scene is rendered headlessly and compared with tests/golden/<scene>.png.
+//!
+//! On a mismatch the rendering, the reference and a diff are written to
+//! target/golden/<scene>.{actual,expected,diff}.png. The diff shows channel differences scaled by
+//! 4, pixels over the tolerance are opaque.
+//!
+//! After an intended change of the output, bless the new references with
+//!
+//! ```text
+//! WGPU01_BLESS=1 cargo test --test golden
+//! ```
+//!
+//! and review the changed PNGs before committing them. A missing reference fails the test unless
+//! it is blessed.
+//!
+mod common;
+
+use nalgebra_glm as glm;
+use std::path::{Path, PathBuf};
+use wgpu01::{HeadlessCanvas, StrokeData};
+
+/// canvas size of all scenes, small so the software adapter stays fast.
+const SIZE: [u32; 2] = [128, 96];
+
+/// allowed difference per channel, adapters may round and filter slightly differently.
+const TOLERANCE: u8 = 2;
+
+fn golden_dir() -> PathBuf{
+    let dir = Path::new(common::MANIFEST_DIR).join("tests/golden");
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn out_dir() -> PathBuf{
+    let dir = Path::new(common::MANIFEST_DIR).join("target/golden");
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn bless() -> bool{
+    std::env::var_os("WGPU01_BLESS").map_or(false, |v| v != "0")
+}
+
+fn image(name: &str) -> image::DynamicImage{
+    image::open(Path::new(common::MANIFEST_DIR).join("assets").join(name)).unwrap()
+}
+
+///
+/// Renders a scene and compares it with its reference.
+/// Returns the rendering, None if there is no adapter.
+///
+fn check(scene: &str, tolerance: u8, build: impl FnOnce(&mut HeadlessCanvas)) -> Option<image::RgbaImage>{
+    let mut canvas = match common::headless(SIZE){
+        Some(canvas) => canvas,
+        None => return None,
+    };
+    build(&mut canvas);
+    canvas.render().unwrap();
+    let actual = canvas.read_rgba8().unwrap();
+
+    let reference = golden_dir().join(format!("{}.png", scene));
+    if bless(){
+        actual.save(&reference).unwrap();
+        println!("wrote reference {:?}", reference);
+        return Some(actual);
+    }
+    if !reference.exists(){
+        actual.save(out_dir().join(format!("{}.actual.png", scene))).unwrap();
+        panic!("{}: missing reference {:?}, bless it with WGPU01_BLESS=1", scene, reference);
+    }
+
+    let expected = image::open(&reference).unwrap().to_rgba8();
+    let comparison = common::compare(&actual, &expected, tolerance);
+    if !comparison.passed(){
+        let out = out_dir();
+        actual.save(out.join(format!("{}.actual.png", scene))).unwrap();
+        expected.save(out.join(format!("{}.expected.png", scene))).unwrap();
+        comparison.diff.save(out.join(format!("{}.diff.png", scene))).unwrap();
+        panic!(
+            "{}: {} pixels differ by up to {} (tolerance {}), see {:?}",
+            scene, comparison.failed, comparison.max_diff, tolerance, out,
+        );
+    }
+    Some(actual)
+}
+
+///
+/// An opaque color in the top rows of an image of SIZE, transparent below.
+///
+fn band(color: [u8; 3], rows: u32) -> image::DynamicImage{
+    image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(SIZE[0], SIZE[1], |_, y| {
+        let [r, g, b] = color;
+        image::Rgba(if y < rows {[r, g, b, 255]} else {[0; 4]})
+    }))
+}
+
+fn segment(pos0: [f32; 2], pos1: [f32; 2], p0: f32, p1: f32) -> StrokeData{
+    StrokeData{pos0, pos1, p0, p1}
+}
+
+#[test]
+fn single_image(){
+    check("single_image", TOLERANCE, |canvas| {
+        canvas.push_image(&image("test1.jpg"), "Add").unwrap();
+    });
+}
+
+#[test]
+fn two_layers_add(){
+    check("two_layers_add", TOLERANCE, |canvas| {
+        let bottom = canvas.push_image(&image("test1.jpg"), "Add").unwrap();
+        let top = canvas.push_image(&image("test2.jpg"), "Add").unwrap();
+        canvas.canvas.layers[bottom].borrow_mut().fit_to_pixels(SIZE);
+        canvas.canvas.layers[top].borrow_mut().fit_to_pixels([SIZE[0] / 2, SIZE[1] / 2]);
+    });
+}
+
+#[test]
+fn layers_stay_upright(){
+    // every blend and the tonemap sample in the orientation they draw, the band of the first layer
+    // must stay on top of the band of the second.
+    let actual = check("layers_stay_upright", TOLERANCE, |canvas| {
+        for band in [band([255, 0, 0], SIZE[1] / 2), band([0, 255, 0], SIZE[1] / 4)]{
+            let layer = canvas.push_image(&band, "Add").unwrap();
+            canvas.canvas.layers[layer].borrow_mut().fit_to_pixels(SIZE);
+        }
+    });
+    if let Some(actual) = actual{
+        let x = SIZE[0] / 2;
+        assert_eq!(actual.get_pixel(x, 2).0, [255, 255, 0, 255]);
+        assert_eq!(actual.get_pixel(x, SIZE[1] * 3 / 8).0, [255, 0, 0, 255]);
+        assert_eq!(actual.get_pixel(x, SIZE[1] - 3).0[..3], [0, 0, 0]);
+    }
+}
+
+#[test]
+fn layer_transform(){
+    check("layer_transform", TOLERANCE, |canvas| {
+        let layer = canvas.push_image(&image("test1.jpg"), "Add").unwrap();
+        let mut layer = canvas.canvas.layers[layer].borrow_mut();
+        layer.fit_to_pixels([SIZE[0] / 2, SIZE[1] / 2]);
+        // translation is in clip space, after scale.
+        layer.translation = glm::vec3(0.25, -0.1, 0.0);
+        layer.rotation = glm::vec4(0.0, 0.0, 1.0, std::f32::consts::FRAC_PI_6);
+    });
+}
+
+#[test]
+fn strokes(){
+    check("strokes", TOLERANCE, |canvas| {
+        let layer = canvas.push_empty("Add").unwrap();
+        canvas.queue_stroke(layer, "default", segment([0.1, 0.2], [0.9, 0.2], 1.0, 1.0)).unwrap();
+        canvas.queue_stroke(layer, "default", segment([0.1, 0.5], [0.9, 0.5], 1.0, 0.1)).unwrap();
+        canvas.queue_stroke(layer, "default", segment([0.2, 0.8], [0.5, 0.6], 0.5, 0.5)).unwrap();
+        canvas.queue_stroke(layer, "default", segment([0.5, 0.6], [0.8, 0.8], 0.5, 1.0)).unwrap();
+    });
+}
+
+#[test]
+fn strokes_on_image(){
+    check("strokes_on_image", TOLERANCE, |canvas| {
+        let layer = canvas.push_image(&image("test1.jpg"), "Add").unwrap();
+        canvas.canvas.layers[layer].borrow_mut().fit_to_pixels(SIZE);
+        for i in 0..8{
+            let x = 0.1 + 0.1 * i as f32;
+            canvas.queue_stroke(layer, "default", segment([x, 0.2], [x + 0.05, 0.8], 1.0, 0.3)).unwrap();
+        }
+    });
+}
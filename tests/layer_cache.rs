@@ -0,0 +1,102 @@
+//!
+//! The flattened layers below and above the active layer have to give the same image as
+//! compositing every layer.
+//!
+mod common;
+
+use nalgebra_glm as glm;
+use std::sync::Arc;
+use wgpu01::{BlendOp, HeadlessCanvas, StrokeData};
+
+const SIZE: [u32; 2] = [64, 48];
+const TOLERANCE: u8 = 1;
+
+fn gradient(size: [u32; 2], channel: usize) -> image::DynamicImage{
+    image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(size[0], size[1], |x, y| {
+        let mut p = [0, 0, 0, (y * 255 / size[1]) as u8];
+        p[channel] = (x * 255 / size[0]) as u8;
+        image::Rgba(p)
+    }))
+}
+
+///
+/// Four layers with the second one active.
+///
+fn layers(canvas: &mut HeadlessCanvas){
+    canvas.push_image(&gradient([64, 48], 0), "Add").unwrap();
+    canvas.push_empty("Add").unwrap();
+    canvas.push_image(&gradient([40, 30], 1), "Add").unwrap();
+    canvas.push_image(&gradient([20, 40], 2), "Add").unwrap();
+    canvas.canvas.set_active_layer(1).unwrap();
+}
+
+///
+/// Renders a few frames that paint on the active layer and move a layer above it, then renders
+/// again with the top layer active so every layer is composited.
+///
+fn cached_and_full(canvas: &mut HeadlessCanvas) -> (image::RgbaImage, image::RgbaImage){
+    canvas.render().unwrap();
+    for i in 0..3{
+        let x = 0.2 + 0.2 * i as f32;
+        canvas.queue_stroke(1, "default", StrokeData{pos0: [x, 0.3], pos1: [x + 0.1, 0.6], p0: 1.0, p1: 1.0}).unwrap();
+        canvas.canvas.layers[2].borrow_mut().translation = glm::vec3(0.1 * i as f32, 0.0, 0.0);
+        canvas.render().unwrap();
+    }
+    let cached = canvas.read_rgba8().unwrap();
+
+    canvas.canvas.set_active_layer(3).unwrap();
+    canvas.render().unwrap();
+    (cached, canvas.read_rgba8().unwrap())
+}
+
+fn assert_same(cached: &image::RgbaImage, full: &image::RgbaImage){
+    let comparison = common::compare(cached, full, TOLERANCE);
+    assert!(comparison.passed(), "{} pixels differ, by up to {}", comparison.failed, comparison.max_diff);
+    // the first stroke, on top of the red gradient of the bottom layer.
+    assert!(full.get_pixel(16, 26).0[0] > 200, "{:?}", full.get_pixel(16, 26));
+}
+
+#[test]
+fn cached_draw_matches_full_draw(){
+    let mut canvas = match common::headless(SIZE){
+        Some(canvas) => canvas,
+        None => return,
+    };
+    layers(&mut canvas);
+    let (cached, full) = cached_and_full(&mut canvas);
+    assert_same(&cached, &full);
+}
+
+#[test]
+fn layers_above_are_blended_one_by_one_for_non_associative_ops(){
+    let mut canvas = match common::headless(SIZE){
+        Some(canvas) => canvas,
+        None => return,
+    };
+    layers(&mut canvas);
+    let op = BlendOp::new(&canvas.device, &canvas.queue, &canvas.cache, &HeadlessCanvas::FORMAT, include_str!("../src/shaders/add.wgsl"), false).unwrap();
+    assert!(!op.associative());
+    canvas.canvas.layers[3].borrow_mut().set_blendop(Arc::new(op));
+    canvas.canvas.invalidate();
+
+    let (cached, full) = cached_and_full(&mut canvas);
+    assert_same(&cached, &full);
+}
+
+#[test]
+fn removing_every_layer_clears_the_target(){
+    let mut canvas = match common::headless(SIZE){
+        Some(canvas) => canvas,
+        None => return,
+    };
+    layers(&mut canvas);
+    canvas.render().unwrap();
+    assert!(canvas.read_rgba8().unwrap().pixels().any(|p| p.0 != [0; 4]));
+
+    while !canvas.canvas.layers.is_empty(){
+        canvas.canvas.remove_layer(0);
+    }
+    canvas.render().unwrap();
+    let img = canvas.read_rgba8().unwrap();
+    assert!(img.pixels().all(|p| p.0 == [0; 4]), "{:?}", img.pixels().find(|p| p.0 != [0; 4]));
+}
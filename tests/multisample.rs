@@ -0,0 +1,80 @@
+//!
+//! Multisampled drawing of layers and strokes on a headless canvas.
+//!
+mod common;
+
+use nalgebra_glm as glm;
+use wgpu01::{HeadlessCanvas, StrokeData};
+
+const SIZE: [u32; 2] = [64, 64];
+
+///
+/// An opaque white layer rotated so its edges cross pixels diagonally.
+///
+fn rotated_square(canvas: &mut HeadlessCanvas){
+    let img = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(32, 32, image::Rgba([255, 255, 255, 255])));
+    let layer = canvas.push_image(&img, "Add").unwrap();
+    canvas.canvas.layers[layer].borrow_mut().rotation = glm::vec4(0.0, 0.0, 1.0, 0.5);
+}
+
+///
+/// Pixels that are neither fully covered nor empty, with some room for rounding.
+///
+fn partial_pixels(img: &image::RgbaImage) -> usize{
+    img.pixels().filter(|p| p.0[3] > 8 && p.0[3] < 247).count()
+}
+
+#[test]
+fn rotated_edges_get_partial_coverage(){
+    let mut canvas = match common::headless(SIZE){
+        Some(canvas) => canvas,
+        None => return,
+    };
+    rotated_square(&mut canvas);
+    canvas.render().unwrap();
+    let single = canvas.read_rgba8().unwrap();
+
+    canvas.set_sample_count(4).unwrap();
+    // pushed after setting the sample count, so push_layer has to apply it.
+    rotated_square(&mut canvas);
+    assert_eq!(canvas.canvas.layers[1].borrow().sample_count(), 4);
+    canvas.render().unwrap();
+    let multi = canvas.read_rgba8().unwrap();
+
+    assert_eq!(partial_pixels(&single), 0);
+    assert!(partial_pixels(&multi) > 20, "{} partially covered pixels", partial_pixels(&multi));
+    // the inside stays opaque.
+    assert!(multi.get_pixel(32, 32).0.iter().all(|v| *v >= 254), "{:?}", multi.get_pixel(32, 32));
+}
+
+#[test]
+fn strokes_are_drawn_multisampled(){
+    let mut canvas = match common::headless(SIZE){
+        Some(canvas) => canvas,
+        None => return,
+    };
+    let layer = canvas.push_empty("Add").unwrap();
+    // layers pushed before take the sample count too.
+    canvas.set_sample_count(4).unwrap();
+    canvas.queue_stroke(layer, "default", StrokeData{pos0: [0.2, 0.5], pos1: [0.8, 0.5], p0: 1.0, p1: 1.0}).unwrap();
+    canvas.render().unwrap();
+    let img = canvas.read_rgba8().unwrap();
+
+    let center = img.get_pixel(32, 32).0;
+    assert!(center[0] > 150 && center[1] == 0 && center[3] > 100, "{:?}", center);
+    assert_eq!(img.get_pixel(32, 2).0, [0, 0, 0, 0]);
+}
+
+#[test]
+fn strokes_of_another_sample_count_fail(){
+    let mut canvas = match common::headless(SIZE){
+        Some(canvas) => canvas,
+        None => return,
+    };
+    let layer = canvas.push_empty("Add").unwrap();
+    canvas.queue_stroke(layer, "default", StrokeData{pos0: [0.2, 0.5], pos1: [0.8, 0.5], p0: 1.0, p1: 1.0}).unwrap();
+    canvas.set_sample_count(4).unwrap();
+
+    let err = canvas.render().unwrap_err();
+    assert!(err.to_string().contains("Sample count 4 of the layer does not match sample count 1"), "{}", err);
+}
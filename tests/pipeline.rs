@@ -0,0 +1,234 @@
+//!
+//! Pipelines built with the builders of the pipeline module and drawn on the fallback adapter.
+//! Tests pass without checking anything if there is no adapter.
+//!
+mod common;
+
+use std::borrow::Cow;
+use wgpu01::binding::{self, BindGroupBuilder, BindGroupLayoutBuilder};
+use wgpu01::pipeline::*;
+use wgpu01::render_target::{ColorAttachment, DepthStencilAttachment};
+use wgpu01::{color, texture};
+
+const SIZE: [u32; 2] = [8, 4];
+
+///
+/// Rectangles without vertex buffers, the left half of the target at depth 0.5 and the whole
+/// target at depth 0.25 or 0.75.
+///
+const DEPTH_STENCIL_SHADER: &str = r#"
+fn rect_vertex(index: u32, max_x: f32, z: f32) -> vec4<f32>{
+    var xs = array<f32, 6>(-1.0, max_x, -1.0, -1.0, max_x, max_x);
+    var ys = array<f32, 6>(-1.0, -1.0, 1.0, 1.0, -1.0, 1.0);
+    return vec4<f32>(xs[index], ys[index], z, 1.0);
+}
+
+[[stage(vertex)]]
+fn vs_left([[builtin(vertex_index)]] index: u32) -> [[builtin(position)]] vec4<f32>{
+    return rect_vertex(index, 0.0, 0.5);
+}
+
+[[stage(vertex)]]
+fn vs_front([[builtin(vertex_index)]] index: u32) -> [[builtin(position)]] vec4<f32>{
+    return rect_vertex(index, 1.0, 0.25);
+}
+
+[[stage(vertex)]]
+fn vs_back([[builtin(vertex_index)]] index: u32) -> [[builtin(position)]] vec4<f32>{
+    return rect_vertex(index, 1.0, 0.75);
+}
+
+[[stage(fragment)]]
+fn fs_red() -> [[location(0)]] vec4<f32>{
+    return vec4<f32>(1.0, 0.0, 0.0, 1.0);
+}
+
+[[stage(fragment)]]
+fn fs_green() -> [[location(0)]] vec4<f32>{
+    return vec4<f32>(0.0, 1.0, 0.0, 1.0);
+}
+
+[[stage(fragment)]]
+fn fs_blue() -> [[location(0)]] vec4<f32>{
+    return vec4<f32>(0.0, 0.0, 1.0, 1.0);
+}
+"#;
+
+///
+/// Writes the invocation id to every pixel of a storage image.
+/// Half floats hold the ids exactly.
+///
+const FILL_SHADER: &str = r#"
+[[group(0), binding(0)]]
+var output: texture_storage_2d<rgba16float, write>;
+
+[[stage(compute), workgroup_size(4, 4)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>){
+    let size = textureDimensions(output);
+    if (i32(id.x) >= size.x || i32(id.y) >= size.y){
+        return;
+    }
+    textureStore(output, vec2<i32>(id.xy), vec4<f32>(f32(id.x), f32(id.y), 0.0, 1.0));
+}
+"#;
+
+fn stencil_face(compare: wgpu::CompareFunction, pass_op: wgpu::StencilOperation) -> wgpu::StencilFaceState{
+    wgpu::StencilFaceState{
+        compare,
+        fail_op: wgpu::StencilOperation::Keep,
+        depth_fail_op: wgpu::StencilOperation::Keep,
+        pass_op,
+    }
+}
+
+///
+/// Rgba of the pixel in the middle of the left and the right half.
+///
+fn halves(rgba: &[f32]) -> [[f32; 4]; 2]{
+    let pixel = |x: u32| {
+        let i = ((SIZE[1] / 2 * SIZE[0] + x) * 4) as usize;
+        [rgba[i], rgba[i + 1], rgba[i + 2], rgba[i + 3]]
+    };
+    [pixel(SIZE[0] / 4), pixel(SIZE[0] * 3 / 4)]
+}
+
+#[test]
+fn depth_and_stencil_tests_mask_draws(){
+    let canvas = match common::headless([1, 1]){
+        Some(canvas) => canvas,
+        None => return,
+    };
+    let device = &canvas.device;
+    let format = wgpu::TextureFormat::Rgba8Unorm;
+    let depth_format = texture::DepthTexture::DEPTH_STENCIL_FORMAT;
+    assert_eq!(depth_format, wgpu::TextureFormat::Depth24PlusStencil8);
+
+    let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor{
+        label: Some("Depth Stencil Shader"),
+        source: wgpu::ShaderSource::Wgsl(Cow::from(DEPTH_STENCIL_SHADER)),
+    });
+    let layout = PipelineLayoutBuilder::new().create(device, None);
+    let pipeline = |vs: &str, fs: &str, depth_compare, stencil| {
+        RenderPipelineBuilder::new(
+            VertexStateBuilder::new(&shader).set_entry_point(vs).build(),
+            FragmentStateBuilder::new(&shader).set_entry_point(fs).push_target_replace(format).build(),
+        )
+            .set_layout(&layout)
+            .set_depth_stencil_format(depth_format)
+            .set_depth_compare(depth_compare, true)
+            .set_stencil_face(stencil)
+            .set_stencil_masks(0xff, 0xff)
+            .build(device)
+            .unwrap()
+    };
+    // marks the left half in the stencil and writes depth 0.5 there.
+    let mark = pipeline("vs_left", "fs_red", wgpu::CompareFunction::Always, stencil_face(wgpu::CompareFunction::Always, wgpu::StencilOperation::Replace));
+    // in front of everything, only drawn where the stencil is marked.
+    let marked = pipeline("vs_front", "fs_green", wgpu::CompareFunction::Always, stencil_face(wgpu::CompareFunction::Equal, wgpu::StencilOperation::Keep));
+    // behind the mark, only drawn where the depth was cleared.
+    let behind = pipeline("vs_back", "fs_blue", wgpu::CompareFunction::Less, stencil_face(wgpu::CompareFunction::Always, wgpu::StencilOperation::Keep));
+
+    let target = texture::Texture::new(device, SIZE, Some("Target"), format, color::ColorSpace::Linear, false, texture::Texture::DEFAULT_USAGE);
+    let depth = texture::DepthTexture::new(device, SIZE, Some("Depth Stencil"), depth_format, 1);
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor{label: None});
+    {
+        let mut render_pass = RenderPassBuilder::new()
+            .push_color_attachment(target.view.color_attachment_clear())
+            .set_depth_stencil_attachment(depth.view.depth_stencil_attachment_clear(1.0, 0))
+            .begin(&mut encoder, None);
+
+        let mut pass = render_pass.set_pipeline(&mark);
+        pass.set_stencil_reference(1);
+        pass.draw(0..6, 0..1);
+        let mut pass = render_pass.set_pipeline(&marked);
+        pass.set_stencil_reference(1);
+        pass.draw(0..6, 0..1);
+        let mut pass = render_pass.set_pipeline(&behind);
+        pass.draw(0..6, 0..1);
+    }
+    canvas.queue.submit(std::iter::once(encoder.finish()));
+
+    let rgba = target.read_rgba_f32(device, &canvas.queue).unwrap();
+    assert_eq!(halves(&rgba), [[0.0, 1.0, 0.0, 1.0], [0.0, 0.0, 1.0, 1.0]]);
+}
+
+///
+/// The fill pipeline, its output bind group layout and a storage image of size.
+///
+fn fill_pipeline(device: &wgpu::Device, size: [u32; 2]) -> (ComputePipeline, binding::BindGroupLayoutWithDesc, texture::Texture){
+    let format = wgpu::TextureFormat::Rgba16Float;
+    let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor{
+        label: Some("Fill Shader"),
+        source: wgpu::ShaderSource::Wgsl(Cow::from(FILL_SHADER)),
+    });
+    let output_layout = BindGroupLayoutBuilder::new()
+        .push_entry_compute(binding::glsl::image2D(format, wgpu::StorageTextureAccess::WriteOnly))
+        .create(device, None);
+    let layout = PipelineLayoutBuilder::new()
+        .push_named("output", &output_layout)
+        .create(device, None);
+    let pipeline = ComputePipelineBuilder::new(&shader)
+        .set_label("Fill")
+        .set_layout(&layout)
+        .build(device);
+
+    let usage = texture::Texture::DEFAULT_USAGE | wgpu::TextureUsages::STORAGE_BINDING;
+    let image = texture::Texture::new(device, size, Some("Storage Image"), format, color::ColorSpace::Linear, false, usage);
+    (pipeline, output_layout, image)
+}
+
+#[test]
+fn compute_pass_fills_a_storage_image(){
+    let canvas = match common::headless([1, 1]){
+        Some(canvas) => canvas,
+        None => return,
+    };
+    let device = &canvas.device;
+    // not a multiple of the workgroup size, dispatch_2d has to round up.
+    let size = [6, 5];
+    let (pipeline, output_layout, image) = fill_pipeline(device, size);
+    let output = BindGroupBuilder::new(&output_layout)
+        .texture(&image.view)
+        .create(device, None);
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor{label: None});
+    {
+        let mut compute_pass = ComputePass::begin(&mut encoder, None);
+        let mut pass = compute_pass.set_pipeline(&pipeline);
+        pass.set_bind_group("output", &output, &[]).unwrap();
+        pass.dispatch_2d(size, [4, 4]);
+    }
+    canvas.queue.submit(std::iter::once(encoder.finish()));
+
+    // rows come back top row first, the shader wrote in texture order.
+    let rgba = image.read_rgba_f32(device, &canvas.queue).unwrap();
+    for (i, pixel) in rgba.chunks(4).enumerate(){
+        let (x, row) = (i as u32 % size[0], i as u32 / size[0]);
+        let y = size[1] - 1 - row;
+        let expected = [x as f32, y as f32, 0.0, 1.0];
+        assert_eq!(pixel, expected, "pixel {} {}", x, row);
+    }
+}
+
+#[test]
+fn compute_pass_rejects_unknown_bind_groups(){
+    let canvas = match common::headless([1, 1]){
+        Some(canvas) => canvas,
+        None => return,
+    };
+    let device = &canvas.device;
+    let (pipeline, output_layout, image) = fill_pipeline(device, [4, 4]);
+    let output = BindGroupBuilder::new(&output_layout)
+        .texture(&image.view)
+        .create(device, None);
+
+    assert_eq!(pipeline.bind_group_index("output"), Ok(0));
+    let missing = BindingError::BindGroup{pipeline: "Fill".to_string(), name: "input".to_string()};
+    assert_eq!(pipeline.bind_group_index("input"), Err(missing.clone()));
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor{label: None});
+    let mut compute_pass = ComputePass::begin(&mut encoder, None);
+    let mut pass = compute_pass.set_pipeline(&pipeline);
+    assert_eq!(pass.set_bind_group("input", &output, &[]), Err(missing));
+}
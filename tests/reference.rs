@@ -0,0 +1,178 @@
+//!
+//! The CPU reference renderer on its own and as an oracle for the GPU.
+//!
+mod common;
+
+use nalgebra_glm as glm;
+use wgpu01::{ColorSpace, HeadlessCanvas, ReferenceCanvas, StrokeData};
+
+/// allowed difference per channel between GPU and CPU.
+const TOLERANCE: u8 = 3;
+
+///
+/// A small image with gradients, an edge and varying alpha so misplaced pixels show.
+///
+fn pattern(size: [u32; 2]) -> image::RgbaImage{
+    image::RgbaImage::from_fn(size[0], size[1], |x, y| {
+        let edge = if x < size[0] / 3 {255} else {0};
+        image::Rgba([
+            (x * 255 / (size[0] - 1)) as u8,
+            (y * 255 / (size[1] - 1)) as u8,
+            edge,
+            255 - (y * 64 / size[1]) as u8,
+        ])
+    })
+}
+
+fn segment(pos0: [f32; 2], pos1: [f32; 2]) -> StrokeData{
+    StrokeData{pos0, pos1, p0: 1.0, p1: 1.0}
+}
+
+#[test]
+fn image_at_canvas_size_is_unchanged(){
+    let img = pattern([40, 30]);
+    let mut canvas = ReferenceCanvas::new([40, 30], ColorSpace::Linear);
+    canvas.push_image(&img, "Add").unwrap();
+    let out = canvas.render().unwrap();
+
+    let comparison = common::compare(&out, &img, 1);
+    assert!(comparison.passed(), "differs by up to {}", comparison.max_diff);
+}
+
+#[test]
+fn strokes_paint_the_brush_color(){
+    let mut canvas = ReferenceCanvas::new([32, 32], ColorSpace::Linear);
+    let layer = canvas.push_empty("Add").unwrap();
+    canvas.queue_stroke(layer, "default", segment([0.2, 0.5], [0.8, 0.5])).unwrap();
+    assert!(canvas.queue_stroke(layer + 1, "default", segment([0.0, 0.0], [1.0, 1.0])).is_err());
+    assert!(canvas.queue_stroke(layer, "missing", segment([0.0, 0.0], [1.0, 1.0])).is_err());
+
+    let out = canvas.render().unwrap();
+    // half a pixel off the stroke line, the brush color with the alpha of the falloff.
+    let center = out.get_pixel(16, 16).0;
+    assert!(center[0] > 150 && center[1] == 0 && center[2] == 0 && center[3] > 100, "{:?}", center);
+    assert_eq!(out.get_pixel(16, 2).0, [0, 0, 0, 0]);
+}
+
+///
+/// Renders a scene on the GPU and the CPU and compares them.
+/// max_failed is the share of pixels allowed over the tolerance, rasterization of edges differs.
+///
+fn check_against_gpu(size: [u32; 2], max_failed: f32, build: impl Fn(&mut dyn Scene)){
+    let mut gpu = match common::headless(size){
+        Some(canvas) => canvas,
+        None => return,
+    };
+    let mut cpu = ReferenceCanvas::new(size, ColorSpace::Linear);
+    build(&mut gpu);
+    build(&mut cpu);
+
+    gpu.render().unwrap();
+    let gpu = gpu.read_rgba8().unwrap();
+    let cpu = cpu.render().unwrap();
+
+    let comparison = common::compare(&gpu, &cpu, TOLERANCE);
+    let allowed = (max_failed * (size[0] * size[1]) as f32) as usize;
+    if comparison.failed > allowed{
+        let dir = std::path::Path::new(common::MANIFEST_DIR).join("target/reference");
+        std::fs::create_dir_all(&dir).unwrap();
+        gpu.save(dir.join("gpu.png")).unwrap();
+        cpu.save(dir.join("cpu.png")).unwrap();
+        comparison.diff.save(dir.join("diff.png")).unwrap();
+        panic!("{} pixels differ by up to {}, {} allowed, see {:?}", comparison.failed, comparison.max_diff, allowed, dir);
+    }
+}
+
+///
+/// The operations the scenes use, on both renderers.
+///
+trait Scene{
+    fn image(&mut self, img: &image::RgbaImage) -> usize;
+    fn empty(&mut self) -> usize;
+    fn transform(&mut self, layer: usize, translation: glm::Vec3, scale: glm::Vec3, rotation: glm::Vec4);
+    fn stroke(&mut self, layer: usize, data: StrokeData);
+}
+
+impl Scene for HeadlessCanvas{
+    fn image(&mut self, img: &image::RgbaImage) -> usize{
+        self.push_image(&image::DynamicImage::ImageRgba8(img.clone()), "Add").unwrap()
+    }
+
+    fn empty(&mut self) -> usize{
+        self.push_empty("Add").unwrap()
+    }
+
+    fn transform(&mut self, layer: usize, translation: glm::Vec3, scale: glm::Vec3, rotation: glm::Vec4){
+        let mut layer = self.canvas.layers[layer].borrow_mut();
+        layer.translation = translation;
+        layer.scale = scale;
+        layer.rotation = rotation;
+    }
+
+    fn stroke(&mut self, layer: usize, data: StrokeData){
+        self.queue_stroke(layer, "default", data).unwrap();
+    }
+}
+
+impl Scene for ReferenceCanvas{
+    fn image(&mut self, img: &image::RgbaImage) -> usize{
+        self.push_image(img, "Add").unwrap()
+    }
+
+    fn empty(&mut self) -> usize{
+        self.push_empty("Add").unwrap()
+    }
+
+    fn transform(&mut self, layer: usize, translation: glm::Vec3, scale: glm::Vec3, rotation: glm::Vec4){
+        let layer = &mut self.layers[layer];
+        layer.translation = translation;
+        layer.scale = scale;
+        layer.rotation = rotation;
+    }
+
+    fn stroke(&mut self, layer: usize, data: StrokeData){
+        self.queue_stroke(layer, "default", data).unwrap();
+    }
+}
+
+#[test]
+fn gpu_matches_image(){
+    check_against_gpu([64, 48], 0.0, |scene| {
+        scene.image(&pattern([64, 48]));
+    });
+}
+
+#[test]
+fn gpu_matches_add(){
+    check_against_gpu([64, 48], 0.02, |scene| {
+        scene.image(&pattern([64, 48]));
+        let top = scene.image(&pattern([32, 24]));
+        scene.transform(top, glm::vec3(0.0, 0.0, 0.0), glm::vec3(16.0, 12.0, 1.0), glm::vec4(0.0, 0.0, 1.0, 0.0));
+    });
+}
+
+#[test]
+fn gpu_matches_transform(){
+    check_against_gpu([64, 48], 0.05, |scene| {
+        let layer = scene.image(&pattern([48, 32]));
+        scene.transform(layer, glm::vec3(0.2, -0.1, 0.0), glm::vec3(30.0, 20.0, 1.0), glm::vec4(0.0, 0.0, 1.0, 0.3));
+    });
+}
+
+#[test]
+fn gpu_matches_minified(){
+    // a quarter of the size samples mip level 2.
+    check_against_gpu([64, 48], 0.02, |scene| {
+        let layer = scene.image(&pattern([256, 192]));
+        scene.transform(layer, glm::vec3(0.0, 0.0, 0.0), glm::vec3(32.0, 24.0, 1.0), glm::vec4(0.0, 0.0, 1.0, 0.0));
+    });
+}
+
+#[test]
+fn gpu_matches_strokes(){
+    check_against_gpu([64, 48], 0.02, |scene| {
+        let layer = scene.empty();
+        scene.stroke(layer, segment([0.1, 0.2], [0.9, 0.3]));
+        scene.stroke(layer, segment([0.5, 0.1], [0.4, 0.9]));
+    });
+}
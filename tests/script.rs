@@ -0,0 +1,156 @@
+//!
+//! Records scripts on a HeadlessCanvas and applies them.
+//! Tests pass without checking anything if there is no adapter.
+//!
+mod common;
+
+use std::path::Path;
+use wgpu01::script::{self, Command};
+use wgpu01::Error;
+
+const SIZE: [u32; 2] = [64, 48];
+
+fn script_error(result: wgpu01::Result<Vec<Command>>) -> String{
+    match result{
+        Err(Error::Script(message)) => message,
+        Err(err) => panic!("expected a script error, got {}", err),
+        Ok(commands) => panic!("expected a script error, got {:?}", commands),
+    }
+}
+
+#[test]
+fn records_commands_without_changing_the_canvas(){
+    let mut canvas = match common::headless(SIZE){
+        Some(canvas) => canvas,
+        None => return,
+    };
+    let commands = script::record(&mut canvas, r#"
+        let paint = canvas.add_layer("Add");
+        paint.translation = [1, 2];
+        paint.stroke(0.1, 0.2, 0.3, 0.4);
+        canvas.set_active(0);
+        canvas.render();
+        canvas.save("out.png");
+    "#).unwrap();
+
+    assert_eq!(commands.len(), 6, "{:?}", commands);
+    assert!(matches!(&commands[0], Command::AddLayer{blendop} if blendop == "Add"));
+    assert!(matches!(&commands[1], Command::SetTransform{layer: 0, translation, ..} if translation[..2] == [1.0, 2.0]));
+    match &commands[2]{
+        Command::Stroke{layer: 0, brushop, data} => {
+            assert_eq!(brushop, "default");
+            assert_eq!((data.pos0, data.pos1), ([0.1, 0.2], [0.3, 0.4]));
+        },
+        command => panic!("expected a stroke, got {:?}", command),
+    }
+    assert!(matches!(commands[3], Command::SetActiveLayer(0)));
+    assert!(matches!(commands[4], Command::Render));
+    assert!(matches!(&commands[5], Command::Save(path) if path == Path::new("out.png")));
+
+    assert!(canvas.canvas.layers.is_empty());
+}
+
+#[test]
+fn rejects_missing_layers_and_ops(){
+    let mut canvas = match common::headless(SIZE){
+        Some(canvas) => canvas,
+        None => return,
+    };
+    let cases = [
+        (r#"canvas.add_layer("Add"); canvas.layer(3);"#, "Layer 3 does not exist"),
+        (r#"canvas.add_layer("Frobnicate");"#, "No BlendOp named \"Frobnicate\""),
+        (r#"let paint = canvas.add_layer("Add"); paint.blendop = "Frobnicate";"#, "No BlendOp named \"Frobnicate\""),
+        (r#"canvas.add_layer("Add").stroke("frobnicate", [0, 0], [1, 1]);"#, "No BrushOp named \"frobnicate\""),
+    ];
+    for (src, expected) in cases{
+        let message = script_error(script::record(&mut canvas, src));
+        assert!(message.contains(expected), "{:?} gave {:?}", src, message);
+
+        // run applies nothing when recording fails.
+        assert!(matches!(script::run(&mut canvas, src), Err(Error::Script(_))));
+        assert!(canvas.canvas.layers.is_empty(), "{:?} changed the canvas", src);
+    }
+}
+
+#[test]
+fn layers_keep_their_handle_when_layers_below_are_removed(){
+    let mut canvas = match common::headless(SIZE){
+        Some(canvas) => canvas,
+        None => return,
+    };
+    let commands = script::record(&mut canvas, r#"
+        let below = canvas.add_layer("Add");
+        let above = canvas.add_layer("Add");
+        canvas.remove_layer(0);
+        if above.index != 0 { throw "expected index 0"; }
+        above.stroke(0.1, 0.2, 0.3, 0.4);
+    "#).unwrap();
+    assert!(matches!(commands.last(), Some(Command::Stroke{layer: 0, ..})), "{:?}", commands);
+
+    let message = script_error(script::record(&mut canvas, r#"
+        let below = canvas.add_layer("Add");
+        canvas.add_layer("Add");
+        canvas.remove_layer(0);
+        below.stroke(0.1, 0.2, 0.3, 0.4);
+    "#));
+    assert!(message.contains("The layer was removed"), "{}", message);
+}
+
+#[test]
+fn applies_to_headless_canvas(){
+    let mut canvas = match common::headless(SIZE){
+        Some(canvas) => canvas,
+        None => return,
+    };
+    script::run(&mut canvas, r#"
+        let paint = canvas.add_layer("Add");
+        paint.stroke(0.2, 0.5, 0.8, 0.5);
+        canvas.add_layer("Add");
+        canvas.render();
+    "#).unwrap();
+
+    assert_eq!(canvas.canvas.layers.len(), 2);
+    let img = canvas.read_rgba8().unwrap();
+    assert!(img.get_pixel(32, 24).0[0] > 200, "{:?}", img.get_pixel(32, 24));
+    assert_eq!(img.get_pixel(2, 2).0[0], 0, "{:?}", img.get_pixel(2, 2));
+}
+
+#[test]
+fn unreadable_images_leave_the_canvas_untouched(){
+    let mut canvas = match common::headless(SIZE){
+        Some(canvas) => canvas,
+        None => return,
+    };
+    let missing = Path::new(common::MANIFEST_DIR).join("assets").join("missing.png");
+    let commands = [
+        Command::AddLayer{blendop: "Add".to_string()},
+        Command::AddImage{path: missing, blendop: "Add".to_string()},
+    ];
+    assert!(script::apply(&mut canvas, &commands).is_err());
+    assert!(canvas.canvas.layers.is_empty());
+}
+
+#[test]
+fn runs_demo_script(){
+    let mut canvas = match common::headless([320, 240]){
+        Some(canvas) => canvas,
+        None => return,
+    };
+    let src = std::fs::read_to_string(Path::new(common::MANIFEST_DIR).join("assets/scripts/demo.rhai")).unwrap();
+    let mut commands = script::record(&mut canvas, &src).unwrap();
+
+    // the demo refers to paths relative to the repository and saves next to it.
+    let out = std::env::temp_dir().join("wgpu01_script").join("demo.png");
+    std::fs::create_dir_all(out.parent().unwrap()).unwrap();
+    for command in &mut commands{
+        match command{
+            Command::AddImage{path, ..} => *path = Path::new(common::MANIFEST_DIR).join(&*path),
+            Command::Save(path) => *path = out.clone(),
+            _ => (),
+        }
+    }
+    script::apply(&mut canvas, &commands).unwrap();
+
+    assert_eq!(canvas.canvas.layers.len(), 2);
+    assert_eq!(image::open(&out).unwrap().to_rgba8().dimensions(), (320, 240));
+}
@@ -0,0 +1,129 @@
+//!
+//! Reading, writing and replaying stroke logs.
+//!
+use std::collections::BTreeMap;
+use wgpu01::stroke_log::{RecordedStroke, Sample, StrokeLog, StrokeRecorder, VERSION};
+use wgpu01::{ColorSpace, Error, HeadlessCanvas, ReferenceCanvas};
+
+fn sample(time: f64, x: f32, y: f32, pressure: f32) -> Sample{
+    Sample{time, pos: [x, y], pressure, tilt: [0.1, -0.25]}
+}
+
+fn recorded() -> (StrokeLog, Vec<wgpu01::StrokeData>){
+    let mut recorder = StrokeRecorder::new();
+    let mut painted = Vec::new();
+
+    recorder.begin(0, "default", BTreeMap::new());
+    for i in 0..20{
+        let t = i as f32 / 19.0;
+        painted.extend(recorder.sample(sample(i as f64 / 60.0, 0.1 + 0.8 * t, 0.3 + 0.1 * t.sin(), 1.0 - 0.5 * t)));
+    }
+
+    recorder.begin(0, "default", BTreeMap::new());
+    painted.extend(recorder.sample(sample(1.0, 1.0 / 3.0, 0.7, 0.2)));
+    painted.extend(recorder.sample(sample(1.1, 2.0 / 3.0, 0.7, 0.9)));
+
+    (recorder.finish(), painted)
+}
+
+fn with_params(brush: &str, param: &str) -> StrokeLog{
+    let mut params = BTreeMap::new();
+    params.insert(param.to_string(), 0.3);
+    let mut stroke = RecordedStroke::new(0, brush, params);
+    stroke.samples.push(sample(0.0, 0.5, 0.5, 1.0));
+    stroke.samples.push(sample(0.1, 0.6, 0.5, 1.0));
+    StrokeLog{strokes: vec![stroke]}
+}
+
+#[test]
+fn round_trip_is_exact(){
+    let (mut log, _) = recorded();
+    log.strokes.extend(with_params("default", "size").strokes);
+
+    let mut bytes = Vec::new();
+    log.write(&mut bytes).unwrap();
+    let text = String::from_utf8(bytes.clone()).unwrap();
+    assert!(text.starts_with(&format!("wgpu01-strokes {}\n", VERSION)));
+
+    assert_eq!(StrokeLog::read(&bytes[..]).unwrap(), log);
+}
+
+#[test]
+fn replay_paints_what_was_recorded(){
+    let (log, painted) = recorded();
+    let replayed: Vec<_> = log.strokes.iter().flat_map(|stroke| stroke.segments()).collect();
+
+    assert_eq!(replayed.len(), painted.len());
+    for (a, b) in replayed.iter().zip(&painted){
+        assert_eq!(bytemuck::bytes_of(a), bytemuck::bytes_of(b));
+    }
+}
+
+#[test]
+fn rejects_invalid_logs(){
+    let cases = [
+        "",
+        "strokes 1\n",
+        "wgpu01-strokes 2\n",
+        "wgpu01-strokes 1\nsample 0 0 0 1 0 0\n",
+        "wgpu01-strokes 1\nstroke 0 default\nsample 0 0 0 1 0\nend\n",
+        "wgpu01-strokes 1\nstroke 0 default size\nend\n",
+        "wgpu01-strokes 1\nstroke 0 default\nsample 0 0 0 1 0 0\n",
+        "wgpu01-strokes 1\nend\n",
+    ];
+    for case in &cases{
+        assert!(matches!(StrokeLog::read(case.as_bytes()), Err(Error::Io(_))), "{:?} was accepted", case);
+    }
+
+    let unended = "wgpu01-strokes 1\nstroke 0 default\nend\n\nstroke 0 default\nsample 0 0 0 1 0 0\n";
+    let err = StrokeLog::read(unended.as_bytes()).unwrap_err().to_string();
+    assert!(err.contains("line 5"), "{}", err);
+}
+
+#[test]
+fn write_rejects_names_that_cant_be_read_back(){
+    for (brush, param) in [("my brush", "size"), ("", "size"), ("default", "a=b"), ("default", "tip size"), ("default", "")]{
+        let mut bytes = Vec::new();
+        let result = with_params(brush, param).write(&mut bytes);
+        assert!(matches!(result, Err(Error::InvalidArgument(_))), "{:?} {:?} was written", brush, param);
+        assert!(bytes.is_empty());
+    }
+}
+
+#[test]
+fn replay_rejects_params(){
+    let mut canvas = ReferenceCanvas::new([16, 16], ColorSpace::Linear);
+    canvas.push_empty("Add").unwrap();
+    let blank = canvas.clone().render().unwrap();
+
+    assert!(matches!(canvas.replay(&with_params("default", "size")), Err(Error::InvalidArgument(_))));
+    assert_eq!(canvas.render().unwrap().as_raw(), blank.as_raw(), "a rejected log was painted");
+}
+
+#[test]
+fn replay_is_deterministic(){
+    let (log, _) = recorded();
+
+    let mut images = Vec::new();
+    for _ in 0..2{
+        let mut canvas = match HeadlessCanvas::new([64, 48]){
+            Ok(canvas) => canvas,
+            Err(Error::NoAdapter) => {
+                println!("skipped, no adapter");
+                return;
+            },
+            Err(err) => panic!("{}", err),
+        };
+        canvas.push_empty("Add").unwrap();
+        canvas.render().unwrap();
+        let blank = canvas.read_rgba8().unwrap();
+
+        log.replay(&canvas.canvas, &canvas.brushops).unwrap();
+        canvas.render().unwrap();
+        let painted = canvas.read_rgba8().unwrap();
+        assert_ne!(painted.as_raw(), blank.as_raw(), "nothing was painted");
+        images.push(painted);
+    }
+
+    assert_eq!(images[0].as_raw(), images[1].as_raw());
+}